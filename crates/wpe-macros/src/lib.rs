@@ -0,0 +1,133 @@
+//! Procedural macros for the `wpe` crate.
+//!
+//! Use them through the `wpe` crate's re-exports, e.g. `wpe::embed_assets!`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Expand to a `&'static [(&'static str, &'static [u8])]` of every file in a
+/// directory, relative to `CARGO_MANIFEST_DIR`.
+///
+/// Paths in the list are relative to the directory and use `/` separators.
+/// Files are included with `include_bytes!`, so editing them triggers a
+/// rebuild; adding or removing files does not until the crate is rebuilt.
+#[proc_macro]
+pub fn embed_dir(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(output) => output,
+        Err(message) => format!("compile_error!({message:?})")
+            .parse()
+            .unwrap_or_default(),
+    }
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, String> {
+    let dir = parse_path(input)?;
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| "CARGO_MANIFEST_DIR is not set".to_string())?;
+    let root = Path::new(&manifest_dir).join(&dir);
+
+    let mut files = Vec::new();
+    walk(&root, &root, &mut files)
+        .map_err(|e| format!("Failed to read assets in {}: {e}", root.display()))?;
+    files.sort();
+
+    let entries = files
+        .iter()
+        .map(|(name, path)| {
+            let path = path
+                .to_str()
+                .ok_or_else(|| format!("Asset path is not UTF-8: {}", path.display()))?;
+            Ok(format!(
+                "({name:?}, include_bytes!({path:?}) as &'static [u8])"
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    format!("&[{}]", entries.join(","))
+        .parse()
+        .map_err(|e| format!("Failed to embed assets: {e}"))
+}
+
+/// Parse the single string literal argument.
+fn parse_path(input: TokenStream) -> Result<String, String> {
+    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
+
+    // `$dir:literal` fragments from macro_rules! arrive wrapped in a None group
+    while let [TokenTree::Group(group)] = tokens.as_slice() {
+        if group.delimiter() != Delimiter::None {
+            break;
+        }
+        tokens = group.stream().into_iter().collect();
+    }
+
+    match tokens.as_slice() {
+        [TokenTree::Literal(literal)] => unquote(&literal.to_string())
+            .ok_or_else(|| "Expected a string literal without escapes".to_string()),
+        _ => Err("Expected a directory path as a string literal".to_string()),
+    }
+}
+
+/// The contents of a plain or raw string literal.
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let body = raw[hashes..].strip_prefix('"')?;
+        return body
+            .strip_suffix(&raw[..hashes])?
+            .strip_suffix('"')
+            .map(str::to_string);
+    }
+
+    let body = literal.strip_prefix('"')?.strip_suffix('"')?;
+    (!body.contains('\\')).then(|| body.to_string())
+}
+
+/// Collect `(relative name, absolute path)` for every file under `dir`.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(root, &path, files)?;
+        } else if path.is_file() {
+            let name = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let path = fs::canonicalize(&path)?;
+            files.push((name, path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""dist""#).as_deref(), Some("dist"));
+        assert_eq!(unquote(r##"r#"web/dist"#"##).as_deref(), Some("web/dist"));
+        assert_eq!(unquote(r#"r"dist""#).as_deref(), Some("dist"));
+        assert_eq!(unquote(r#""a\nb""#), None);
+        assert_eq!(unquote("42"), None);
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut files = Vec::new();
+        walk(root, root, &mut files).unwrap();
+
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert!(names.contains(&"Cargo.toml"));
+        assert!(names.contains(&"src/lib.rs"));
+        assert!(files.iter().all(|(_, path)| path.is_absolute()));
+    }
+}
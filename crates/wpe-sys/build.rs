@@ -14,6 +14,11 @@ fn main() {
         .probe("gobject-2.0")
         .expect("gobject-2.0 not found. Install glib2 package.");
 
+    let gio_unix = pkg_config::Config::new()
+        .atleast_version("2.0")
+        .probe("gio-unix-2.0")
+        .expect("gio-unix-2.0 not found. Install glib2 package.");
+
     let wpe_webkit = pkg_config::Config::new()
         .atleast_version("2.0")
         .probe("wpe-webkit-2.0")
@@ -50,12 +55,28 @@ fn main() {
         .allowlist_function("g_object_unref")
         .allowlist_function("g_object_new")
         .allowlist_function("g_signal_connect_data")
+        .allowlist_function("g_signal_handler_disconnect")
         .allowlist_function("g_signal_emit_by_name")
         .allowlist_function("g_main_context_.*")
         .allowlist_function("g_main_loop_.*")
+        .allowlist_function("g_timeout_add_full")
+        .allowlist_function("g_source_remove")
         .allowlist_function("g_bytes_.*")
         .allowlist_function("g_error_free")
         .allowlist_function("g_free")
+        .allowlist_function("g_strfreev")
+        // GIO streams (custom URI scheme bodies)
+        .allowlist_function("g_memory_input_stream_new_from_bytes")
+        .allowlist_function("g_unix_input_stream_new")
+        .allowlist_function("g_memory_output_stream_new_resizable")
+        .allowlist_function("g_memory_output_stream_get_data")
+        .allowlist_function("g_memory_output_stream_get_data_size")
+        .allowlist_function("g_output_stream_splice_async")
+        .allowlist_function("g_output_stream_splice_finish")
+        // GIO app info (opening URLs with the default handler)
+        .allowlist_function("g_app_info_launch_default_for_uri")
+        // libsoup headers (custom URI scheme requests/responses)
+        .allowlist_function("soup_message_headers_.*")
         // Types
         .allowlist_type("WPE.*")
         .allowlist_type("WebKit.*")
@@ -76,6 +97,7 @@ fn main() {
         .include_paths
         .iter()
         .chain(gobject.include_paths.iter())
+        .chain(gio_unix.include_paths.iter())
         .chain(wpe_webkit.include_paths.iter())
         .chain(wpe_platform.include_paths.iter())
         .chain(wpe_platform_headless.include_paths.iter());
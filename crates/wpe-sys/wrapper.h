@@ -6,6 +6,9 @@
 /* GLib/GObject - needed for memory management */
 #include <glib-object.h>
 
+/* GIO Unix streams - used to stream custom URI scheme responses */
+#include <gio/gunixinputstream.h>
+
 /* WPE Platform API (modern, clean API) */
 #include <wpe/wpe-platform.h>
 
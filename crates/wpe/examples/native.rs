@@ -63,35 +63,7 @@ const HTML_CONTENT: &str = r#"
     </div>
 
     <script>
-    // IPC Bridge (injected by Rust when using load_html_with_ipc)
-    (function() {
-        'use strict';
-        const hasWebKitHandler = typeof webkit !== 'undefined' &&
-                                 webkit.messageHandlers &&
-                                 webkit.messageHandlers.wpe;
-
-        window.__wpe_receive = function(msg) {
-            window.dispatchEvent(new CustomEvent('wpe:message', { detail: msg }));
-        };
-
-        window.__wpe_send = function(msg) {
-            if (hasWebKitHandler) {
-                webkit.messageHandlers.wpe.postMessage(JSON.stringify(msg));
-            } else {
-                console.log('No WebKit handler, would send:', msg);
-            }
-        };
-
-        window.wpe = {
-            send(type, payload) { window.__wpe_send({ type, payload }); },
-            onMessage(callback) {
-                window.addEventListener('wpe:message', (e) => callback(e.detail));
-            }
-        };
-
-        console.log('IPC Bridge initialized. WebKit handler:', hasWebKitHandler);
-        window.dispatchEvent(new CustomEvent('wpe:ready'));
-    })();
+    // window.wpe is installed by Rust at document start
 
     // UI functions
     function log(msg) {
@@ -129,7 +101,6 @@ fn main() -> Result<()> {
 
     let mut window = NativeWindow::new(settings)?;
     window.set_title("WPE Native IPC Example");
-    window.load_html(HTML_CONTENT, None)?;
 
     println!("Running native WPE window with IPC...");
     println!("Click buttons in the web page to test JavaScript ↔ Rust communication.");
@@ -165,6 +136,12 @@ fn main() -> Result<()> {
                         println!("Loading: {:.0}%", progress * 100.0);
                     }
                 }
+                NavigationEvent::ReadyToShow(features) => {
+                    println!("Ready to show: {:?}", features);
+                }
+                NavigationEvent::CloseRequested => {
+                    println!("Page requested close");
+                }
             }
         }
 
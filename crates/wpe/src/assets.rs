@@ -0,0 +1,544 @@
+//! Static asset serving over the `app://` scheme.
+//!
+//! [`StaticAssets`] serves files from a directory or from data embedded in
+//! the binary. Attach it with [`crate::WebViewSettings::with_assets`] and the
+//! web view loads [`ASSETS_URL`] on startup:
+//!
+//! ```rust,ignore
+//! let settings = WebViewSettings::new().with_assets(wpe::embed_assets!("dist"));
+//! ```
+//!
+//! Assets can also be registered on any other scheme, since [`StaticAssets`]
+//! implements [`SchemeHandler`].
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::scheme::{self, SchemeHandler, SchemeRequest, SchemeResponse};
+use crate::{Error, Result};
+
+/// Scheme used for assets attached through `WebViewSettings::with_assets`.
+pub const ASSETS_SCHEME: &str = "app";
+
+/// URL loaded by web views created with `WebViewSettings::with_assets`.
+pub const ASSETS_URL: &str = "app://localhost/";
+
+/// Assets attached to web views, keyed by web view address.
+static MOUNTS: Mutex<Vec<(usize, StaticAssets)>> = Mutex::new(Vec::new());
+
+/// Outcome of registering [`ASSETS_SCHEME`], shared by every later view.
+static ASSETS_SCHEME_INIT: OnceLock<std::result::Result<(), String>> = OnceLock::new();
+
+#[doc(hidden)]
+pub use wpe_macros::embed_dir;
+
+/// Embed files into the binary as [`StaticAssets`].
+///
+/// The directory is relative to `CARGO_MANIFEST_DIR`. With only a directory,
+/// every file under it is embedded, found when the crate is compiled; files
+/// added to the directory later are picked up by the next rebuild of the
+/// crate, not by an incremental build. Listing the files limits the embedded
+/// set to them.
+///
+/// ```rust,ignore
+/// let assets = wpe::embed_assets!("dist");
+/// let assets = wpe::embed_assets!("dist", ["index.html", "js/app.js"]);
+/// ```
+#[macro_export]
+macro_rules! embed_assets {
+    ($dir:literal) => {
+        $crate::assets::StaticAssets::from_embedded($crate::assets::embed_dir!($dir))
+    };
+    ($dir:literal, [$($file:literal),* $(,)?]) => {
+        $crate::assets::StaticAssets::from_embedded(&[
+            $((
+                $file,
+                include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $dir, "/", $file))
+                    as &'static [u8],
+            )),*
+        ])
+    };
+}
+
+/// Where asset data is read from.
+#[derive(Debug, Clone)]
+enum AssetSource {
+    Directory(PathBuf),
+    Embedded(&'static [(&'static str, &'static [u8])]),
+}
+
+/// A set of static files served to a web view.
+///
+/// Directory indexes resolve to `index.html`, unknown extensionless paths
+/// fall back to the root `index.html` (for client-side routing), and
+/// `Range` requests are answered with partial content.
+#[derive(Debug, Clone)]
+pub struct StaticAssets {
+    source: AssetSource,
+    spa_fallback: bool,
+}
+
+impl StaticAssets {
+    /// Serve files from a directory on disk.
+    #[must_use]
+    pub fn from_directory(path: impl Into<PathBuf>) -> Self {
+        Self {
+            source: AssetSource::Directory(path.into()),
+            spa_fallback: true,
+        }
+    }
+
+    /// Serve files embedded in the binary.
+    ///
+    /// Each entry is a path relative to the site root and the file contents.
+    /// See [`embed_assets!`](crate::embed_assets) for building the list.
+    #[must_use]
+    pub fn from_embedded(files: &'static [(&'static str, &'static [u8])]) -> Self {
+        Self {
+            source: AssetSource::Embedded(files),
+            spa_fallback: true,
+        }
+    }
+
+    /// Enable or disable serving `index.html` for unknown routes (default: enabled).
+    #[must_use]
+    pub fn with_spa_fallback(mut self, enabled: bool) -> Self {
+        self.spa_fallback = enabled;
+        self
+    }
+
+    /// Find the asset for a request path.
+    ///
+    /// Returns the resolved relative path and its data.
+    fn resolve(&self, request_path: &str) -> Option<(String, Asset)> {
+        let path = normalize_path(request_path)?;
+
+        if path.is_empty() || request_path.ends_with('/') {
+            let index = join(&path, "index.html");
+            return self.open(&index).map(|asset| (index, asset));
+        }
+
+        if let Some(asset) = self.open(&path) {
+            return Some((path, asset));
+        }
+
+        let index = join(&path, "index.html");
+        if let Some(asset) = self.open(&index) {
+            return Some((index, asset));
+        }
+
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        if self.spa_fallback && !file_name.contains('.') {
+            return self
+                .open("index.html")
+                .map(|asset| ("index.html".to_string(), asset));
+        }
+
+        None
+    }
+
+    /// Open a normalized relative path.
+    fn open(&self, path: &str) -> Option<Asset> {
+        match self.source {
+            AssetSource::Directory(ref root) => {
+                let full = root.join(path);
+                let file = File::open(&full).ok()?;
+                let metadata = file.metadata().ok()?;
+                metadata
+                    .is_file()
+                    .then(|| Asset::File(file, metadata.len()))
+            }
+            AssetSource::Embedded(files) => files
+                .iter()
+                .find(|(name, _)| name.trim_start_matches('/') == path)
+                .map(|(_, data)| Asset::Bytes(data)),
+        }
+    }
+}
+
+impl SchemeHandler for StaticAssets {
+    fn handle(&self, request: &SchemeRequest) -> SchemeResponse {
+        if request.method() != "GET" && request.method() != "HEAD" {
+            return SchemeResponse::empty(405).with_header("Allow", "GET, HEAD");
+        }
+
+        let Some((path, asset)) = self.resolve(request.path()) else {
+            tracing::debug!("Asset not found: {}", request.path());
+            return SchemeResponse::not_found();
+        };
+
+        let content_type = mime_type(Path::new(&path));
+        let length = asset.len();
+
+        if request.method() == "HEAD" {
+            return SchemeResponse::new(Vec::new(), content_type)
+                .with_header("Accept-Ranges", "bytes")
+                .with_header("Content-Length", length.to_string());
+        }
+
+        let range = match request.header("Range").map(|r| parse_range(r, length)) {
+            None => None,
+            Some(Some(range)) => Some(range),
+            Some(None) => {
+                return SchemeResponse::empty(416)
+                    .with_header("Content-Range", format!("bytes */{length}"));
+            }
+        };
+
+        let (start, end) = range.unwrap_or((0, length.saturating_sub(1)));
+        let response = match asset.slice(start, end, length) {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Failed to read asset {}: {}", path, e);
+                return SchemeResponse::empty(500);
+            }
+        };
+
+        let response = response
+            .with_content_type(content_type)
+            .with_header("Accept-Ranges", "bytes");
+
+        if range.is_some() {
+            response
+                .with_status(206)
+                .with_header("Content-Range", format!("bytes {start}-{end}/{length}"))
+        } else {
+            response
+        }
+    }
+}
+
+/// Asset data opened for a request.
+enum Asset {
+    Bytes(&'static [u8]),
+    File(File, u64),
+}
+
+impl Asset {
+    fn len(&self) -> u64 {
+        match self {
+            Self::Bytes(data) => data.len() as u64,
+            Self::File(_, len) => *len,
+        }
+    }
+
+    /// Build a response for the inclusive byte range `start..=end`.
+    fn slice(self, start: u64, end: u64, length: u64) -> std::io::Result<SchemeResponse> {
+        if length == 0 {
+            return Ok(SchemeResponse::new(Vec::new(), ""));
+        }
+
+        let count = end - start + 1;
+        match self {
+            Self::Bytes(data) => {
+                let bytes = &data[start as usize..=end as usize];
+                Ok(SchemeResponse::new(bytes.to_vec(), ""))
+            }
+            Self::File(mut file, _) => {
+                file.seek(SeekFrom::Start(start))?;
+                Ok(SchemeResponse::stream(file.take(count), "").with_length(count))
+            }
+        }
+    }
+}
+
+/// Register [`ASSETS_SCHEME`] on first use.
+///
+/// Views with assets call this before wiring anything up, so a failure
+/// fails their creation.
+///
+/// # Errors
+/// Returns an error if the scheme could not be registered.
+pub(crate) fn register_scheme() -> Result<()> {
+    ASSETS_SCHEME_INIT
+        .get_or_init(|| {
+            scheme::register(ASSETS_SCHEME, Box::new(on_assets_request)).map_err(|e| match e {
+                Error::UriSchemeError(message) => message,
+                other => other.to_string(),
+            })
+        })
+        .clone()
+        .map_err(Error::UriSchemeError)
+}
+
+/// Serve `assets` to requests for [`ASSETS_SCHEME`] made by `web_view`.
+///
+/// The scheme must have been registered with [`register_scheme`]. Call
+/// [`unmount`] before the web view is released.
+pub(crate) fn mount(web_view: *mut wpe_sys::WebKitWebView, assets: &StaticAssets) {
+    if let Ok(mut mounts) = MOUNTS.lock() {
+        mounts.push((web_view as usize, assets.clone()));
+    }
+}
+
+/// Serve the assets mounted on `related` (if any) to `web_view` as well.
+///
+/// Popups created from an app page keep loading its `app://` files.
+pub(crate) fn mount_related(
+    web_view: *mut wpe_sys::WebKitWebView,
+    related: *mut wpe_sys::WebKitWebView,
+) {
+    if let Some(assets) = mounted(related as usize) {
+        mount(web_view, &assets);
+    }
+}
+
+/// Stop serving assets to `web_view`.
+pub(crate) fn unmount(web_view: *mut wpe_sys::WebKitWebView) {
+    if let Ok(mut mounts) = MOUNTS.lock() {
+        mounts.retain(|(address, _)| *address != web_view as usize);
+    }
+}
+
+/// The assets mounted on the web view at `address`.
+fn mounted(address: usize) -> Option<StaticAssets> {
+    let mounts = MOUNTS.lock().ok()?;
+    mounts
+        .iter()
+        .find(|(a, _)| *a == address)
+        .map(|(_, assets)| assets.clone())
+}
+
+/// Scheme handler dispatching to the assets mounted on the requesting web view.
+///
+/// Views without mounted assets get a 404.
+fn on_assets_request(request: &SchemeRequest) -> SchemeResponse {
+    match mounted(request.web_view() as usize) {
+        Some(assets) => assets.handle(request),
+        None => SchemeResponse::not_found(),
+    }
+}
+
+/// Percent-decode a request path and strip it to a safe relative path.
+///
+/// Returns `None` if the path tries to escape the root.
+fn normalize_path(path: &str) -> Option<String> {
+    let decoded = percent_decode(path.split(['?', '#']).next().unwrap_or_default())?;
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s => segments.push(s),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+/// Decode `%XX` escapes in a URI path.
+pub(crate) fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+fn join(dir: &str, file: &str) -> String {
+    if dir.is_empty() {
+        file.to_string()
+    } else {
+        format!("{dir}/{file}")
+    }
+}
+
+/// Parse a single-range `Range` header into an inclusive byte range.
+///
+/// Returns `None` if the range cannot be satisfied for a body of `length` bytes.
+fn parse_range(header: &str, length: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || length == 0 {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (length.saturating_sub(suffix), length - 1)
+        }
+        (start, "") => (start.parse().ok()?, length - 1),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.min(length - 1),
+        ),
+    };
+
+    (start <= end && start < length).then_some((start, end))
+}
+
+/// Infer a MIME type from a file extension.
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js" | "mjs") => "text/javascript",
+        Some("json" | "map") => "application/json",
+        Some("wasm") => "application/wasm",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("ogg") => "audio/ogg",
+        Some("mp3") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("txt") => "text/plain",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FILES: &[(&str, &[u8])] = &[
+        ("index.html", b"<h1>root</h1>"),
+        ("app.js", b"console.log(1)"),
+        ("docs/index.html", b"<h1>docs</h1>"),
+        ("video.mp4", b"0123456789"),
+    ];
+
+    fn resolve(assets: &StaticAssets, path: &str) -> Option<String> {
+        assets.resolve(path).map(|(path, _)| path)
+    }
+
+    #[test]
+    fn test_resolve_index() {
+        let assets = StaticAssets::from_embedded(FILES);
+        assert_eq!(resolve(&assets, "/"), Some("index.html".to_string()));
+        assert_eq!(
+            resolve(&assets, "/docs/"),
+            Some("docs/index.html".to_string())
+        );
+        assert_eq!(
+            resolve(&assets, "/docs"),
+            Some("docs/index.html".to_string())
+        );
+        assert_eq!(resolve(&assets, "/app.js"), Some("app.js".to_string()));
+    }
+
+    #[test]
+    fn test_spa_fallback() {
+        let assets = StaticAssets::from_embedded(FILES);
+        assert_eq!(
+            resolve(&assets, "/settings/profile"),
+            Some("index.html".to_string())
+        );
+        assert_eq!(resolve(&assets, "/missing.js"), None);
+
+        let assets = assets.with_spa_fallback(false);
+        assert_eq!(resolve(&assets, "/settings/profile"), None);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("/a/./b//c"), Some("a/b/c".to_string()));
+        assert_eq!(
+            normalize_path("/my%20file.txt"),
+            Some("my file.txt".to_string())
+        );
+        assert_eq!(normalize_path("/app.js?v=2"), Some("app.js".to_string()));
+        assert_eq!(normalize_path("/../etc/passwd"), None);
+        assert_eq!(normalize_path("/%2e%2e/secret"), None);
+        assert_eq!(normalize_path("/bad%zz"), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 10), Some((0, 4)));
+        assert_eq!(parse_range("bytes=5-", 10), Some((5, 9)));
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 9)));
+        assert_eq!(parse_range("bytes=8-100", 10), Some((8, 9)));
+        assert_eq!(parse_range("bytes=10-12", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+    }
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type(Path::new("index.HTML")), "text/html");
+        assert_eq!(mime_type(Path::new("app.mjs")), "text/javascript");
+        assert_eq!(mime_type(Path::new("module.wasm")), "application/wasm");
+        assert_eq!(mime_type(Path::new("LICENSE")), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_embed_assets_macro() {
+        let assets = crate::embed_assets!("src", ["lib.rs"]);
+        assert_eq!(resolve(&assets, "/lib.rs"), Some("lib.rs".to_string()));
+    }
+
+    #[test]
+    fn test_embed_assets_directory() {
+        let assets = crate::embed_assets!("src");
+        assert_eq!(resolve(&assets, "/lib.rs"), Some("lib.rs".to_string()));
+        assert_eq!(
+            resolve(&assets, "/shaders/fullscreen_quad.wgsl"),
+            Some("shaders/fullscreen_quad.wgsl".to_string())
+        );
+    }
+
+    #[test]
+    fn test_range_slice() {
+        let asset = Asset::Bytes(b"0123456789");
+        let response = asset.slice(2, 5, 10).unwrap();
+        assert!(matches!(
+            response.body(),
+            crate::scheme::ResponseBody::Bytes(b) if b == b"2345"
+        ));
+    }
+
+    #[test]
+    fn test_unmounted_view_has_no_assets() {
+        // MOUNTS is shared by tests running in parallel; key the entries by
+        // addresses only this test uses
+        let views = [0u8; 2];
+        let mounted_view = &views[0] as *const u8 as *mut wpe_sys::WebKitWebView;
+        let other_view = &views[1] as *const u8 as usize;
+        MOUNTS
+            .lock()
+            .unwrap()
+            .push((mounted_view as usize, StaticAssets::from_embedded(FILES)));
+
+        assert!(mounted(mounted_view as usize).is_some());
+        assert!(mounted(other_view).is_none());
+
+        unmount(mounted_view);
+        assert!(mounted(mounted_view as usize).is_none());
+    }
+}
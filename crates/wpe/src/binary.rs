@@ -0,0 +1,442 @@
+//! Binary IPC without base64 or script evaluation.
+//!
+//! JSON messages travel through `webkit.messageHandlers` and evaluated
+//! scripts, which means binary data has to be base64-encoded. This channel
+//! moves raw bytes through the `wpe://bytes/` scheme instead: JavaScript
+//! uploads with `fetch` and downloads `ArrayBuffer`s or `ReadableStream`s.
+//!
+//! ```javascript
+//! // JavaScript -> Rust
+//! await wpe.sendBytes('image', canvasBytes);
+//! await wpe.sendStream('upload', file); // Blob or ReadableStream, sent in chunks
+//!
+//! // Rust -> JavaScript
+//! wpe.onBytes((type, buffer) => console.log(type, buffer.byteLength));
+//! wpe.onStream((type, stream, length) => consume(stream.getReader()));
+//! ```
+//!
+//! ```rust,ignore
+//! webview.send_bytes("thumbnail", png)?;
+//! webview.send_stream("video", File::open(path)?, Some(size))?;
+//!
+//! for message in webview.receive_bytes() {
+//!     println!("{}: {} bytes from {:?}", message.message_type, message.data.len(), message.origin);
+//! }
+//! ```
+//!
+//! Outgoing data is held until the page fetches it, under an unguessable ID.
+//! At most [`MAX_PENDING_TRANSFERS`] transfers are held, the oldest being
+//! dropped first, and all of them are dropped when the page navigates.
+//!
+//! With an isolated script world (`WebViewSettings::with_script_world`),
+//! page scripts can post to `wpe://bytes/` just like the bridge, so uploads
+//! are refused and `wpe.sendBytes` rejects. Downloads still work, since only
+//! the bridge learns their IDs.
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use crate::assets;
+use crate::ipc::{self, IPC_SCHEME};
+use crate::scheme::{SchemeRequest, SchemeResponse};
+
+/// Host of the binary channel's URIs (`wpe://bytes/...`).
+const BYTES_HOST: &str = "bytes";
+
+/// Most outgoing transfers held for the page at once.
+pub const MAX_PENDING_TRANSFERS: usize = 64;
+
+/// A binary message from the frontend, sent with `wpe.sendBytes` or `wpe.sendStream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryMessage {
+    /// The type given by JavaScript.
+    pub message_type: String,
+    /// The raw bytes.
+    pub data: Vec<u8>,
+    /// Security origin of the sending frame, from the request's `Origin` header.
+    pub origin: Option<String>,
+    /// Stream ID shared by the chunks of one `wpe.sendStream` call.
+    pub stream: Option<String>,
+    /// Whether this is the (empty) final chunk of a stream.
+    pub end: bool,
+}
+
+/// Incoming messages and outgoing data for one web view.
+#[derive(Default)]
+pub(crate) struct BinaryChannel {
+    inbox: Mutex<VecDeque<BinaryMessage>>,
+    /// Outgoing data by transfer ID, oldest first
+    outbox: Mutex<VecDeque<(String, SchemeResponse)>>,
+    /// Whether uploads from the page are refused (the bridge runs in an isolated world)
+    refuse_uploads: bool,
+    /// Global the bridge is installed as
+    global: String,
+}
+
+impl BinaryChannel {
+    /// A channel for a web view whose bridge runs in `world` as `window[global]`.
+    pub(crate) fn new(world: Option<&str>, global: &str) -> Self {
+        Self {
+            refuse_uploads: world.is_some(),
+            global: global.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Take the messages received since the last call.
+    pub(crate) fn receive(&self) -> Vec<BinaryMessage> {
+        match self.inbox.lock() {
+            Ok(mut inbox) => inbox.drain(..).collect(),
+            Err(e) => {
+                tracing::warn!("Failed to lock binary inbox: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Hold `data` for the page and return the script announcing it.
+    pub(crate) fn offer_bytes(&self, message_type: &str, data: Vec<u8>) -> String {
+        let length = data.len() as u64;
+        let response = SchemeResponse::new(data, "application/octet-stream");
+        self.offer(message_type, response, false, Some(length))
+    }
+
+    /// Hold a streamed body for the page and return the script announcing it.
+    pub(crate) fn offer_stream(
+        &self,
+        message_type: &str,
+        reader: impl Read + Send + 'static,
+        length: Option<u64>,
+    ) -> String {
+        let mut response = SchemeResponse::stream(reader, "application/octet-stream");
+        if let Some(length) = length {
+            response = response.with_length(length);
+        }
+        self.offer(message_type, response, true, length)
+    }
+
+    fn offer(
+        &self,
+        message_type: &str,
+        response: SchemeResponse,
+        stream: bool,
+        length: Option<u64>,
+    ) -> String {
+        let id = transfer_id();
+        let info = serde_json::json!({
+            "type": message_type,
+            "id": id,
+            "stream": stream,
+            "length": length,
+        });
+        let script = ipc::hook_script(&self.global, "__receiveBytes", &info.to_string());
+
+        if let Ok(mut outbox) = self.outbox.lock() {
+            if outbox.len() >= MAX_PENDING_TRANSFERS {
+                if let Some((dropped, _)) = outbox.pop_front() {
+                    tracing::warn!("Dropping binary transfer {} the page never fetched", dropped);
+                }
+            }
+            outbox.push_back((id, response));
+        }
+        script
+    }
+
+    fn take(&self, id: &str) -> Option<SchemeResponse> {
+        let mut outbox = self.outbox.lock().ok()?;
+        let index = outbox.iter().position(|(pending, _)| pending == id)?;
+        outbox.remove(index).map(|(_, response)| response)
+    }
+
+    /// Drop data offered to a page that has been navigated away from.
+    fn clear_outbox(&self) {
+        if let Ok(mut outbox) = self.outbox.lock() {
+            if !outbox.is_empty() {
+                tracing::debug!("Dropping {} unfetched binary transfers", outbox.len());
+                outbox.clear();
+            }
+        }
+    }
+
+    fn push(&self, message: BinaryMessage) {
+        if let Ok(mut inbox) = self.inbox.lock() {
+            inbox.push_back(message);
+        }
+    }
+}
+
+/// Binary channels, keyed by web view address.
+static BINARY_ROUTES: Mutex<Vec<(usize, Arc<BinaryChannel>)>> = Mutex::new(Vec::new());
+
+/// Serve `wpe://bytes/` requests from `web_view` with `channel`.
+///
+/// The channel's outgoing data is dropped whenever `web_view` commits a
+/// navigation. Call [`unroute`] before the web view is released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn route(web_view: *mut wpe_sys::WebKitWebView, channel: &Arc<BinaryChannel>) {
+    ipc::register_scheme();
+    if let Ok(mut routes) = BINARY_ROUTES.lock() {
+        routes.push((web_view as usize, Arc::clone(channel)));
+    }
+
+    // The web view releases its reference through on_channel_released
+    let signal_name = CString::new("load-changed").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                wpe_sys::WebKitLoadEvent,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_load_changed)),
+        Arc::into_raw(Arc::clone(channel)) as *mut _,
+        Some(on_channel_released),
+        0,
+    );
+}
+
+/// Signal handler for load-changed that clears the outbox on commit.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_load_changed(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    load_event: wpe_sys::WebKitLoadEvent,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() || load_event != wpe_sys::WebKitLoadEvent_WEBKIT_LOAD_COMMITTED {
+        return;
+    }
+
+    let channel = &*(user_data as *const BinaryChannel);
+    channel.clear_outbox();
+}
+
+/// Release the channel reference held by the load-changed handler.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_channel_released(
+    user_data: *mut std::ffi::c_void,
+    _closure: *mut wpe_sys::GClosure,
+) {
+    if !user_data.is_null() {
+        drop(Arc::from_raw(user_data as *const BinaryChannel));
+    }
+}
+
+/// Stop serving binary requests for `web_view`, dropping undelivered data.
+pub(crate) fn unroute(web_view: *mut wpe_sys::WebKitWebView) {
+    if let Ok(mut routes) = BINARY_ROUTES.lock() {
+        routes.retain(|(address, _)| *address != web_view as usize);
+    }
+}
+
+/// Whether `uri` belongs to the binary channel.
+pub(crate) fn is_binary_uri(uri: &str) -> bool {
+    target(uri).is_some()
+}
+
+/// The part of a `wpe://bytes/...` URI after the host.
+fn target(uri: &str) -> Option<&str> {
+    uri.strip_prefix(IPC_SCHEME)?
+        .strip_prefix("://")?
+        .strip_prefix(BYTES_HOST)?
+        .strip_prefix('/')
+}
+
+/// A parsed binary channel request.
+#[derive(Debug, PartialEq, Eq)]
+enum BinaryRequest {
+    /// Upload: `POST wpe://bytes/in/<type>[?stream=<id>[&end=1]]`
+    Upload {
+        message_type: String,
+        stream: Option<String>,
+        end: bool,
+    },
+    /// Download: `GET wpe://bytes/out/<id>`
+    Download(String),
+}
+
+/// Parse a binary channel URI, or return the HTTP status to reject it with.
+fn parse_request(uri: &str, method: &str) -> std::result::Result<BinaryRequest, u16> {
+    let target = target(uri).ok_or(404_u16)?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    if let Some(message_type) = path.strip_prefix("in/") {
+        if method != "POST" {
+            return Err(405);
+        }
+        let message_type = assets::percent_decode(message_type).ok_or(400_u16)?;
+        let mut stream = None;
+        let mut end = false;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "stream" => stream = assets::percent_decode(value),
+                "end" => end = value == "1",
+                _ => {}
+            }
+        }
+        return Ok(BinaryRequest::Upload {
+            message_type,
+            stream,
+            end,
+        });
+    }
+
+    if let Some(id) = path.strip_prefix("out/") {
+        if method != "GET" {
+            return Err(405);
+        }
+        return Ok(BinaryRequest::Download(id.to_string()));
+    }
+
+    Err(404)
+}
+
+/// Scheme handler for `wpe://bytes/`.
+pub(crate) fn on_binary_request(request: &SchemeRequest) -> SchemeResponse {
+    if request.method() == "OPTIONS" {
+        return SchemeResponse::empty(204)
+            .with_header("Access-Control-Allow-Methods", "GET, POST")
+            .with_header("Access-Control-Allow-Headers", "Content-Type");
+    }
+
+    let address = request.web_view() as usize;
+    let channel = BINARY_ROUTES.lock().ok().and_then(|routes| {
+        routes
+            .iter()
+            .find(|(a, _)| *a == address)
+            .map(|(_, c)| Arc::clone(c))
+    });
+    let Some(channel) = channel else {
+        tracing::warn!("Binary IPC request from unknown web view");
+        return SchemeResponse::not_found();
+    };
+
+    match parse_request(request.uri(), request.method()) {
+        Ok(BinaryRequest::Upload { .. }) if channel.refuse_uploads => {
+            tracing::warn!("Refusing binary upload: the bridge runs in an isolated world");
+            SchemeResponse::empty(403)
+        }
+        Ok(BinaryRequest::Upload {
+            message_type,
+            stream,
+            end,
+        }) => {
+            channel.push(BinaryMessage {
+                message_type,
+                data: request.body().to_vec(),
+                origin: request.header("Origin").map(str::to_string),
+                stream,
+                end,
+            });
+            SchemeResponse::empty(204)
+        }
+        Ok(BinaryRequest::Download(id)) => channel.take(&id).unwrap_or_else(|| {
+            tracing::warn!("Binary IPC download for unknown ID");
+            SchemeResponse::not_found()
+        }),
+        Err(status) => SchemeResponse::empty(status),
+    }
+}
+
+/// A random ID for an outgoing transfer.
+fn transfer_id() -> String {
+    let state = RandomState::new();
+    let high = state.build_hasher().finish();
+    let mut hasher = state.build_hasher();
+    hasher.write_u64(high);
+    format!("{high:016x}{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request("wpe://bytes/in/file%20chunk?stream=abc&end=1", "POST"),
+            Ok(BinaryRequest::Upload {
+                message_type: "file chunk".to_string(),
+                stream: Some("abc".to_string()),
+                end: true,
+            })
+        );
+        assert_eq!(
+            parse_request("wpe://bytes/in/image", "POST"),
+            Ok(BinaryRequest::Upload {
+                message_type: "image".to_string(),
+                stream: None,
+                end: false,
+            })
+        );
+        assert_eq!(
+            parse_request("wpe://bytes/out/0123", "GET"),
+            Ok(BinaryRequest::Download("0123".to_string()))
+        );
+        assert_eq!(parse_request("wpe://bytes/out/0123", "POST"), Err(405));
+        assert_eq!(parse_request("wpe://bytes/other", "GET"), Err(404));
+        assert!(!is_binary_uri("wpe://message"));
+    }
+
+    #[test]
+    fn test_offer_and_take() {
+        let channel = BinaryChannel::new(None, "wpe");
+        let script = channel.offer_bytes("thumb", vec![1, 2, 3]);
+
+        let json = script
+            .strip_prefix(r#"window["wpe"].__receiveBytes("#)
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap();
+        let info: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(info["type"], "thumb");
+        assert_eq!(info["stream"], false);
+        assert_eq!(info["length"], 3);
+
+        let id = info["id"].as_str().unwrap();
+        assert_eq!(id.len(), 32);
+        assert!(channel.take(id).is_some());
+        assert!(channel.take(id).is_none());
+        assert_ne!(transfer_id(), transfer_id());
+    }
+
+    /// The ID announced by an offer script.
+    fn offered_id(script: &str) -> String {
+        let json = script
+            .strip_prefix(r#"window["wpe"].__receiveBytes("#)
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap();
+        let info: serde_json::Value = serde_json::from_str(json).unwrap();
+        info["id"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_outbox_is_bounded() {
+        let channel = BinaryChannel::new(None, "wpe");
+        let first = offered_id(&channel.offer_bytes("frame", vec![0]));
+        let ids: Vec<String> = (0..MAX_PENDING_TRANSFERS)
+            .map(|_| offered_id(&channel.offer_bytes("frame", vec![1])))
+            .collect();
+
+        assert!(channel.take(&first).is_none());
+        assert!(ids.iter().all(|id| channel.take(id).is_some()));
+    }
+
+    #[test]
+    fn test_clear_outbox() {
+        let channel = BinaryChannel::new(Some("app"), "wpe");
+        assert!(channel.refuse_uploads);
+
+        let id = offered_id(&channel.offer_bytes("thumb", vec![1]));
+        channel.clear_outbox();
+        assert!(channel.take(&id).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+(function(config) {
+    'use strict';
+
+    // Already installed (e.g. both as a user script and inline)
+    if (window[config.globalName] && window[config.globalName].__send) {
+        return;
+    }
+
+    // Check if WebKit message handler is available
+    const hasWebKitHandler = typeof webkit !== 'undefined' &&
+                             webkit.messageHandlers &&
+                             webkit.messageHandlers[config.handlerName];
+
+    // Random per-frame prefix, so other frames can't guess or reuse our IDs
+    const frameNonce = typeof crypto.randomUUID === 'function'
+        ? crypto.randomUUID()
+        : Array.from(crypto.getRandomValues(new Uint8Array(16)),
+                     (b) => b.toString(16).padStart(2, '0')).join('');
+
+    // Request and stream IDs: the frame's nonce and a counter
+    let lastId = 0;
+    const nextId = function() {
+        lastId += 1;
+        return frameNonce + ':' + lastId;
+    };
+
+    // Messages and binary data for this bridge's listeners only, so bridges
+    // installed under other globals don't see them
+    const events = new EventTarget();
+
+    // Receive message from Rust
+    const receive = function(msg) {
+        events.dispatchEvent(new CustomEvent('message', { detail: msg }));
+    };
+
+    // Topic subscriptions fed by Rust
+    const subscriptions = new Map();
+
+    // Receive a batch of [topic, value] events, then ask Rust for the next one
+    const publish = function(batch) {
+        for (const [topic, value] of batch.events) {
+            const callbacks = subscriptions.get(topic);
+            if (!callbacks) {
+                continue;
+            }
+            for (const callback of Array.from(callbacks)) {
+                try {
+                    callback(value);
+                } catch (e) {
+                    console.error('wpe: subscriber for ' + topic + ' failed', e);
+                }
+            }
+        }
+        post({ type: '__wpe_ack', payload: { seq: batch.seq } });
+    };
+
+    // Receive binary data from Rust: fetch it from the binary channel
+    const receiveBytes = function(info) {
+        fetch('wpe://bytes/out/' + info.id).then((response) => {
+            if (info.stream) {
+                events.dispatchEvent(new CustomEvent('stream', {
+                    detail: { type: info.type, stream: response.body, length: info.length }
+                }));
+                return;
+            }
+            return response.arrayBuffer().then((data) => {
+                events.dispatchEvent(new CustomEvent('bytes', {
+                    detail: { type: info.type, data }
+                }));
+            });
+        }).catch((e) => console.error('wpe: failed to receive bytes', e));
+    };
+
+    // Post one binary chunk to Rust
+    const postBytes = async function(type, data, query) {
+        const response = await fetch(
+            'wpe://bytes/in/' + encodeURIComponent(type) + (query || ''),
+            { method: 'POST', body: data }
+        );
+        if (!response.ok) {
+            throw new Error('Binary IPC failed with status ' + response.status);
+        }
+    };
+
+    // Send message to Rust
+    const post = function(msg) {
+        if (hasWebKitHandler) {
+            // Use WebKit native message handler (preferred)
+            webkit.messageHandlers[config.handlerName].postMessage(JSON.stringify(msg));
+        } else {
+            // Fallback to fetch-based approach
+            fetch('wpe://message', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify(msg)
+            }).catch(() => {});
+        }
+    };
+
+    // Public API
+    const api = {
+        send(type, payload) {
+            post({ type, payload });
+        },
+
+        onMessage(callback) {
+            events.addEventListener('message', (e) => callback(e.detail));
+        },
+
+        // Promise-based request/response. Options:
+        //   timeout: ms, overriding the configured default; 0 or null waits indefinitely
+        //   signal: an AbortSignal that cancels the call in Rust
+        //   onProgress: called with each progress report from the handler
+        async call(type, payload, options) {
+            options = options || {};
+            const timeout = options.timeout !== undefined ? options.timeout : config.callTimeout;
+            const signal = options.signal;
+            if (signal && signal.aborted) {
+                throw signal.reason || new DOMException('Aborted', 'AbortError');
+            }
+
+            return new Promise((resolve, reject) => {
+                const id = nextId();
+                let timer = null;
+                const finish = () => {
+                    events.removeEventListener('message', handler);
+                    clearTimeout(timer);
+                    if (signal) {
+                        signal.removeEventListener('abort', onAbort);
+                    }
+                };
+                // Stop waiting and tell Rust to drop the call
+                const cancel = (error) => {
+                    finish();
+                    post({ type: '__wpe_cancel', payload: { id } });
+                    reject(error);
+                };
+                const onAbort = () => {
+                    cancel(signal.reason || new DOMException('Aborted', 'AbortError'));
+                };
+                const handler = (e) => {
+                    if (!e.detail || e.detail._responseId !== id) {
+                        return;
+                    }
+                    if (e.detail.progress) {
+                        try {
+                            if (options.onProgress) {
+                                options.onProgress(e.detail.payload);
+                            }
+                        } catch (err) {
+                            console.error('wpe: onProgress for ' + type + ' failed', err);
+                        }
+                        return;
+                    }
+                    finish();
+                    if (e.detail.error) {
+                        reject(new Error(e.detail.error));
+                    } else {
+                        resolve(e.detail.result);
+                    }
+                };
+                events.addEventListener('message', handler);
+                if (signal) {
+                    signal.addEventListener('abort', onAbort);
+                }
+                post({ type, payload, _requestId: id });
+
+                if (timeout) {
+                    timer = setTimeout(() => cancel(new Error('Request timeout')), timeout);
+                }
+            });
+        },
+
+        // Receive values Rust publishes on a topic; returns an unsubscribe function
+        subscribe(topic, callback) {
+            let callbacks = subscriptions.get(topic);
+            if (!callbacks) {
+                callbacks = new Set();
+                subscriptions.set(topic, callbacks);
+                post({ type: '__wpe_subscribe', payload: { topic } });
+            }
+            callbacks.add(callback);
+
+            return () => {
+                if (!callbacks.delete(callback) || callbacks.size > 0) {
+                    return;
+                }
+                if (subscriptions.get(topic) === callbacks) {
+                    subscriptions.delete(topic);
+                    post({ type: '__wpe_unsubscribe', payload: { topic } });
+                }
+            };
+        },
+
+        // Send an ArrayBuffer, typed array or Blob to Rust
+        sendBytes(type, data) {
+            return postBytes(type, data);
+        },
+
+        // Send a Blob or ReadableStream to Rust in order, in chunks of at most chunkSize
+        async sendStream(type, source, chunkSize = 1024 * 1024) {
+            const query = '?stream=' + nextId();
+            const stream = source instanceof Blob ? source.stream() : source;
+            const reader = stream.getReader();
+            for (;;) {
+                const { done, value } = await reader.read();
+                if (done) {
+                    break;
+                }
+                const bytes = value instanceof Uint8Array ? value : new Uint8Array(value);
+                for (let offset = 0; offset < bytes.byteLength; offset += chunkSize) {
+                    await postBytes(type, bytes.subarray(offset, offset + chunkSize), query);
+                }
+            }
+            await postBytes(type, new Uint8Array(0), query + '&end=1');
+        },
+
+        onBytes(callback) {
+            events.addEventListener('bytes', (e) => callback(e.detail.type, e.detail.data));
+        },
+
+        onStream(callback) {
+            events.addEventListener('stream', (e) => {
+                callback(e.detail.type, e.detail.stream, e.detail.length);
+            });
+        },
+
+        // Check if native handler is available
+        hasNativeHandler() {
+            return hasWebKitHandler;
+        }
+    };
+
+    // Hooks called by Rust, hidden from enumeration
+    Object.defineProperties(api, {
+        __receive: { value: receive },
+        __publish: { value: publish },
+        __receiveBytes: { value: receiveBytes },
+        __send: { value: post },
+    });
+    window[config.globalName] = api;
+
+    // Signal that bridge is ready, e.g. `wpe:ready` for the default global
+    window.dispatchEvent(new CustomEvent(config.globalName + ':ready', {
+        detail: { globalName: config.globalName }
+    }));
+})
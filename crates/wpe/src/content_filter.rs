@@ -0,0 +1,527 @@
+//! Declarative content blocking with WebKit content rule lists.
+//!
+//! Rule lists use the Safari content blocker JSON format. WebKit compiles
+//! them into a [`ContentFilterStore`] on disk, so later runs can load the
+//! compiled list instead of compiling it again. Attached filters are
+//! applied by WebKit itself, without calling into Rust for each request.
+//!
+//! ```rust,ignore
+//! use wpe::ContentFilterStore;
+//!
+//! let store = ContentFilterStore::new("/var/cache/kiosk/content-filters")?;
+//! let ads = store.load_or_compile("ads-v3", include_str!("ads.json"))?;
+//! webview.add_content_filter(&ads);
+//! ```
+//!
+//! The store runs its work on the GLib main context. The plain methods
+//! block by iterating the default context until WebKit reports back, which
+//! suits setup code before the window runs. They must not be called from
+//! inside the main loop (a GLib signal handler, IPC handler or timer), where
+//! the nested iteration would dispatch other callbacks re-entrantly; use the
+//! `_with_callback` variants there.
+//!
+//! ```rust,ignore
+//! store.load_with_callback("ads-v3", move |result| match result {
+//!     Ok(ads) => tracing::info!("Loaded {}", ads.identifier()),
+//!     Err(e) => tracing::warn!("No compiled ads list: {}", e),
+//! })?;
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::{Error, Result};
+
+/// A compiled content rule list.
+///
+/// Attach it with `add_content_filter` on a web view or native window.
+/// Cloning is cheap and shares the compiled list.
+pub struct ContentFilter {
+    raw: *mut wpe_sys::WebKitUserContentFilter,
+}
+
+impl ContentFilter {
+    /// The identifier the list was compiled under.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn identifier(&self) -> String {
+        // SAFETY: raw is a valid filter referenced by this handle.
+        unsafe {
+            let identifier = wpe_sys::webkit_user_content_filter_get_identifier(self.raw);
+            if identifier.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(identifier).to_string_lossy().into_owned()
+            }
+        }
+    }
+
+    /// The underlying `WebKitUserContentFilter`.
+    pub(crate) fn raw(&self) -> *mut wpe_sys::WebKitUserContentFilter {
+        self.raw
+    }
+}
+
+impl Clone for ContentFilter {
+    #[allow(unsafe_code)]
+    fn clone(&self) -> Self {
+        // SAFETY: raw is a valid filter referenced by this handle.
+        unsafe {
+            wpe_sys::webkit_user_content_filter_ref(self.raw);
+        }
+        Self { raw: self.raw }
+    }
+}
+
+impl Drop for ContentFilter {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        if !self.raw.is_null() {
+            // SAFETY: we own one reference to raw.
+            unsafe {
+                wpe_sys::webkit_user_content_filter_unref(self.raw);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ContentFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentFilter")
+            .field("identifier", &self.identifier())
+            .finish()
+    }
+}
+
+/// An on-disk store of compiled content rule lists.
+pub struct ContentFilterStore {
+    raw: *mut wpe_sys::WebKitUserContentFilterStore,
+    path: PathBuf,
+}
+
+impl ContentFilterStore {
+    /// Open (or create) the store in directory `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the path contains NUL bytes.
+    #[allow(unsafe_code)]
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let c_path = path_to_cstring(&path)?;
+
+        // SAFETY: c_path is a valid C string; the store copies it.
+        let raw = unsafe { wpe_sys::webkit_user_content_filter_store_new(c_path.as_ptr()) };
+        if raw.is_null() {
+            return Err(Error::ContentFilterError(format!(
+                "failed to open store at {}",
+                path.display()
+            )));
+        }
+
+        Ok(Self { raw, path })
+    }
+
+    /// The directory compiled lists are stored in.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compile a JSON rule list and save it under `identifier`.
+    ///
+    /// Replaces any list already stored under that identifier. Blocks until
+    /// the list is compiled; see the [module docs](self).
+    ///
+    /// # Errors
+    /// Returns an error if the identifier is invalid or the rules do not compile.
+    pub fn compile(&self, identifier: &str, json: &str) -> Result<ContentFilter> {
+        let result = block_on(|done| self.compile_with_callback(identifier, json, done))?;
+        if result.is_ok() {
+            tracing::debug!("Compiled content filter '{}'", identifier);
+        }
+        result
+    }
+
+    /// Compile a JSON rule list under `identifier` and pass the result to `callback`.
+    ///
+    /// The callback runs on the GLib main loop once WebKit is done.
+    ///
+    /// # Errors
+    /// Returns an error if the identifier is invalid, in which case the
+    /// callback is never called.
+    #[allow(unsafe_code)]
+    pub fn compile_with_callback<F>(&self, identifier: &str, json: &str, callback: F) -> Result<()>
+    where
+        F: FnOnce(Result<ContentFilter>) + 'static,
+    {
+        let c_identifier = identifier_to_cstring(identifier)?;
+
+        // SAFETY: raw is a valid store, and the pending operation holds a
+        // reference to it until finish runs. g_bytes_new copies the rules,
+        // and WebKit copies them again before save returns.
+        unsafe {
+            let source = wpe_sys::g_bytes_new(json.as_ptr() as *const _, json.len() as u64);
+            let store = self.raw;
+            start(
+                |ready, user_data| {
+                    wpe_sys::webkit_user_content_filter_store_save(
+                        store,
+                        c_identifier.as_ptr(),
+                        source,
+                        ptr::null_mut(),
+                        ready,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+                    let filter = wpe_sys::webkit_user_content_filter_store_save_finish(
+                        store, result, &mut error,
+                    );
+                    filter_or_error(filter, error)
+                },
+                callback,
+            );
+            wpe_sys::g_bytes_unref(source);
+        }
+        Ok(())
+    }
+
+    /// Compile the JSON rule list in file `path` and save it under `identifier`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or the rules do not compile.
+    pub fn compile_file(&self, identifier: &str, path: impl AsRef<Path>) -> Result<ContentFilter> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            Error::ContentFilterError(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        self.compile(identifier, &json)
+    }
+
+    /// Load the list previously compiled under `identifier`.
+    ///
+    /// Blocks until the list is loaded; see the [module docs](self).
+    ///
+    /// # Errors
+    /// Returns an error if no list is stored under that identifier.
+    pub fn load(&self, identifier: &str) -> Result<ContentFilter> {
+        block_on(|done| self.load_with_callback(identifier, done))?
+    }
+
+    /// Load the list compiled under `identifier` and pass the result to `callback`.
+    ///
+    /// The callback runs on the GLib main loop once WebKit is done.
+    ///
+    /// # Errors
+    /// Returns an error if the identifier is invalid, in which case the
+    /// callback is never called.
+    #[allow(unsafe_code)]
+    pub fn load_with_callback<F>(&self, identifier: &str, callback: F) -> Result<()>
+    where
+        F: FnOnce(Result<ContentFilter>) + 'static,
+    {
+        let c_identifier = identifier_to_cstring(identifier)?;
+
+        // SAFETY: raw is a valid store, and the pending operation holds a
+        // reference to it until finish runs.
+        unsafe {
+            let store = self.raw;
+            start(
+                |ready, user_data| {
+                    wpe_sys::webkit_user_content_filter_store_load(
+                        store,
+                        c_identifier.as_ptr(),
+                        ptr::null_mut(),
+                        ready,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+                    let filter = wpe_sys::webkit_user_content_filter_store_load_finish(
+                        store, result, &mut error,
+                    );
+                    filter_or_error(filter, error)
+                },
+                callback,
+            );
+        }
+        Ok(())
+    }
+
+    /// Load the list stored under `identifier`, compiling `json` if there is none.
+    ///
+    /// The stored list is used as-is, so give changed rules a new identifier
+    /// (e.g. with a version suffix) or [`remove`](Self::remove) the old one.
+    /// Blocks until done; see the [module docs](self).
+    ///
+    /// # Errors
+    /// Returns an error if the rules have to be compiled and do not compile.
+    pub fn load_or_compile(&self, identifier: &str, json: &str) -> Result<ContentFilter> {
+        self.load(identifier)
+            .or_else(|_| self.compile(identifier, json))
+    }
+
+    /// Delete the list stored under `identifier`.
+    ///
+    /// Blocks until done; see the [module docs](self).
+    ///
+    /// # Errors
+    /// Returns an error if no list is stored under that identifier.
+    #[allow(unsafe_code)]
+    pub fn remove(&self, identifier: &str) -> Result<()> {
+        let c_identifier = identifier_to_cstring(identifier)?;
+
+        // SAFETY: raw is a valid store for the duration of the call.
+        unsafe {
+            let store = self.raw;
+            wait_for(
+                |callback, user_data| {
+                    wpe_sys::webkit_user_content_filter_store_remove(
+                        store,
+                        c_identifier.as_ptr(),
+                        ptr::null_mut(),
+                        callback,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+                    if wpe_sys::webkit_user_content_filter_store_remove_finish(
+                        store, result, &mut error,
+                    ) == 0
+                    {
+                        Err(error_from_gerror(error))
+                    } else {
+                        Ok(())
+                    }
+                },
+            )
+        }
+    }
+
+    /// The identifiers of all stored lists.
+    ///
+    /// Blocks until done; see the [module docs](self).
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn identifiers(&self) -> Vec<String> {
+        // SAFETY: raw is a valid store for the duration of the call. The
+        // returned array is owned by us and freed with g_strfreev.
+        unsafe {
+            let store = self.raw;
+            wait_for(
+                |callback, user_data| {
+                    wpe_sys::webkit_user_content_filter_store_fetch_identifiers(
+                        store,
+                        ptr::null_mut(),
+                        callback,
+                        user_data,
+                    );
+                },
+                move |result| {
+                    let identifiers =
+                        wpe_sys::webkit_user_content_filter_store_fetch_identifiers_finish(
+                            store, result,
+                        );
+                    let mut out = Vec::new();
+                    if identifiers.is_null() {
+                        return out;
+                    }
+                    let mut cursor = identifiers;
+                    while !(*cursor).is_null() {
+                        out.push(CStr::from_ptr(*cursor).to_string_lossy().into_owned());
+                        cursor = cursor.add(1);
+                    }
+                    wpe_sys::g_strfreev(identifiers);
+                    out
+                },
+            )
+        }
+    }
+}
+
+impl Drop for ContentFilterStore {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        if !self.raw.is_null() {
+            // SAFETY: we own the store's reference.
+            unsafe {
+                wpe_sys::g_object_unref(self.raw as *mut _);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ContentFilterStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ContentFilterStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Completion of a pending store operation.
+type ReadyCallback = Box<dyn FnOnce(*mut wpe_sys::GAsyncResult)>;
+
+/// Completion handler for store operations.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_ready(
+    _source: *mut wpe_sys::GObject,
+    result: *mut wpe_sys::GAsyncResult,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+    let callback = Box::from_raw(user_data as *mut ReadyCallback);
+    callback(result);
+}
+
+/// Start an async store operation and pass its result to `done`.
+///
+/// `begin` receives the callback and user data to pass to the operation;
+/// `finish` turns its result into the value handed to `done`.
+#[allow(unsafe_code)]
+unsafe fn start<T: 'static>(
+    begin: impl FnOnce(wpe_sys::GAsyncReadyCallback, *mut std::ffi::c_void),
+    finish: impl FnOnce(*mut wpe_sys::GAsyncResult) -> T + 'static,
+    done: impl FnOnce(T) + 'static,
+) {
+    let callback: ReadyCallback = Box::new(move |result| done(finish(result)));
+
+    // Double-box so the fat trait object pointer fits in a gpointer.
+    begin(Some(on_ready), Box::into_raw(Box::new(callback)) as *mut _);
+}
+
+/// Start an async store operation and iterate the main context until it completes.
+///
+/// Must not be called from inside the main loop; see the module docs.
+#[allow(unsafe_code)]
+unsafe fn wait_for<T: 'static>(
+    begin: impl FnOnce(wpe_sys::GAsyncReadyCallback, *mut std::ffi::c_void),
+    finish: impl FnOnce(*mut wpe_sys::GAsyncResult) -> T + 'static,
+) -> T {
+    block_on(|done| {
+        start(begin, finish, done);
+        Ok::<_, std::convert::Infallible>(())
+    })
+    .unwrap_or_else(|never| match never {})
+}
+
+/// Run `begin` with a completion callback and iterate the default main
+/// context until it is called.
+///
+/// If `begin` fails the callback was never registered and its error is returned.
+#[allow(unsafe_code)]
+fn block_on<T: 'static, E>(
+    begin: impl FnOnce(Box<dyn FnOnce(T)>) -> std::result::Result<(), E>,
+) -> std::result::Result<T, E> {
+    let slot = Rc::new(RefCell::new(None));
+    let sink = Rc::clone(&slot);
+    begin(Box::new(move |value| {
+        *sink.borrow_mut() = Some(value);
+    }))?;
+
+    // SAFETY: The default context is only iterated from the thread running it.
+    let context = unsafe { wpe_sys::g_main_context_default() };
+    loop {
+        if let Some(value) = slot.borrow_mut().take() {
+            return Ok(value);
+        }
+        // SAFETY: As above.
+        unsafe {
+            wpe_sys::g_main_context_iteration(context, 1);
+        }
+    }
+}
+
+/// Wrap a filter returned by a `_finish` function, or convert its error.
+#[allow(unsafe_code)]
+unsafe fn filter_or_error(
+    filter: *mut wpe_sys::WebKitUserContentFilter,
+    error: *mut wpe_sys::GError,
+) -> Result<ContentFilter> {
+    if filter.is_null() {
+        Err(error_from_gerror(error))
+    } else {
+        Ok(ContentFilter { raw: filter })
+    }
+}
+
+/// Convert (and free) a `GError` from the store.
+#[allow(unsafe_code)]
+unsafe fn error_from_gerror(error: *mut wpe_sys::GError) -> Error {
+    if error.is_null() {
+        return Error::ContentFilterError("unknown error".to_string());
+    }
+    let message = if (*error).message.is_null() {
+        "unknown error".to_string()
+    } else {
+        CStr::from_ptr((*error).message)
+            .to_string_lossy()
+            .into_owned()
+    };
+    wpe_sys::g_error_free(error);
+    Error::ContentFilterError(message)
+}
+
+fn identifier_to_cstring(identifier: &str) -> Result<CString> {
+    if identifier.is_empty() {
+        return Err(Error::ContentFilterError(
+            "identifier cannot be empty".to_string(),
+        ));
+    }
+    CString::new(identifier)
+        .map_err(|_| Error::ContentFilterError(format!("invalid identifier: {identifier:?}")))
+}
+
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::ContentFilterError(format!("invalid path: {}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store that is never handed to WebKit.
+    fn detached_store() -> ContentFilterStore {
+        ContentFilterStore {
+            raw: ptr::null_mut(),
+            path: PathBuf::from("/nonexistent"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_identifiers() {
+        let store = detached_store();
+        assert!(matches!(
+            store.compile("", "[]"),
+            Err(Error::ContentFilterError(_))
+        ));
+        assert!(matches!(
+            store.load("ads\0v1"),
+            Err(Error::ContentFilterError(_))
+        ));
+        assert!(matches!(
+            store.load_with_callback("", |_| panic!("callback must not run")),
+            Err(Error::ContentFilterError(_))
+        ));
+    }
+
+    #[test]
+    fn test_compile_missing_file() {
+        let store = detached_store();
+        let err = store
+            .compile_file("ads", "/nonexistent/rules.json")
+            .unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/rules.json"));
+    }
+}
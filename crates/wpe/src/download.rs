@@ -0,0 +1,676 @@
+//! Downloads started by pages or from Rust.
+//!
+//! WebKit reports downloads through the web view's network session. Each
+//! one is tracked as a [`Download`] handle, which can be queried for
+//! progress and cancelled, and its lifecycle is queued as
+//! [`DownloadEvent`]s on the web view that started it. Downloads need no
+//! UI, so they work the same in the headless [`WebView`](crate::WebView).
+//!
+//! ```rust,ignore
+//! use wpe::DownloadDestination;
+//!
+//! webview.on_download(|_download, suggested_filename| {
+//!     DownloadDestination::Path(downloads_dir.join(suggested_filename))
+//! });
+//!
+//! let download = webview.download_uri("https://example.com/report.csv")?;
+//!
+//! for event in webview.receive_download_events() {
+//!     if let DownloadEvent::Progress { download, received, total } = event {
+//!         println!("{}: {} of {:?} bytes", download.url(), received, total);
+//!     }
+//! }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of download IDs.
+static NEXT_DOWNLOAD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Where a download is saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadDestination {
+    /// Let WebKit pick a file in the user's download directory.
+    Default,
+    /// Save to this absolute path. Existing files are not overwritten.
+    Path(PathBuf),
+    /// Cancel the download.
+    Cancel,
+}
+
+/// The state of a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    /// Data is still being received.
+    InProgress,
+    /// The file was saved completely.
+    Finished,
+    /// The download stopped with an error.
+    Failed,
+    /// The download was cancelled.
+    Cancelled,
+}
+
+/// A download lifecycle event.
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    /// A download started.
+    Started(Download),
+    /// More data was received.
+    ///
+    /// Consecutive progress events of a download are coalesced, so only
+    /// the latest one is queued.
+    Progress {
+        download: Download,
+        /// Bytes received so far
+        received: u64,
+        /// Expected size, if the server reported one
+        total: Option<u64>,
+    },
+    /// The download finished successfully.
+    Finished(Download),
+    /// The download failed.
+    Failed { download: Download, error: String },
+    /// The download was cancelled.
+    Cancelled(Download),
+}
+
+/// Handle to a download.
+///
+/// Handles are cheap to clone and stay valid after the download ends.
+#[derive(Clone)]
+pub struct Download {
+    inner: Rc<DownloadInner>,
+}
+
+struct DownloadInner {
+    id: u64,
+    /// Strong reference to the WebKit download
+    raw: *mut wpe_sys::WebKitDownload,
+    status: Cell<DownloadStatus>,
+}
+
+impl Drop for DownloadInner {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        if !self.raw.is_null() {
+            // SAFETY: raw was referenced when the handle was created.
+            unsafe {
+                wpe_sys::g_object_unref(self.raw as *mut _);
+            }
+        }
+    }
+}
+
+impl Download {
+    /// Wrap `raw`, taking a new reference to it.
+    ///
+    /// # Safety
+    /// `raw` must be null or a valid `WebKitDownload`.
+    #[allow(unsafe_code)]
+    unsafe fn new(raw: *mut wpe_sys::WebKitDownload) -> Self {
+        if !raw.is_null() {
+            wpe_sys::g_object_ref(raw as *mut _);
+        }
+        Self {
+            inner: Rc::new(DownloadInner {
+                id: NEXT_DOWNLOAD_ID.fetch_add(1, Ordering::Relaxed),
+                raw,
+                status: Cell::new(DownloadStatus::InProgress),
+            }),
+        }
+    }
+
+    /// A process-unique ID for this download.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// The current state of the download.
+    #[must_use]
+    pub fn status(&self) -> DownloadStatus {
+        self.inner.status.get()
+    }
+
+    /// The URL being downloaded.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn url(&self) -> String {
+        // SAFETY: raw is kept alive by this handle.
+        unsafe {
+            let request = wpe_sys::webkit_download_get_request(self.inner.raw);
+            if request.is_null() {
+                return String::new();
+            }
+            let uri = wpe_sys::webkit_uri_request_get_uri(request);
+            if uri.is_null() {
+                return String::new();
+            }
+            CStr::from_ptr(uri).to_string_lossy().into_owned()
+        }
+    }
+
+    /// The file the download is saved to, once it has been decided.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn destination(&self) -> Option<PathBuf> {
+        // SAFETY: raw is kept alive by this handle.
+        unsafe {
+            let destination = wpe_sys::webkit_download_get_destination(self.inner.raw);
+            (!destination.is_null()).then(|| {
+                PathBuf::from(std::ffi::OsStr::from_bytes(
+                    CStr::from_ptr(destination).to_bytes(),
+                ))
+            })
+        }
+    }
+
+    /// Bytes received so far.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn received_bytes(&self) -> u64 {
+        // SAFETY: raw is kept alive by this handle.
+        unsafe { wpe_sys::webkit_download_get_received_data_length(self.inner.raw) }
+    }
+
+    /// The expected size in bytes, if the server reported one.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn total_bytes(&self) -> Option<u64> {
+        // SAFETY: raw is kept alive by this handle.
+        unsafe {
+            let response = wpe_sys::webkit_download_get_response(self.inner.raw);
+            if response.is_null() {
+                return None;
+            }
+            let length = wpe_sys::webkit_uri_response_get_content_length(response);
+            (length > 0).then_some(length)
+        }
+    }
+
+    /// Estimated progress between 0.0 and 1.0.
+    #[must_use]
+    #[allow(unsafe_code)]
+    pub fn progress(&self) -> f64 {
+        // SAFETY: raw is kept alive by this handle.
+        unsafe { wpe_sys::webkit_download_get_estimated_progress(self.inner.raw) }
+    }
+
+    /// Cancel the download if it is still in progress.
+    #[allow(unsafe_code)]
+    pub fn cancel(&self) {
+        if self.status() != DownloadStatus::InProgress {
+            return;
+        }
+        self.inner.status.set(DownloadStatus::Cancelled);
+        // SAFETY: raw is kept alive by this handle.
+        unsafe {
+            wpe_sys::webkit_download_cancel(self.inner.raw);
+        }
+    }
+}
+
+impl PartialEq for Download {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for Download {}
+
+impl std::fmt::Debug for Download {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Download")
+            .field("id", &self.inner.id)
+            .field("status", &self.inner.status.get())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handler choosing where a download is saved.
+pub(crate) type DestinationHandler = Box<dyn Fn(&Download, &str) -> DownloadDestination>;
+
+/// Download state of a web view, shared with the download signal handlers.
+#[derive(Default)]
+pub(crate) struct DownloadState {
+    /// The web view whose downloads are tracked
+    web_view: Cell<*mut wpe_sys::WebKitWebView>,
+    handler: RefCell<Option<DestinationHandler>>,
+    /// Downloads still in progress
+    active: RefCell<Vec<Download>>,
+    events: RefCell<VecDeque<DownloadEvent>>,
+}
+
+impl DownloadState {
+    /// Install the destination handler, replacing any previous one.
+    pub(crate) fn set_handler(&self, handler: DestinationHandler) {
+        *self.handler.borrow_mut() = Some(handler);
+    }
+
+    /// Take the events queued since the last call.
+    pub(crate) fn take_events(&self) -> Vec<DownloadEvent> {
+        self.events.borrow_mut().drain(..).collect()
+    }
+
+    fn find(&self, raw: *mut wpe_sys::WebKitDownload) -> Option<Download> {
+        self.active
+            .borrow()
+            .iter()
+            .find(|download| download.inner.raw == raw)
+            .cloned()
+    }
+
+    fn push(&self, event: DownloadEvent) {
+        let mut events = self.events.borrow_mut();
+        if let DownloadEvent::Progress { download, .. } = &event {
+            if let Some(DownloadEvent::Progress { download: last, .. }) = events.back() {
+                if last == download {
+                    events.pop_back();
+                }
+            }
+        }
+        events.push_back(event);
+    }
+
+    /// Mark `download` as ended with `status` and stop tracking it.
+    fn end(&self, download: &Download, status: DownloadStatus, event: DownloadEvent) {
+        download.inner.status.set(status);
+        self.active.borrow_mut().retain(|active| active != download);
+        self.push(event);
+    }
+}
+
+/// Signal handler for the network session's download-started.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_download_started(
+    _session: *mut wpe_sys::WebKitNetworkSession,
+    raw: *mut wpe_sys::WebKitDownload,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() || raw.is_null() {
+        return;
+    }
+
+    let state_ptr = user_data as *const DownloadState;
+    // The session is shared between web views; only track our own downloads
+    if wpe_sys::webkit_download_get_web_view(raw) != (*state_ptr).web_view.get() {
+        return;
+    }
+
+    // SAFETY: user_data came from Rc::into_raw and is still owned by the connection.
+    Rc::increment_strong_count(state_ptr);
+    let state = Rc::from_raw(state_ptr);
+    track(&state, raw);
+}
+
+/// Signal handler for decide-destination.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_decide_destination(
+    raw: *mut wpe_sys::WebKitDownload,
+    suggested_filename: *const std::ffi::c_char,
+    user_data: *mut std::ffi::c_void,
+) -> i32 {
+    if user_data.is_null() {
+        return 0;
+    }
+
+    let state = &*(user_data as *const DownloadState);
+    let Some(download) = state.find(raw) else {
+        return 0;
+    };
+    let Ok(handler) = state.handler.try_borrow() else {
+        tracing::warn!("Download handler re-entered, using the default destination");
+        return 0;
+    };
+    let Some(handler) = handler.as_ref() else {
+        return 0;
+    };
+
+    let suggested_filename = if suggested_filename.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(suggested_filename)
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    match handler(&download, &suggested_filename) {
+        DownloadDestination::Default => 0,
+        DownloadDestination::Path(path) => {
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                tracing::warn!("Invalid download destination {}", path.display());
+                download.cancel();
+                return 1;
+            };
+            tracing::debug!("Saving download to {}", path.display());
+            wpe_sys::webkit_download_set_destination(raw, c_path.as_ptr());
+            1
+        }
+        DownloadDestination::Cancel => {
+            download.cancel();
+            1
+        }
+    }
+}
+
+/// Signal handler for received-data.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_received_data(
+    raw: *mut wpe_sys::WebKitDownload,
+    _data_length: u64,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let state = &*(user_data as *const DownloadState);
+    if let Some(download) = state.find(raw) {
+        let received = download.received_bytes();
+        let total = download.total_bytes();
+        state.push(DownloadEvent::Progress {
+            download,
+            received,
+            total,
+        });
+    }
+}
+
+/// Signal handler for failed (emitted before finished).
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_failed(
+    raw: *mut wpe_sys::WebKitDownload,
+    error: *mut wpe_sys::GError,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let state = &*(user_data as *const DownloadState);
+    let Some(download) = state.find(raw) else {
+        return;
+    };
+
+    let cancelled = download.status() == DownloadStatus::Cancelled
+        || (!error.is_null()
+            && u32::try_from((*error).code).is_ok_and(|code| {
+                code == wpe_sys::WebKitDownloadError_WEBKIT_DOWNLOAD_ERROR_CANCELLED_BY_USER
+            }));
+    if cancelled {
+        tracing::debug!("Download {} cancelled", download.id());
+        state.end(
+            &download,
+            DownloadStatus::Cancelled,
+            DownloadEvent::Cancelled(download.clone()),
+        );
+        return;
+    }
+
+    let error = if error.is_null() || (*error).message.is_null() {
+        "unknown error".to_string()
+    } else {
+        CStr::from_ptr((*error).message)
+            .to_string_lossy()
+            .into_owned()
+    };
+    tracing::warn!("Download {} failed: {}", download.id(), error);
+    state.end(
+        &download,
+        DownloadStatus::Failed,
+        DownloadEvent::Failed {
+            download: download.clone(),
+            error,
+        },
+    );
+}
+
+/// Signal handler for finished.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_finished(
+    raw: *mut wpe_sys::WebKitDownload,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    // Failed downloads were already ended by on_failed
+    let state = &*(user_data as *const DownloadState);
+    if let Some(download) = state.find(raw) {
+        tracing::debug!("Download {} finished", download.id());
+        state.end(
+            &download,
+            DownloadStatus::Finished,
+            DownloadEvent::Finished(download.clone()),
+        );
+    }
+}
+
+/// Release the state reference held by a download signal connection.
+#[allow(unsafe_code)]
+unsafe extern "C" fn release_state(data: *mut std::ffi::c_void, _closure: *mut wpe_sys::GClosure) {
+    drop(Rc::from_raw(data as *const DownloadState));
+}
+
+/// Start tracking `raw` in `state`, or return its existing handle.
+///
+/// # Safety
+/// `raw` must be a valid `WebKitDownload`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn track(
+    state: &Rc<DownloadState>,
+    raw: *mut wpe_sys::WebKitDownload,
+) -> Download {
+    if let Some(download) = state.find(raw) {
+        return download;
+    }
+
+    let download = Download::new(raw);
+    state.active.borrow_mut().push(download.clone());
+
+    // Each connection owns a state reference, released when the download
+    // is finalized, so events keep arriving after the web view is gone
+    let handlers: [(&str, unsafe extern "C" fn()); 4] = [
+        (
+            "decide-destination",
+            std::mem::transmute::<
+                unsafe extern "C" fn(
+                    *mut wpe_sys::WebKitDownload,
+                    *const std::ffi::c_char,
+                    *mut std::ffi::c_void,
+                ) -> i32,
+                unsafe extern "C" fn(),
+            >(on_decide_destination),
+        ),
+        (
+            "received-data",
+            std::mem::transmute::<
+                unsafe extern "C" fn(*mut wpe_sys::WebKitDownload, u64, *mut std::ffi::c_void),
+                unsafe extern "C" fn(),
+            >(on_received_data),
+        ),
+        (
+            "failed",
+            std::mem::transmute::<
+                unsafe extern "C" fn(
+                    *mut wpe_sys::WebKitDownload,
+                    *mut wpe_sys::GError,
+                    *mut std::ffi::c_void,
+                ),
+                unsafe extern "C" fn(),
+            >(on_failed),
+        ),
+        (
+            "finished",
+            std::mem::transmute::<
+                unsafe extern "C" fn(*mut wpe_sys::WebKitDownload, *mut std::ffi::c_void),
+                unsafe extern "C" fn(),
+            >(on_finished),
+        ),
+    ];
+    for (name, handler) in handlers {
+        let signal_name = CString::new(name).expect("static string has no NUL bytes");
+        wpe_sys::g_signal_connect_data(
+            raw as *mut _,
+            signal_name.as_ptr(),
+            Some(handler),
+            Rc::into_raw(Rc::clone(state)) as *mut _,
+            Some(release_state),
+            0,
+        );
+    }
+
+    tracing::debug!("Download {} started: {}", download.id(), download.url());
+    state.push(DownloadEvent::Started(download.clone()));
+    download
+}
+
+/// Track the downloads of `web_view` in `state`.
+///
+/// Returns the state pointer, which must be reclaimed with `Rc::from_raw`
+/// after [`disconnect`], and the handler ID to pass to it.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect(
+    web_view: *mut wpe_sys::WebKitWebView,
+    state: &Rc<DownloadState>,
+) -> (*const DownloadState, u64) {
+    state.web_view.set(web_view);
+    let session = wpe_sys::webkit_web_view_get_network_session(web_view);
+    if session.is_null() {
+        tracing::warn!("Web view has no network session, downloads are not tracked");
+        return (ptr::null(), 0);
+    }
+
+    let state_ptr = Rc::into_raw(Rc::clone(state));
+
+    let signal_name = CString::new("download-started").expect("static string has no NUL bytes");
+    let handler_id = wpe_sys::g_signal_connect_data(
+        session as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitNetworkSession,
+                *mut wpe_sys::WebKitDownload,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_download_started)),
+        state_ptr as *mut _,
+        None,
+        0,
+    );
+
+    (state_ptr, handler_id)
+}
+
+/// Stop tracking the downloads of `web_view`.
+///
+/// The network session outlives web views, so its handler has to be
+/// removed before the state pointer is reclaimed.
+///
+/// # Safety
+/// `web_view` must be the valid `WebKitWebView` passed to [`connect`].
+#[allow(unsafe_code)]
+pub(crate) unsafe fn disconnect(web_view: *mut wpe_sys::WebKitWebView, handler_id: u64) {
+    if handler_id == 0 {
+        return;
+    }
+    let session = wpe_sys::webkit_web_view_get_network_session(web_view);
+    if !session.is_null() {
+        wpe_sys::g_signal_handler_disconnect(session as *mut _, handler_id);
+    }
+}
+
+/// Start downloading `url` from `web_view`.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView` connected to `state`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn download_uri(
+    web_view: *mut wpe_sys::WebKitWebView,
+    state: &Rc<DownloadState>,
+    url: &str,
+) -> crate::Result<Download> {
+    if url.is_empty() {
+        return Err(crate::Error::InvalidUrl("URL cannot be empty".to_string()));
+    }
+    let c_url = CString::new(url).map_err(|_| crate::Error::InvalidUrl(url.to_string()))?;
+
+    let raw = wpe_sys::webkit_web_view_download_uri(web_view, c_url.as_ptr());
+    if raw.is_null() {
+        return Err(crate::Error::DownloadFailed(url.to_string()));
+    }
+
+    // download-started has usually tracked it already
+    let download = track(state, raw);
+    wpe_sys::g_object_unref(raw as *mut _);
+    Ok(download)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(unsafe_code)]
+    fn download() -> Download {
+        // SAFETY: null downloads are never passed to WebKit by these tests.
+        unsafe { Download::new(ptr::null_mut()) }
+    }
+
+    #[test]
+    fn test_progress_events_coalesce() {
+        let state = DownloadState::default();
+        let (a, b) = (download(), download());
+        let progress = |download: &Download, received| DownloadEvent::Progress {
+            download: download.clone(),
+            received,
+            total: Some(100),
+        };
+
+        state.push(DownloadEvent::Started(a.clone()));
+        state.push(progress(&a, 10));
+        state.push(progress(&a, 20));
+        state.push(progress(&b, 5));
+        state.push(progress(&a, 30));
+
+        let events = state.take_events();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[1],
+            DownloadEvent::Progress { received: 20, .. }
+        ));
+        assert!(matches!(
+            &events[3],
+            DownloadEvent::Progress { received: 30, .. }
+        ));
+        assert!(state.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_end_stops_tracking() {
+        let state = DownloadState::default();
+        let a = download();
+        state.active.borrow_mut().push(a.clone());
+
+        state.end(
+            &a,
+            DownloadStatus::Finished,
+            DownloadEvent::Finished(a.clone()),
+        );
+        assert_eq!(a.status(), DownloadStatus::Finished);
+        assert!(state.find(ptr::null_mut()).is_none());
+        assert_ne!(a.id(), download().id());
+    }
+}
@@ -23,9 +23,31 @@ pub enum Error {
     #[error("JavaScript evaluation failed: {0}")]
     JavaScriptError(String),
 
+    #[error("JavaScript exception at {source_url}:{line}: {message}")]
+    JavaScriptException {
+        message: String,
+        line: u32,
+        source_url: String,
+    },
+
     #[error("IPC error: {0}")]
     IpcError(String),
 
+    #[error("URI scheme error: {0}")]
+    UriSchemeError(String),
+
+    #[error("Timed out waiting for the page to load")]
+    LoadTimeout,
+
+    #[error("Snapshot failed: {0}")]
+    SnapshotFailed(String),
+
+    #[error("Download failed: {0}")]
+    DownloadFailed(String),
+
+    #[error("Content filter error: {0}")]
+    ContentFilterError(String),
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
@@ -0,0 +1,354 @@
+//! Filtering of document navigations.
+//!
+//! A [`DocumentFilter`] sees the method, URL and headers of each document a
+//! frame navigates to, and allows, blocks or rewrites it. Navigations are
+//! filtered through WebKit's `decide-policy` signal in the UI process, so
+//! pages cannot get around the filter.
+//!
+//! This is not a general request filter. Subresources (`fetch()`,
+//! `XMLHttpRequest`, images, scripts, stylesheets) are never seen: WebKit
+//! only lets web process extensions intercept them through
+//! `WebKitWebPage::send-request`, and this crate does not ship one, so
+//! headers can't be added to API calls either. Block subresources with a
+//! compiled rule list from [`crate::content_filter`], which WebKit applies to
+//! every request without a round trip to Rust.
+//!
+//! Only `GET` navigations can be rewritten, and the rewritten document is
+//! loaded in the main frame, since WebKit doesn't say which frame a
+//! navigation targets. Rewrite only URLs that are loaded as top-level pages.
+//!
+//! ```rust,ignore
+//! use wpe::{DocumentRequest, DocumentRewrite, FilterAction};
+//!
+//! webview.set_document_filter(|request: &DocumentRequest| {
+//!     if request.url.contains("tracker.example.com") {
+//!         FilterAction::Block
+//!     } else if request.url.starts_with("https://app.example.com/") {
+//!         FilterAction::Rewrite(DocumentRewrite::new().with_header("Authorization", "Bearer ..."))
+//!     } else {
+//!         FilterAction::Allow
+//!     }
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::scheme;
+
+/// A document navigation seen by a [`DocumentFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentRequest {
+    /// HTTP method, e.g. `GET`
+    pub method: String,
+    /// Absolute URL of the request
+    pub url: String,
+    /// Request headers
+    pub headers: Vec<(String, String)>,
+}
+
+impl DocumentRequest {
+    /// The value of header `name` (case-insensitive), if present.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Changes applied to a rewritten navigation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentRewrite {
+    /// Replacement URL, if the request should go elsewhere
+    pub url: Option<String>,
+    /// Headers to add, replacing any with the same name
+    pub headers: Vec<(String, String)>,
+}
+
+impl DocumentRewrite {
+    /// A rewrite that keeps the request unchanged.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send the request to `url` instead.
+    #[must_use]
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set a request header.
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// What to do with a filtered navigation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Navigate unchanged.
+    Allow,
+    /// Drop the navigation. The frame stays on its current document.
+    Block,
+    /// Load a modified request in the main frame instead.
+    ///
+    /// The original headers are kept unless replaced. Only `GET` navigations
+    /// can be rewritten; others are allowed unchanged with a warning, since
+    /// their body can't be carried over.
+    Rewrite(DocumentRewrite),
+}
+
+/// Decides what happens to the documents a page navigates to.
+///
+/// Implemented for any `Fn(&DocumentRequest) -> FilterAction` closure that
+/// is `Send + Sync`.
+pub trait DocumentFilter: Send + Sync + 'static {
+    /// Decide what to do with `request`.
+    fn filter(&self, request: &DocumentRequest) -> FilterAction;
+}
+
+impl<F> DocumentFilter for F
+where
+    F: Fn(&DocumentRequest) -> FilterAction + Send + Sync + 'static,
+{
+    fn filter(&self, request: &DocumentRequest) -> FilterAction {
+        self(request)
+    }
+}
+
+/// Whether a navigation with `method` can be rewritten.
+///
+/// Rewrites are loaded as new requests, which can't carry a request body.
+fn can_rewrite(method: &str) -> bool {
+    method.eq_ignore_ascii_case("GET")
+}
+
+/// Filter state of a web view, shared with the `decide-policy` handler.
+#[derive(Default)]
+pub(crate) struct FilterState {
+    filter: RefCell<Option<Arc<dyn DocumentFilter>>>,
+    /// URL of a rewritten document load, which is not filtered again
+    bypass: RefCell<Option<String>>,
+}
+
+impl FilterState {
+    /// Install `filter`, replacing any previous one.
+    pub(crate) fn set(&self, filter: Arc<dyn DocumentFilter>) {
+        *self.filter.borrow_mut() = Some(filter);
+    }
+
+    /// Remove the filter.
+    pub(crate) fn clear(&self) {
+        *self.filter.borrow_mut() = None;
+    }
+
+    /// Whether `url` is the rewritten load to let through, consuming it.
+    fn take_bypass(&self, url: &str) -> bool {
+        let mut bypass = self.bypass.borrow_mut();
+        if bypass.as_deref() == Some(url) {
+            *bypass = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Signal handler for decide-policy, filtering document loads.
+///
+/// Connected before the navigation policy, so allowed loads still reach it.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_decide_policy(
+    web_view: *mut wpe_sys::WebKitWebView,
+    decision: *mut wpe_sys::WebKitPolicyDecision,
+    decision_type: wpe_sys::WebKitPolicyDecisionType,
+    user_data: *mut std::ffi::c_void,
+) -> i32 {
+    if user_data.is_null()
+        || decision.is_null()
+        || decision_type
+            != wpe_sys::WebKitPolicyDecisionType_WEBKIT_POLICY_DECISION_TYPE_NAVIGATION_ACTION
+    {
+        return 0;
+    }
+
+    let state = &*(user_data as *const FilterState);
+    let Some(filter) = state.filter.borrow().clone() else {
+        return 0;
+    };
+
+    let action = wpe_sys::webkit_navigation_policy_decision_get_navigation_action(
+        decision as *mut wpe_sys::WebKitNavigationPolicyDecision,
+    );
+    if action.is_null() {
+        return 0;
+    }
+    let uri_request = wpe_sys::webkit_navigation_action_get_request(action);
+    if uri_request.is_null() {
+        return 0;
+    }
+
+    let url = copy_str(wpe_sys::webkit_uri_request_get_uri(uri_request));
+    if state.take_bypass(&url) {
+        return 0;
+    }
+
+    let method = copy_str(wpe_sys::webkit_uri_request_get_http_method(uri_request));
+    let request = DocumentRequest {
+        method: if method.is_empty() {
+            "GET".to_string()
+        } else {
+            method
+        },
+        url,
+        headers: scheme::read_headers(wpe_sys::webkit_uri_request_get_http_headers(uri_request)),
+    };
+
+    match filter.filter(&request) {
+        FilterAction::Allow => 0,
+        FilterAction::Block => {
+            tracing::debug!("Blocked document {}", request.url);
+            wpe_sys::webkit_policy_decision_ignore(decision);
+            1
+        }
+        FilterAction::Rewrite(_) if !can_rewrite(&request.method) => {
+            tracing::warn!(
+                "Not rewriting {} navigation to {}; only GET navigations can be rewritten",
+                request.method,
+                request.url
+            );
+            0
+        }
+        FilterAction::Rewrite(rewrite) => {
+            wpe_sys::webkit_policy_decision_ignore(decision);
+            let url = rewrite.url.unwrap_or(request.url);
+            tracing::debug!("Rewrote document load to {}", url);
+            let mut headers = request.headers;
+            for (name, value) in rewrite.headers {
+                headers.retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+                headers.push((name, value));
+            }
+            load_rewritten(web_view, state, url, &headers);
+            1
+        }
+    }
+}
+
+/// Load `url` with `headers` in the main frame, bypassing the filter once.
+#[allow(unsafe_code)]
+unsafe fn load_rewritten(
+    web_view: *mut wpe_sys::WebKitWebView,
+    state: &FilterState,
+    url: String,
+    headers: &[(String, String)],
+) {
+    let Ok(c_url) = CString::new(url.as_str()) else {
+        tracing::warn!("Invalid rewritten URL: {}", url);
+        return;
+    };
+
+    let uri_request = wpe_sys::webkit_uri_request_new(c_url.as_ptr());
+    let http_headers = wpe_sys::webkit_uri_request_get_http_headers(uri_request);
+    if !http_headers.is_null() {
+        for (name, value) in headers {
+            if let (Ok(name), Ok(value)) =
+                (CString::new(name.as_str()), CString::new(value.as_str()))
+            {
+                wpe_sys::soup_message_headers_replace(http_headers, name.as_ptr(), value.as_ptr());
+            }
+        }
+    }
+
+    *state.bypass.borrow_mut() = Some(url);
+    wpe_sys::webkit_web_view_load_request(web_view, uri_request);
+    wpe_sys::g_object_unref(uri_request as *mut _);
+}
+
+/// Copy a string owned by WebKit (or return an empty string for NULL).
+#[allow(unsafe_code)]
+unsafe fn copy_str(ptr: *const std::ffi::c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+/// Filter the document loads of `web_view` with the filter in `state`.
+///
+/// Connect this before the navigation policy. The returned pointer keeps
+/// the state alive for the signal handler and must be reclaimed with
+/// `Rc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect(
+    web_view: *mut wpe_sys::WebKitWebView,
+    state: &Rc<FilterState>,
+) -> *const FilterState {
+    let state_ptr = Rc::into_raw(Rc::clone(state));
+
+    let signal_name = CString::new("decide-policy").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                *mut wpe_sys::WebKitPolicyDecision,
+                wpe_sys::WebKitPolicyDecisionType,
+                *mut std::ffi::c_void,
+            ) -> i32,
+            unsafe extern "C" fn(),
+        >(on_decide_policy)),
+        state_ptr as *mut _,
+        None,
+        0,
+    );
+
+    state_ptr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_header() {
+        let request = DocumentRequest {
+            method: "GET".to_string(),
+            url: "https://app.example.com/".to_string(),
+            headers: vec![("X-Token".to_string(), "abc".to_string())],
+        };
+
+        assert_eq!(request.header("x-token"), Some("abc"));
+        assert_eq!(request.header("Accept"), None);
+    }
+
+    #[test]
+    fn test_only_get_navigations_are_rewritten() {
+        assert!(can_rewrite("GET"));
+        assert!(can_rewrite("get"));
+        assert!(!can_rewrite("POST"));
+        assert!(!can_rewrite("PUT"));
+    }
+
+    #[test]
+    fn test_rewrite_bypasses_filter_once() {
+        let state = FilterState::default();
+        *state.bypass.borrow_mut() = Some("https://app.example.com/".to_string());
+
+        assert!(!state.take_bypass("https://other.example.com/"));
+        assert!(state.take_bypass("https://app.example.com/"));
+        assert!(!state.take_bypass("https://app.example.com/"));
+    }
+}
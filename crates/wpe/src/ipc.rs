@@ -1,87 +1,173 @@
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
-
+use std::ffi::CString;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use crate::binary;
+use crate::native::NavigationCounter;
+use crate::policy;
+use crate::scheme::{self, SchemeRequest, SchemeResponse};
+use crate::script;
 use crate::{Result, WebView};
 
-/// JavaScript bridge code injected into web pages.
+/// Shared message queue for receiving messages from JavaScript.
+pub(crate) type MessageQueue = Arc<MessageQueueInner>;
+
+/// Messages received from JavaScript, tagged with the navigation they were sent in.
 ///
-/// This bridge supports two modes:
+/// This is the inner type that Arc::into_raw returns a pointer to.
+pub(crate) struct MessageQueueInner {
+    messages: Mutex<VecDeque<(u64, FrontendMessage)>>,
+    navigation: NavigationCounter,
+}
+
+impl MessageQueueInner {
+    /// Create a queue for the web view whose committed navigations `navigation` counts.
+    pub(crate) fn new(navigation: &NavigationCounter) -> MessageQueue {
+        Arc::new(Self {
+            messages: Mutex::new(VecDeque::new()),
+            navigation: Arc::clone(navigation),
+        })
+    }
+
+    /// Queue a message sent by the current page.
+    pub(crate) fn push(&self, message: FrontendMessage) {
+        let navigation = self.navigation.load(Ordering::Acquire);
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push_back((navigation, message));
+        }
+    }
+
+    /// Take the queued messages.
+    ///
+    /// Messages sent by a page that has been navigated away from since are
+    /// dropped, so its requests can't start calls answered to the next page.
+    pub(crate) fn take(&self) -> Vec<FrontendMessage> {
+        let navigation = self.navigation.load(Ordering::Acquire);
+        match self.messages.lock() {
+            Ok(mut messages) => messages
+                .drain(..)
+                .filter_map(|(sent_in, message)| {
+                    if sent_in == navigation {
+                        Some(message)
+                    } else {
+                        tracing::debug!(
+                            "Dropping IPC message '{}' from a previous page",
+                            message.message_type
+                        );
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Failed to lock message queue: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// JavaScript bridge code injected into web pages, for the default [`IpcConfig`].
+///
+/// `bridge.js` is a function expression taking the configuration; this calls
+/// it with the defaults. This bridge supports two modes:
 /// 1. WebKit native message handler (preferred, uses webkit.messageHandlers.wpe)
 /// 2. Fetch-based fallback (uses wpe://message endpoint)
-pub const JS_BRIDGE: &str = r#"
-(function() {
-    'use strict';
-
-    // Check if WebKit message handler is available
-    const hasWebKitHandler = typeof webkit !== 'undefined' &&
-                             webkit.messageHandlers &&
-                             webkit.messageHandlers.wpe;
-
-    // Receive message from Rust
-    window.__wpe_receive = function(msg) {
-        window.dispatchEvent(new CustomEvent('wpe:message', { detail: msg }));
-    };
+///
+/// [`IpcConfig::bridge_script`] generates it for other configurations.
+pub const JS_BRIDGE: &str = concat!(
+    include_str!("bridge.js"),
+    r#"({"callTimeout":30000,"globalName":"wpe","handlerName":"wpe"});"#,
+    "\n"
+);
+
+/// Script calling `hook` (e.g. `__receive`) of the bridge installed as `global`.
+pub(crate) fn hook_script(global: &str, hook: &str, argument: &str) -> String {
+    format!(
+        "window[{}].{hook}({argument})",
+        serde_json::Value::from(global)
+    )
+}
+
+/// Configuration of the JavaScript IPC bridge.
+///
+/// Renaming the handler and global lets the bridge coexist with other
+/// content using the default names. The hooks Rust calls into (`__receive`,
+/// `__publish`, `__receiveBytes`) are hidden properties of the global, so
+/// bridges installed under different globals don't interfere, and each
+/// announces itself with a `<global>:ready` event on `window`.
+///
+/// ```rust,ignore
+/// let settings = WebViewSettings::new().with_ipc(
+///     IpcConfig::new()
+///         .with_handler_name("editor")
+///         .with_global_name("editor")
+///         .with_call_timeout(Some(Duration::from_secs(120))),
+/// );
+/// // JavaScript: await editor.call('export', doc, { timeout: 0 });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcConfig {
+    /// Script message handler the bridge posts to (`webkit.messageHandlers.<name>`)
+    pub handler_name: String,
+    /// Global the bridge API is installed as (`window.<name>`)
+    pub global_name: String,
+    /// Default `call()` timeout; `None` waits indefinitely
+    pub call_timeout: Option<Duration>,
+}
 
-    // Send message to Rust
-    window.__wpe_send = function(msg) {
-        if (hasWebKitHandler) {
-            // Use WebKit native message handler (preferred)
-            webkit.messageHandlers.wpe.postMessage(JSON.stringify(msg));
-        } else {
-            // Fallback to fetch-based approach
-            fetch('wpe://message', {
-                method: 'POST',
-                headers: { 'Content-Type': 'application/json' },
-                body: JSON.stringify(msg)
-            }).catch(() => {});
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self {
+            handler_name: "wpe".to_string(),
+            global_name: "wpe".to_string(),
+            call_timeout: Some(Duration::from_secs(30)),
         }
-    };
+    }
+}
 
-    // Public API
-    window.wpe = {
-        send(type, payload) {
-            window.__wpe_send({ type, payload });
-        },
+impl IpcConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        onMessage(callback) {
-            window.addEventListener('wpe:message', (e) => callback(e.detail));
-        },
+    #[must_use]
+    pub fn with_handler_name(mut self, name: impl Into<String>) -> Self {
+        self.handler_name = name.into();
+        self
+    }
 
-        // Promise-based request/response
-        async call(type, payload) {
-            return new Promise((resolve, reject) => {
-                const id = Math.random().toString(36).slice(2);
-                const handler = (e) => {
-                    if (e.detail && e.detail._responseId === id) {
-                        window.removeEventListener('wpe:message', handler);
-                        if (e.detail.error) {
-                            reject(new Error(e.detail.error));
-                        } else {
-                            resolve(e.detail.result);
-                        }
-                    }
-                };
-                window.addEventListener('wpe:message', handler);
-                window.__wpe_send({ type, payload, _requestId: id });
-
-                // Timeout after 30 seconds
-                setTimeout(() => {
-                    window.removeEventListener('wpe:message', handler);
-                    reject(new Error('Request timeout'));
-                }, 30000);
-            });
-        },
+    #[must_use]
+    pub fn with_global_name(mut self, name: impl Into<String>) -> Self {
+        self.global_name = name.into();
+        self
+    }
 
-        // Check if native handler is available
-        hasNativeHandler() {
-            return hasWebKitHandler;
-        }
-    };
+    /// Set the default `call()` timeout, or `None` to wait indefinitely.
+    ///
+    /// Individual calls can override it with `call(type, payload, { timeout })`,
+    /// in milliseconds.
+    #[must_use]
+    pub fn with_call_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
 
-    // Signal that bridge is ready
-    window.dispatchEvent(new CustomEvent('wpe:ready'));
-})();
-"#;
+    /// Generate the bridge script for this configuration.
+    #[must_use]
+    pub fn bridge_script(&self) -> String {
+        let config = serde_json::json!({
+            "handlerName": self.handler_name,
+            "globalName": self.global_name,
+            "callTimeout": self.call_timeout.map(|t| u64::try_from(t.as_millis()).unwrap_or(u64::MAX)),
+        });
+        format!("{}({config});\n", include_str!("bridge.js"))
+    }
+}
 
 /// A message from the frontend (JavaScript) to the backend (Rust).
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -90,8 +176,24 @@ pub struct FrontendMessage {
     pub message_type: String,
     #[serde(default)]
     pub payload: serde_json::Value,
+    /// ID of a request awaiting a response.
+    ///
+    /// The bridge builds it from a random per-frame nonce and a counter, so
+    /// one frame can't guess another's IDs to answer or cancel its calls.
     #[serde(rename = "_requestId", skip_serializing_if = "Option::is_none")]
     pub request_id: Option<String>,
+    /// Security origin of the sending page, e.g. `app://localhost`.
+    ///
+    /// Set from what WebKit reports, never from the message itself. Messages
+    /// sent through the `wpe://message` fetch fallback take it from the
+    /// request's `Origin` header. Messages posted to the script message
+    /// handler only have one when the bridge runs in an isolated script world
+    /// (`WebViewSettings::with_script_world`): WebKit doesn't say which frame
+    /// posted them, and only there is the top frame's bridge the sole sender.
+    /// Without an origin, commands restricted with
+    /// [`IpcRouter::allow_origins`](crate::IpcRouter::allow_origins) are refused.
+    #[serde(skip)]
+    pub origin: Option<String>,
 }
 
 /// A message from the backend (Rust) to the frontend (JavaScript).
@@ -107,6 +209,9 @@ pub struct BackendMessage {
     pub result: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Marks an intermediate progress report for the request in `response_id`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub progress: bool,
 }
 
 impl BackendMessage {
@@ -119,6 +224,7 @@ impl BackendMessage {
             response_id: None,
             result: None,
             error: None,
+            progress: false,
         }
     }
 
@@ -131,6 +237,7 @@ impl BackendMessage {
             response_id: Some(request_id),
             result: Some(result),
             error: None,
+            progress: false,
         }
     }
 
@@ -143,6 +250,20 @@ impl BackendMessage {
             response_id: Some(request_id),
             result: None,
             error: Some(error.into()),
+            progress: false,
+        }
+    }
+
+    /// Create a progress report for a request, passed to its `onProgress` callback.
+    #[must_use]
+    pub fn progress(request_id: String, payload: serde_json::Value) -> Self {
+        Self {
+            message_type: "progress".to_string(),
+            payload,
+            response_id: Some(request_id),
+            result: None,
+            error: None,
+            progress: true,
         }
     }
 }
@@ -168,6 +289,9 @@ impl IpcBridge {
     }
 
     /// Inject the IPC bridge into HTML content.
+    ///
+    /// Web views install the bridge in every page themselves; this is only
+    /// needed for HTML shown outside of this crate's web views.
     #[must_use]
     pub fn inject_bridge(html: &str) -> String {
         format!("{html}<script>{JS_BRIDGE}</script>")
@@ -175,10 +299,11 @@ impl IpcBridge {
 
     /// Poll for pending messages from the frontend.
     ///
+    /// Messages posted by the page since the last call are moved from the
+    /// web view's script message handler into the bridge and returned.
     /// This should be called in your event loop to receive messages.
-    pub fn poll(&mut self, _webview: &WebView) -> Vec<FrontendMessage> {
-        // TODO: Call window.__wpe_poll() and parse the result
-        // For now, drain the internal queue
+    pub fn poll(&mut self, webview: &WebView) -> Vec<FrontendMessage> {
+        self.pending_messages.extend(webview.receive_messages());
         self.pending_messages.drain(..).collect()
     }
 
@@ -188,7 +313,7 @@ impl IpcBridge {
     /// Returns an error if the message could not be serialized or sent.
     pub fn send(&self, webview: &WebView, message: &BackendMessage) -> Result<()> {
         let json = serde_json::to_string(message)?;
-        let script = format!("window.__wpe_receive({json})");
+        let script = hook_script(&webview.settings().ipc.global_name, "__receive", &json);
         webview.evaluate_script(&script)
     }
 
@@ -235,6 +360,277 @@ impl Default for IpcBridge {
     }
 }
 
+/// What the script message handler of a web view needs to queue messages.
+pub(crate) struct MessageHandlerState {
+    queue: MessageQueue,
+    web_view: *mut wpe_sys::WebKitWebView,
+    /// Whether the handler lives in an isolated world, where only the
+    /// bridge in the top frame can post to it
+    isolated: bool,
+}
+
+impl MessageHandlerState {
+    /// The origin to attribute a message posted to the handler to.
+    #[allow(unsafe_code)]
+    fn sender_origin(&self) -> Option<String> {
+        if !self.isolated || self.web_view.is_null() {
+            return None;
+        }
+        // SAFETY: web_view outlives the handler connected to its user content manager.
+        unsafe { policy::page_origin(self.web_view) }
+    }
+}
+
+/// Signal handler for script-message-received.
+/// This is called when JavaScript sends a message via webkit.messageHandlers.<name>.postMessage().
+#[allow(unsafe_code)]
+pub(crate) unsafe extern "C" fn on_script_message(
+    _manager: *mut wpe_sys::WebKitUserContentManager,
+    js_result: *mut wpe_sys::JSCValue,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() || js_result.is_null() {
+        tracing::warn!("on_script_message: null pointer");
+        return;
+    }
+
+    let state = &*(user_data as *const MessageHandlerState);
+
+    // Convert JSCValue to string
+    let c_str = wpe_sys::jsc_value_to_string(js_result);
+    if c_str.is_null() {
+        tracing::warn!("Failed to convert JSCValue to string");
+        return;
+    }
+
+    let rust_str = std::ffi::CStr::from_ptr(c_str).to_string_lossy();
+    tracing::debug!("Received message from JS: {}", rust_str);
+
+    // Parse the JSON message
+    match serde_json::from_str::<FrontendMessage>(&rust_str) {
+        Ok(mut msg) => {
+            msg.origin = state.sender_origin();
+            state.queue.push(msg);
+        }
+        Err(e) => {
+            tracing::warn!("Failed to parse message from JS: {}", e);
+        }
+    }
+
+    // Free the C string
+    wpe_sys::g_free(c_str as *mut _);
+}
+
+/// Register the script message handler `name` on a user content manager.
+///
+/// Messages posted through `webkit.messageHandlers.<name>` are parsed and pushed
+/// onto `queue`. The handler is only exposed to scripts in `world` (the
+/// page's main world when `None`); only then are messages attributed to the
+/// origin of the page in `web_view`. The returned pointer keeps the queue
+/// alive for the signal handler and must be reclaimed with `Box::from_raw`
+/// once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be valid and `manager` must be its `WebKitUserContentManager`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect_message_handler(
+    web_view: *mut wpe_sys::WebKitWebView,
+    manager: *mut wpe_sys::WebKitUserContentManager,
+    world: Option<&str>,
+    name: &str,
+    queue: &MessageQueue,
+) -> *mut MessageHandlerState {
+    let state_ptr = Box::into_raw(Box::new(MessageHandlerState {
+        queue: Arc::clone(queue),
+        web_view,
+        isolated: world.is_some(),
+    }));
+
+    let (Ok(signal_name), Ok(handler_name)) = (
+        CString::new(format!("script-message-received::{name}")),
+        CString::new(name),
+    ) else {
+        tracing::error!("Invalid script message handler name: {:?}", name);
+        return state_ptr;
+    };
+
+    // Connect to the script-message-received signal BEFORE registering the handler
+    let signal_id = wpe_sys::g_signal_connect_data(
+        manager as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitUserContentManager,
+                *mut wpe_sys::JSCValue,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_script_message)),
+        state_ptr as *mut _,
+        None,
+        0, // G_CONNECT_DEFAULT
+    );
+
+    if signal_id == 0 {
+        tracing::warn!("Failed to connect script-message-received signal");
+    } else {
+        tracing::debug!("Connected script-message-received signal: {}", signal_id);
+    }
+
+    // Register the script message handler
+    let world_name = match script::world_name(world) {
+        Ok(name) => name,
+        Err(e) => {
+            tracing::error!("Invalid script world name: {}", e);
+            return state_ptr;
+        }
+    };
+    let registered = wpe_sys::webkit_user_content_manager_register_script_message_handler(
+        manager,
+        handler_name.as_ptr(),
+        world_name.as_ref().map_or(ptr::null(), |c| c.as_ptr()),
+    );
+
+    if registered == 0 {
+        tracing::warn!("Failed to register script message handler (may already exist)");
+    } else {
+        tracing::debug!(
+            "Registered '{}' script message handler in world {:?}",
+            name,
+            world
+        );
+    }
+
+    state_ptr
+}
+
+/// URI scheme used by the bridge's fetch-based fallback (`wpe://message`).
+pub(crate) const IPC_SCHEME: &str = "wpe";
+
+/// Message queues fed by the fetch fallback, keyed by web view address.
+static FETCH_ROUTES: Mutex<Vec<(usize, MessageQueue)>> = Mutex::new(Vec::new());
+
+static IPC_SCHEME_INIT: Once = Once::new();
+
+/// Register the `wpe` scheme, once per process.
+pub(crate) fn register_scheme() {
+    IPC_SCHEME_INIT.call_once(|| {
+        if let Err(e) = scheme::register(IPC_SCHEME, Box::new(on_fetch_message)) {
+            tracing::warn!("Failed to register IPC fetch scheme: {}", e);
+        }
+    });
+}
+
+/// Deliver messages posted to `wpe://message` by `web_view` into `queue`.
+///
+/// Registers the `wpe` scheme on first use. Call [`unroute_fetch_messages`]
+/// before the web view is released.
+pub(crate) fn route_fetch_messages(web_view: *mut wpe_sys::WebKitWebView, queue: &MessageQueue) {
+    register_scheme();
+
+    if let Ok(mut routes) = FETCH_ROUTES.lock() {
+        routes.push((web_view as usize, queue.clone()));
+    }
+}
+
+/// Stop delivering fetch fallback messages for `web_view`.
+pub(crate) fn unroute_fetch_messages(web_view: *mut wpe_sys::WebKitWebView) {
+    if let Ok(mut routes) = FETCH_ROUTES.lock() {
+        routes.retain(|(address, _)| *address != web_view as usize);
+    }
+}
+
+/// Scheme handler for `wpe://`: messages and binary data.
+///
+/// Only the page loaded in the web view may use the scheme. Requests from
+/// other origins, such as third-party iframes, are refused, and CORS headers
+/// name the page's origin instead of `*`.
+fn on_fetch_message(request: &SchemeRequest) -> SchemeResponse {
+    let Some(origin) = allowed_origin(request.header("Origin"), request.page_origin()) else {
+        tracing::warn!(
+            "Refusing {} from origin {:?}",
+            request.uri(),
+            request.header("Origin")
+        );
+        return SchemeResponse::empty(403);
+    };
+
+    let response = if binary::is_binary_uri(request.uri()) {
+        binary::on_binary_request(request)
+    } else {
+        match parse_fetch_message(request.uri(), request.method(), request.body()) {
+            Ok(Some(mut msg)) => {
+                // The browser sets Origin; don't trust what the page put in the body
+                msg.origin = Some(origin.to_string());
+                let address = request.web_view() as usize;
+                let queue = FETCH_ROUTES.lock().ok().and_then(|routes| {
+                    routes
+                        .iter()
+                        .find(|(a, _)| *a == address)
+                        .map(|(_, q)| q.clone())
+                });
+
+                match queue {
+                    Some(queue) => {
+                        queue.push(msg);
+                        SchemeResponse::empty(204)
+                    }
+                    None => {
+                        tracing::warn!("Fetch IPC message from unknown web view");
+                        SchemeResponse::not_found()
+                    }
+                }
+            }
+            Ok(None) => SchemeResponse::empty(204)
+                .with_header("Access-Control-Allow-Methods", "POST")
+                .with_header("Access-Control-Allow-Headers", "Content-Type"),
+            Err(status) => SchemeResponse::empty(status),
+        }
+    };
+
+    response
+        .with_header("Access-Control-Allow-Origin", origin)
+        .with_header("Vary", "Origin")
+}
+
+/// The `Origin` header of a `wpe://` request, if it is the origin of the
+/// page loaded in the requesting web view.
+fn allowed_origin<'a>(origin: Option<&'a str>, page_origin: Option<&str>) -> Option<&'a str> {
+    let origin = origin?;
+    let page_origin = page_origin?;
+    (policy::origin(origin).as_deref() == Some(page_origin)).then_some(origin)
+}
+
+/// Parse a fetch fallback request into a message.
+///
+/// Returns `Ok(None)` for CORS preflight requests and an HTTP status code for
+/// invalid requests.
+fn parse_fetch_message(
+    uri: &str,
+    method: &str,
+    body: &[u8],
+) -> std::result::Result<Option<FrontendMessage>, u16> {
+    let target = uri
+        .strip_prefix(IPC_SCHEME)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .map(|rest| rest.trim_end_matches('/'));
+    if target != Some("message") {
+        return Err(404);
+    }
+
+    match method {
+        "OPTIONS" => Ok(None),
+        "POST" => match serde_json::from_slice(body) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(e) => {
+                tracing::warn!("Failed to parse fetch message from JS: {}", e);
+                Err(400)
+            }
+        },
+        _ => Err(405),
+    }
+}
+
 /// Typed message handler for convenient message routing.
 pub trait MessageHandler {
     /// The message type this handler responds to.
@@ -257,6 +653,46 @@ mod tests {
         assert!(msg.request_id.is_none());
     }
 
+    #[test]
+    fn test_frontend_message_ignores_payload_origin() {
+        let json = r#"{"type":"ping","_origin":"app://localhost"}"#;
+        let msg: FrontendMessage = serde_json::from_str(json).unwrap();
+        assert!(msg.origin.is_none());
+
+        let mut msg: FrontendMessage = serde_json::from_str(r#"{"type":"ping"}"#).unwrap();
+        msg.origin = Some("app://localhost".to_string());
+        assert!(!serde_json::to_string(&msg).unwrap().contains("_origin"));
+    }
+
+    #[test]
+    fn test_main_world_messages_have_no_origin() {
+        let navigation = NavigationCounter::default();
+        let state = MessageHandlerState {
+            queue: MessageQueueInner::new(&navigation),
+            web_view: ptr::null_mut(),
+            isolated: false,
+        };
+        assert!(state.sender_origin().is_none());
+    }
+
+    #[test]
+    fn test_message_queue_drops_previous_pages() {
+        let navigation = NavigationCounter::default();
+        let queue = MessageQueueInner::new(&navigation);
+        let message = |t: &str| -> FrontendMessage {
+            serde_json::from_value(serde_json::json!({"type": t, "_requestId": "1"})).unwrap()
+        };
+
+        queue.push(message("old"));
+        navigation.fetch_add(1, Ordering::AcqRel);
+        queue.push(message("new"));
+
+        let messages = queue.take();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message_type, "new");
+        assert!(queue.take().is_empty());
+    }
+
     #[test]
     fn test_frontend_message_with_request_id() {
         let json = r#"{"type":"call","payload":{},"_requestId":"abc123"}"#;
@@ -307,7 +743,40 @@ mod tests {
         let result = IpcBridge::inject_bridge(html);
         assert!(result.contains("<html><body>Hello</body></html>"));
         assert!(result.contains("<script>"));
-        assert!(result.contains("window.wpe"));
+        assert!(result.contains(r#""globalName":"wpe""#));
+    }
+
+    #[test]
+    fn test_parse_fetch_message() {
+        let body = br#"{"type":"ping","payload":1,"_requestId":"r1"}"#;
+        let msg = parse_fetch_message("wpe://message", "POST", body)
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.message_type, "ping");
+        assert_eq!(msg.request_id, Some("r1".to_string()));
+
+        assert!(matches!(
+            parse_fetch_message("wpe://message/", "OPTIONS", b""),
+            Ok(None)
+        ));
+        let status = |uri, method, body| parse_fetch_message(uri, method, body).err();
+        assert_eq!(status("wpe://message", "GET", b""), Some(405));
+        assert_eq!(status("wpe://other", "POST", body), Some(404));
+        assert_eq!(status("wpe://message", "POST", b"{"), Some(400));
+    }
+
+    #[test]
+    fn test_allowed_origin() {
+        let page = Some("app://localhost");
+        assert_eq!(
+            allowed_origin(Some("app://localhost"), page),
+            Some("app://localhost")
+        );
+        // A third-party iframe, an opaque origin and a missing header
+        assert_eq!(allowed_origin(Some("https://ads.example.net"), page), None);
+        assert_eq!(allowed_origin(Some("null"), page), None);
+        assert_eq!(allowed_origin(None, page), None);
+        assert_eq!(allowed_origin(Some("app://localhost"), None), None);
     }
 
     #[test]
@@ -316,4 +785,30 @@ mod tests {
         // Just verify it creates without panicking
         assert_eq!(IpcBridge::js_bridge_code().len() > 0, true);
     }
+
+    #[test]
+    fn test_ipc_config_bridge_script() {
+        assert_eq!(IpcConfig::default().bridge_script(), JS_BRIDGE);
+
+        let script = IpcConfig::new()
+            .with_handler_name("editor")
+            .with_global_name("editorBridge")
+            .with_call_timeout(None)
+            .bridge_script();
+        assert_eq!(
+            script.strip_prefix(include_str!("bridge.js")),
+            Some(
+                r#"({"callTimeout":null,"globalName":"editorBridge","handlerName":"editor"});
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_hook_script() {
+        assert_eq!(
+            hook_script("editor-bridge", "__receive", "{}"),
+            r#"window["editor-bridge"].__receive({})"#
+        );
+    }
 }
@@ -6,6 +6,8 @@
 //! ## Features
 //!
 //! - `winit` (default): Integration with the winit windowing library
+//! - `png`: PNG encoding for [`Snapshot`]
+//! - `pdf`: Raster PDF captures via `WebView::capture_to_pdf`
 //!
 //! ## Example
 //!
@@ -37,10 +39,27 @@
 //! apt install libwpe-1.0-dev libwpewebkit-1.0-dev libwpebackend-fdo-1.0-dev
 //! ```
 
+pub mod assets;
+pub mod binary;
+pub mod content_filter;
+pub mod download;
 pub mod error;
+pub mod filter;
 pub mod input;
 pub mod ipc;
 pub mod native;
+pub mod policy;
+pub mod popup;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod router;
+pub mod scheme;
+pub mod script;
+pub mod settings;
+pub mod snapshot;
+pub mod subscription;
+mod typescript;
+pub mod user_content;
 pub mod webview;
 
 #[cfg(feature = "x11")]
@@ -51,9 +70,25 @@ pub mod renderer;
 #[cfg(feature = "winit")]
 pub mod window;
 
+pub use assets::StaticAssets;
+pub use binary::BinaryMessage;
+pub use content_filter::{ContentFilter, ContentFilterStore};
+pub use download::{Download, DownloadDestination, DownloadEvent, DownloadStatus};
 pub use error::{Error, Result};
-pub use ipc::{BackendMessage, FrontendMessage, IpcBridge};
-pub use native::{LoadState, NativeWindow, NavigationEvent};
+pub use filter::{DocumentFilter, DocumentRequest, DocumentRewrite, FilterAction};
+pub use ipc::{BackendMessage, FrontendMessage, IpcBridge, IpcConfig};
+pub use native::{CloseReason, LoadFuture, LoadState, NativeWindow, NavigationEvent};
+#[cfg(feature = "pdf")]
+pub use pdf::{PageMargins, PageSize, PdfOptions};
+pub use policy::{AllowedOrigins, NavigationPolicy, NavigationRequest, NavigationType, PolicyDecision};
+pub use popup::{NewWindow, NewWindowRequest, WindowFeatures, WindowGeometry};
+pub use router::{CallContext, CancellationToken, IpcRouter};
+pub use scheme::{register_uri_scheme, SchemeHandler, SchemeRequest, SchemeResponse};
+pub use script::ScriptFuture;
+pub use settings::{AutoplayPolicy, HardwareAccelerationPolicy, RuntimeSettings};
+pub use snapshot::{Snapshot, SnapshotRegion};
+pub use subscription::Subscription;
+pub use user_content::{InjectedFrames, InjectionTime, StyleLevel, UserScript, UserStyleSheet};
 
 #[cfg(feature = "x11")]
 pub use x11_window::X11Window;
@@ -7,12 +7,35 @@
 //! When the `x11` feature is enabled, the window will automatically fall back
 //! to X11 if Wayland is not available.
 
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::ffi::CString;
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
 use std::ptr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-
-use crate::ipc::{BackendMessage, FrontendMessage, IpcBridge};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::assets;
+use crate::binary::{self, BinaryChannel, BinaryMessage};
+use crate::content_filter::ContentFilter;
+use crate::download::{self, Download, DownloadDestination, DownloadEvent, DownloadState};
+use crate::filter::{self, FilterState, DocumentFilter};
+use crate::ipc::{self, BackendMessage, FrontendMessage, IpcBridge, MessageHandlerState, MessageQueue, MessageQueueInner};
+use crate::policy::{self, NavigationPolicy, PolicySlot, PolicySlotInner};
+use crate::popup::{self, NewWindow, NewWindowRequest, PopupState, WindowFeatures};
+use crate::router::IpcRouter;
+use crate::script::{self, ScriptFuture};
+use crate::settings::RuntimeSettings;
+use crate::subscription::{Subscription, SubscriptionHub};
+use crate::user_content::{self, UserContent, UserScript, UserStyleSheet};
+use crate::webview;
 use crate::{Error, Result, WebViewSettings};
 
 /// Navigation load state.
@@ -53,17 +76,20 @@ pub enum NavigationEvent {
     UrlChanged(String),
     /// Load progress updated (0.0 to 1.0).
     ProgressChanged(f64),
+    /// A window opened by a page is ready to be shown, with the features it requested.
+    ReadyToShow(WindowFeatures),
+    /// The page called `window.close()`.
+    CloseRequested,
 }
 
-/// Shared message queue for receiving messages from JavaScript.
-type MessageQueue = Arc<Mutex<VecDeque<FrontendMessage>>>;
-
 /// Shared event queue for navigation events.
-type EventQueue = Arc<Mutex<VecDeque<NavigationEvent>>>;
+pub(crate) type EventQueue = Arc<Mutex<VecDeque<NavigationEvent>>>;
 
 /// The inner type that Arc::into_raw returns a pointer to.
-type MessageQueueInner = Mutex<VecDeque<FrontendMessage>>;
-type EventQueueInner = Mutex<VecDeque<NavigationEvent>>;
+pub(crate) type EventQueueInner = Mutex<VecDeque<NavigationEvent>>;
+
+/// Shared count of committed navigations, used to cancel in-flight IPC calls.
+pub(crate) type NavigationCounter = Arc<AtomicU64>;
 
 /// A native WPE window that uses WPE's built-in window management.
 pub struct NativeWindow {
@@ -74,87 +100,121 @@ pub struct NativeWindow {
     /// The WebKit web view
     web_view: *mut wpe_sys::WebKitWebView,
     /// User content manager for script message handling
-    #[allow(dead_code)]
     user_content_manager: *mut wpe_sys::WebKitUserContentManager,
     /// Settings used to create this view
-    #[allow(dead_code)]
     settings: WebViewSettings,
-    /// Whether the window should close
-    should_close: bool,
+    /// Close flag, veto hook and running main loop (shared with the close signal handlers)
+    close_state: Rc<CloseState>,
+    /// Raw pointer to the close state (for signal handler cleanup)
+    close_state_ptr: *const CloseState,
     /// IPC bridge for JavaScript communication
     ipc: IpcBridge,
     /// Message queue for incoming messages from JavaScript
     message_queue: MessageQueue,
     /// Event queue for navigation events
     event_queue: EventQueue,
-    /// Raw pointer to message queue (for signal handler cleanup)
-    message_queue_ptr: *const MessageQueueInner,
+    /// Raw pointer to the script message handler state (for signal handler cleanup)
+    message_handler_ptr: *mut MessageHandlerState,
+    /// Binary messages from JavaScript and data waiting to be fetched by it
+    binary: Arc<BinaryChannel>,
     /// Raw pointer to event queue (for signal handler cleanup)
     event_queue_ptr: *const EventQueueInner,
+    /// Count of committed navigations (for cancelling async IPC calls)
+    navigation_count: NavigationCounter,
+    /// Raw pointer to the navigation counter (for signal handler cleanup)
+    navigation_count_ptr: *const AtomicU64,
+    /// Topics the page subscribed to and values waiting to be published
+    subscriptions: Rc<SubscriptionHub>,
+    /// Document filter consulted for navigations
+    document_filter: Rc<FilterState>,
+    /// Raw pointer to the filter state (for signal handler cleanup)
+    document_filter_ptr: *const FilterState,
+    /// Navigation policy consulted by the decide-policy handler
+    navigation_policy: PolicySlot,
+    /// Raw pointer to the navigation policy (for signal handler cleanup)
+    navigation_policy_ptr: *const PolicySlotInner,
+    /// New-window handler and the windows it created
+    popups: Rc<PopupState>,
+    /// Raw pointer to the popup state (for signal handler cleanup)
+    popups_ptr: *const PopupState,
+    /// Tracked downloads and their queued events
+    downloads: Rc<DownloadState>,
+    /// Raw pointer to the download state (for signal handler cleanup)
+    downloads_ptr: *const DownloadState,
+    /// Signal handler ID for the network session's download-started
+    download_signal_id: u64,
+    /// User scripts and style sheets added by the app
+    user_content: UserContent,
 }
 
-/// Signal handler for script-message-received.
-/// This is called when JavaScript sends a message via webkit.messageHandlers.wpe.postMessage().
+/// Signal handler for load-changed.
 #[allow(unsafe_code)]
-unsafe extern "C" fn on_script_message(
-    _manager: *mut wpe_sys::WebKitUserContentManager,
-    js_result: *mut wpe_sys::JSCValue,
+unsafe extern "C" fn on_load_changed(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    load_event: wpe_sys::WebKitLoadEvent,
     user_data: *mut std::ffi::c_void,
 ) {
-    if user_data.is_null() || js_result.is_null() {
-        tracing::warn!("on_script_message: null pointer");
+    if user_data.is_null() {
         return;
     }
 
-    // Get the message queue from user_data
     // Note: Arc::into_raw returns pointer to inner value (Mutex), not the Arc
-    let queue = &*(user_data as *const MessageQueueInner);
-
-    // Convert JSCValue to string
-    let c_str = wpe_sys::jsc_value_to_string(js_result);
-    if c_str.is_null() {
-        tracing::warn!("Failed to convert JSCValue to string");
-        return;
-    }
-
-    let rust_str = std::ffi::CStr::from_ptr(c_str).to_string_lossy();
-    tracing::debug!("Received message from JS: {}", rust_str);
+    let queue = &*(user_data as *const EventQueueInner);
+    let state = LoadState::from_webkit(load_event);
+    tracing::debug!("Load changed: {:?}", state);
 
-    // Parse the JSON message
-    match serde_json::from_str::<FrontendMessage>(&rust_str) {
-        Ok(msg) => {
-            if let Ok(mut q) = queue.lock() {
-                q.push_back(msg);
-            }
-        }
-        Err(e) => {
-            tracing::warn!("Failed to parse message from JS: {}", e);
-        }
+    if let Ok(mut q) = queue.lock() {
+        q.push_back(NavigationEvent::LoadChanged(state));
     }
-
-    // Free the C string
-    wpe_sys::g_free(c_str as *mut _);
 }
 
-/// Signal handler for load-changed.
+/// Signal handler for load-changed that counts committed navigations.
 #[allow(unsafe_code)]
-unsafe extern "C" fn on_load_changed(
+unsafe extern "C" fn on_navigation_committed(
     _web_view: *mut wpe_sys::WebKitWebView,
     load_event: wpe_sys::WebKitLoadEvent,
     user_data: *mut std::ffi::c_void,
 ) {
-    if user_data.is_null() {
+    if user_data.is_null() || load_event != wpe_sys::WebKitLoadEvent_WEBKIT_LOAD_COMMITTED {
         return;
     }
 
-    // Note: Arc::into_raw returns pointer to inner value (Mutex), not the Arc
-    let queue = &*(user_data as *const EventQueueInner);
-    let state = LoadState::from_webkit(load_event);
-    tracing::debug!("Load changed: {:?}", state);
+    let counter = &*(user_data as *const AtomicU64);
+    counter.fetch_add(1, Ordering::AcqRel);
+}
 
-    if let Ok(mut q) = queue.lock() {
-        q.push_back(NavigationEvent::LoadChanged(state));
-    }
+/// Count committed navigations of `web_view` in `counter`.
+///
+/// The returned pointer keeps the counter alive for the signal handler and
+/// must be reclaimed with `Arc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect_navigation_counter(
+    web_view: *mut wpe_sys::WebKitWebView,
+    counter: &NavigationCounter,
+) -> *const AtomicU64 {
+    let counter_ptr = Arc::into_raw(counter.clone());
+
+    let signal_name = CString::new("load-changed").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                wpe_sys::WebKitLoadEvent,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_navigation_committed)),
+        counter_ptr as *mut _,
+        None,
+        0,
+    );
+
+    counter_ptr
 }
 
 /// Signal handler for notify::title.
@@ -205,6 +265,137 @@ unsafe extern "C" fn on_notify_uri(
     }
 }
 
+/// Signal handler for ready-to-show.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_ready_to_show(
+    web_view: *mut wpe_sys::WebKitWebView,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() || web_view.is_null() {
+        return;
+    }
+
+    let queue = &*(user_data as *const EventQueueInner);
+    let features = WindowFeatures::from_web_view(web_view);
+    tracing::debug!("Ready to show: {:?}", features);
+
+    if let Ok(mut q) = queue.lock() {
+        q.push_back(NavigationEvent::ReadyToShow(features));
+    }
+}
+
+/// Signal handler for close that queues [`NavigationEvent::CloseRequested`].
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_close_event(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let queue = &*(user_data as *const EventQueueInner);
+    tracing::debug!("Page requested close");
+
+    if let Ok(mut q) = queue.lock() {
+        q.push_back(NavigationEvent::CloseRequested);
+    }
+}
+
+/// Why a window was asked to close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The compositor closed the window (e.g. its close button).
+    Compositor,
+    /// The page called `window.close()`.
+    Script,
+}
+
+/// Handler that returns whether a close request may proceed.
+type CloseRequestedHandler = Box<dyn Fn(CloseReason) -> bool>;
+
+/// Close state of a native window, shared with its close signal handlers.
+#[derive(Default)]
+pub(crate) struct CloseState {
+    should_close: Cell<bool>,
+    handler: RefCell<Option<CloseRequestedHandler>>,
+    /// The loop driven by `NativeWindow::run`, if it is running
+    main_loop: Cell<*mut wpe_sys::GMainLoop>,
+    /// Where `window.close()` is reported as [`NavigationEvent::CloseRequested`]
+    events: Option<EventQueue>,
+}
+
+impl CloseState {
+    fn new(events: &EventQueue) -> Self {
+        Self {
+            events: Some(Arc::clone(events)),
+            ..Self::default()
+        }
+    }
+
+    /// Ask the app whether to close, and close if it agrees.
+    fn request(&self, reason: CloseReason) {
+        let allowed = match self.handler.try_borrow().as_deref() {
+            Ok(Some(handler)) => handler(reason),
+            _ => true,
+        };
+
+        if allowed {
+            tracing::debug!("Closing window ({:?})", reason);
+            self.close();
+        } else {
+            tracing::debug!("Close request vetoed ({:?})", reason);
+        }
+    }
+
+    /// Close unconditionally, stopping the running main loop.
+    #[allow(unsafe_code)]
+    fn close(&self) {
+        self.should_close.set(true);
+
+        let main_loop = self.main_loop.get();
+        if !main_loop.is_null() {
+            // SAFETY: main_loop is set only while NativeWindow::run owns a valid loop.
+            unsafe {
+                wpe_sys::g_main_loop_quit(main_loop);
+            }
+        }
+    }
+}
+
+/// Signal handler for the view's closed signal (compositor close).
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_view_closed(
+    _view: *mut wpe_sys::WPEView,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let state = &*(user_data as *const CloseState);
+    state.request(CloseReason::Compositor);
+}
+
+/// Signal handler for close (`window.close()` from the page).
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_script_close(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let state = &*(user_data as *const CloseState);
+    tracing::debug!("Page requested close");
+
+    if let Some(Ok(mut q)) = state.events.as_ref().map(|events| events.lock()) {
+        q.push_back(NavigationEvent::CloseRequested);
+    }
+    state.request(CloseReason::Script);
+}
+
 /// Signal handler for notify::estimated-load-progress.
 #[allow(unsafe_code)]
 unsafe extern "C" fn on_notify_progress(
@@ -224,16 +415,398 @@ unsafe extern "C" fn on_notify_progress(
     }
 }
 
+/// Connect the load, title, URI, progress and ready-to-show signals of
+/// `web_view` to `queue`.
+///
+/// The returned pointer keeps the queue alive for the signal handlers and
+/// must be reclaimed with `Arc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect_navigation_events(
+    web_view: *mut wpe_sys::WebKitWebView,
+    queue: &EventQueue,
+) -> *const EventQueueInner {
+    let queue_ptr = Arc::into_raw(queue.clone());
+
+    // load-changed signal
+    let signal_name = CString::new("load-changed").expect("static string has no NUL bytes");
+    let signal_id = wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                wpe_sys::WebKitLoadEvent,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_load_changed)),
+        queue_ptr as *mut _,
+        None,
+        0,
+    );
+    if signal_id > 0 {
+        tracing::debug!("Connected load-changed signal: {}", signal_id);
+    }
+
+    let signal_name = CString::new("ready-to-show").expect("static string has no NUL bytes");
+    let signal_id = wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<WindowHandler, unsafe extern "C" fn()>(on_ready_to_show)),
+        queue_ptr as *mut _,
+        None,
+        0,
+    );
+    if signal_id > 0 {
+        tracing::debug!("Connected ready-to-show signal: {}", signal_id);
+    }
+
+    // notify::* signals share a handler signature
+    let notify_handlers: [(&str, NotifyHandler); 3] = [
+        ("notify::title", on_notify_title),
+        ("notify::uri", on_notify_uri),
+        ("notify::estimated-load-progress", on_notify_progress),
+    ];
+    for (name, handler) in notify_handlers {
+        let signal_name = CString::new(name).expect("static string has no NUL bytes");
+        let signal_id = wpe_sys::g_signal_connect_data(
+            web_view as *mut _,
+            signal_name.as_ptr(),
+            Some(std::mem::transmute::<NotifyHandler, unsafe extern "C" fn()>(handler)),
+            queue_ptr as *mut _,
+            None,
+            0,
+        );
+        if signal_id > 0 {
+            tracing::debug!("Connected {} signal: {}", name, signal_id);
+        }
+    }
+
+    queue_ptr
+}
+
+/// Report `window.close()` in `web_view` as [`NavigationEvent::CloseRequested`].
+///
+/// For views without a [`CloseState`]; native windows report it through
+/// their close handler instead. `queue_ptr` must come from
+/// [`connect_navigation_events`] for the same `web_view`.
+///
+/// # Safety
+/// `web_view` must be valid and `queue_ptr` must outlive it.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect_close_event(
+    web_view: *mut wpe_sys::WebKitWebView,
+    queue_ptr: *const EventQueueInner,
+) {
+    let signal_name = CString::new("close").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<WindowHandler, unsafe extern "C" fn()>(on_close_event)),
+        queue_ptr as *mut _,
+        None,
+        0,
+    );
+}
+
+/// Signature of the `ready-to-show` and `close` signal handlers.
+type WindowHandler = unsafe extern "C" fn(*mut wpe_sys::WebKitWebView, *mut std::ffi::c_void);
+
+/// Signature of the `notify::*` signal handlers.
+type NotifyHandler = unsafe extern "C" fn(
+    *mut wpe_sys::WebKitWebView,
+    *mut std::ffi::c_void,
+    *mut std::ffi::c_void,
+);
+
+/// Route compositor closes of `view` and `window.close()` in `web_view` to `state`.
+///
+/// The returned pointer keeps the state alive for the signal handlers and
+/// must be reclaimed with `Rc::from_raw` once both objects have been released.
+///
+/// # Safety
+/// `web_view` and `view` must be valid, and `view` must belong to `web_view`.
+#[allow(unsafe_code)]
+unsafe fn connect_close_requests(
+    web_view: *mut wpe_sys::WebKitWebView,
+    view: *mut wpe_sys::WPEView,
+    state: &Rc<CloseState>,
+) -> *const CloseState {
+    let state_ptr = Rc::into_raw(Rc::clone(state));
+
+    let signal_name = CString::new("closed").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(*mut wpe_sys::WPEView, *mut std::ffi::c_void),
+            unsafe extern "C" fn(),
+        >(on_view_closed)),
+        state_ptr as *mut _,
+        None,
+        0,
+    );
+
+    let signal_name = CString::new("close").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<WindowHandler, unsafe extern "C" fn()>(on_script_close)),
+        state_ptr as *mut _,
+        None,
+        0,
+    );
+
+    state_ptr
+}
+
+/// Drain all pending events from `queue`.
+pub(crate) fn drain_events(queue: &EventQueue) -> Vec<NavigationEvent> {
+    match queue.lock() {
+        Ok(mut queue) => queue.drain(..).collect(),
+        Err(e) => {
+            tracing::warn!("Failed to lock event queue: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Tracks whether the most recent load has finished.
+///
+/// A load is pending from the moment it is requested from Rust (or WebKit
+/// reports it started) until WebKit reports it finished or failed.
+#[derive(Debug, Default)]
+pub(crate) struct LoadTracker {
+    pending: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl LoadTracker {
+    /// Mark a load as requested.
+    pub(crate) fn start(&self) {
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Whether a load is still in progress.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    fn finish(&self) {
+        self.pending.store(false, Ordering::Release);
+        if let Ok(mut wakers) = self.wakers.lock() {
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        if let Ok(mut wakers) = self.wakers.lock() {
+            if !wakers.iter().any(|w| w.will_wake(waker)) {
+                wakers.push(waker.clone());
+            }
+        }
+    }
+}
+
+/// Signal handler for load-changed that updates a [`LoadTracker`].
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_load_tracked(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    load_event: wpe_sys::WebKitLoadEvent,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let tracker = &*(user_data as *const LoadTracker);
+    match load_event {
+        wpe_sys::WebKitLoadEvent_WEBKIT_LOAD_STARTED => tracker.start(),
+        wpe_sys::WebKitLoadEvent_WEBKIT_LOAD_FINISHED => tracker.finish(),
+        _ => {}
+    }
+}
+
+/// Track the load state of `web_view` in `tracker`.
+///
+/// The returned pointer keeps the tracker alive for the signal handler and
+/// must be reclaimed with `Arc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect_load_tracker(
+    web_view: *mut wpe_sys::WebKitWebView,
+    tracker: &Arc<LoadTracker>,
+) -> *const LoadTracker {
+    let tracker_ptr = Arc::into_raw(tracker.clone());
+
+    let signal_name = CString::new("load-changed").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                wpe_sys::WebKitLoadEvent,
+                *mut std::ffi::c_void,
+            ),
+            unsafe extern "C" fn(),
+        >(on_load_tracked)),
+        tracker_ptr as *mut _,
+        None,
+        0,
+    );
+
+    tracker_ptr
+}
+
+/// A future that resolves once the current page load finishes.
+///
+/// Returned by [`crate::WebView::wait_for_load_async`]. Like
+/// [`ScriptFuture`], it only makes progress while the GLib main context is
+/// being iterated, e.g. by calling [`crate::WebView::spin`].
+#[must_use = "futures do nothing unless polled"]
+pub struct LoadFuture {
+    tracker: Arc<LoadTracker>,
+    deadline: Instant,
+    timer: Option<DeadlineTimer>,
+}
+
+impl LoadFuture {
+    pub(crate) fn new(tracker: Arc<LoadTracker>, timeout: Duration) -> Self {
+        Self {
+            tracker,
+            deadline: Instant::now() + timeout,
+            timer: None,
+        }
+    }
+}
+
+impl Future for LoadFuture {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if !self.tracker.is_pending() {
+            return Poll::Ready(Ok(()));
+        }
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Poll::Ready(Err(Error::LoadTimeout));
+        }
+
+        self.tracker.register(cx.waker());
+
+        // Wake once more at the deadline so the timeout fires without a load event
+        match &self.timer {
+            Some(timer) => timer.set_waker(cx.waker()),
+            None => {
+                let remaining = self.deadline - now;
+                self.timer = Some(DeadlineTimer::start(remaining, cx.waker()));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// A GLib timeout on the default main context that wakes a task once.
+struct DeadlineTimer {
+    source_id: u32,
+    state: Arc<DeadlineState>,
+}
+
+#[derive(Default)]
+struct DeadlineState {
+    waker: Mutex<Option<Waker>>,
+    fired: AtomicBool,
+}
+
+impl DeadlineTimer {
+    #[allow(unsafe_code)]
+    fn start(after: Duration, waker: &Waker) -> Self {
+        let state = Arc::new(DeadlineState::default());
+        if let Ok(mut slot) = state.waker.lock() {
+            *slot = Some(waker.clone());
+        }
+
+        // Round up so the timer never fires before the deadline
+        let interval = u32::try_from(after.as_micros().div_ceil(1000)).unwrap_or(u32::MAX);
+
+        // SAFETY: The Arc pointer is reclaimed by on_deadline_destroy when the
+        // source is removed, either after firing or in Drop.
+        let source_id = unsafe {
+            wpe_sys::g_timeout_add_full(
+                0, // G_PRIORITY_DEFAULT
+                interval,
+                Some(on_deadline),
+                Arc::into_raw(Arc::clone(&state)) as *mut _,
+                Some(on_deadline_destroy),
+            )
+        };
+
+        Self { source_id, state }
+    }
+
+    fn set_waker(&self, waker: &Waker) {
+        if let Ok(mut slot) = self.state.waker.lock() {
+            if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        }
+    }
+}
+
+impl Drop for DeadlineTimer {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        if !self.state.fired.load(Ordering::Acquire) {
+            // SAFETY: The source id came from g_timeout_add_full. If it fires
+            // on another thread after the check, GLib only logs a warning.
+            unsafe {
+                wpe_sys::g_source_remove(self.source_id);
+            }
+        }
+    }
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_deadline(user_data: *mut std::ffi::c_void) -> i32 {
+    let state = &*(user_data as *const DeadlineState);
+    state.fired.store(true, Ordering::Release);
+    let waker = state.waker.lock().ok().and_then(|mut slot| slot.take());
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+    0 // G_SOURCE_REMOVE
+}
+
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_deadline_destroy(user_data: *mut std::ffi::c_void) {
+    drop(Arc::from_raw(user_data as *const DeadlineState));
+}
+
 impl NativeWindow {
     /// Create a new native WPE window with the given settings.
     ///
     /// This will attempt to use Wayland first. If Wayland is not available
     /// and the `x11` feature is enabled, it will fall back to X11.
+    ///
+    /// The window starts loading the settings' URL, [`assets::ASSETS_URL`]
+    /// when assets are attached, or the settings' HTML.
     #[allow(unsafe_code)]
     pub fn new(settings: WebViewSettings) -> Result<Self> {
         // Try Wayland first
-        match Self::new_wayland(settings.clone()) {
-            Ok(window) => return Ok(window),
+        match Self::new_wayland(settings.clone(), ptr::null_mut()) {
+            Ok(mut window) => {
+                window.load_initial_content()?;
+                return Ok(window);
+            }
             Err(e) => {
                 tracing::warn!("Wayland display not available: {}", e);
             }
@@ -248,11 +821,26 @@ impl NativeWindow {
         Err(Error::NoDisplay)
     }
 
+    /// Create a native window sharing a web process with `related`.
+    pub(crate) fn new_related(
+        settings: WebViewSettings,
+        related: *mut wpe_sys::WebKitWebView,
+    ) -> Result<Self> {
+        Self::new_wayland(settings, related)
+    }
+
     /// Create a new native WPE window using Wayland.
     #[allow(unsafe_code)]
-    fn new_wayland(settings: WebViewSettings) -> Result<Self> {
+    fn new_wayland(settings: WebViewSettings, related: *mut wpe_sys::WebKitWebView) -> Result<Self> {
+        if settings.assets.is_some() {
+            assets::register_scheme()?;
+        }
+
+        // Count committed navigations so async IPC calls can be cancelled
+        // and messages from previous pages dropped
+        let navigation_count: NavigationCounter = Arc::new(AtomicU64::new(0));
         // Create the message queue for IPC
-        let message_queue: MessageQueue = Arc::new(Mutex::new(VecDeque::new()));
+        let message_queue: MessageQueue = MessageQueueInner::new(&navigation_count);
         // Create the event queue for navigation events
         let event_queue: EventQueue = Arc::new(Mutex::new(VecDeque::new()));
 
@@ -295,7 +883,7 @@ impl NativeWindow {
             }
 
             // Create a WebKitWebView (it creates its own WPEView)
-            let web_view = wpe_sys::webkit_web_view_new(ptr::null_mut());
+            let web_view = popup::new_web_view(related);
             if web_view.is_null() {
                 tracing::error!("Failed to create WebKitWebView");
                 return Err(Error::WebViewCreationFailed);
@@ -309,60 +897,54 @@ impl NativeWindow {
                 return Err(Error::WebViewCreationFailed);
             }
 
-            // Leak the Arc to keep it alive - we'll clean it up in Drop
-            // Note: clone() creates a new Arc pointing to the same data
-            let queue_ptr = Arc::into_raw(message_queue.clone());
-
-            // Connect to the script-message-received signal BEFORE registering the handler
-            let signal_name = CString::new("script-message-received::wpe")
-                .expect("static string has no NUL bytes");
-            let signal_id = wpe_sys::g_signal_connect_data(
-                user_content_manager as *mut _,
-                signal_name.as_ptr(),
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(
-                        *mut wpe_sys::WebKitUserContentManager,
-                        *mut wpe_sys::JSCValue,
-                        *mut std::ffi::c_void,
-                    ),
-                    unsafe extern "C" fn(),
-                >(on_script_message)),
-                queue_ptr as *mut _,
-                None,
-                0, // G_CONNECT_DEFAULT
-            );
-
-            if signal_id == 0 {
-                tracing::warn!("Failed to connect script-message-received signal");
-            } else {
-                tracing::debug!("Connected script-message-received signal: {}", signal_id);
+            // Get the WPE view from the WebKitWebView
+            let view = wpe_sys::webkit_web_view_get_wpe_view(web_view);
+            if view.is_null() {
+                wpe_sys::g_object_unref(web_view as *mut _);
+                tracing::error!("Failed to get WPE view from WebKitWebView");
+                return Err(Error::WebViewCreationFailed);
             }
 
-            // Register the script message handler
-            let handler_name = CString::new("wpe")
-                .expect("static string has no NUL bytes");
-            let registered = wpe_sys::webkit_user_content_manager_register_script_message_handler(
+            crate::settings::apply(web_view, &settings.runtime_settings());
+
+            // Leak the handler state to keep it alive - we'll clean it up in Drop
+            let world = settings.script_world.as_deref();
+            let message_handler_ptr = ipc::connect_message_handler(
+                web_view,
                 user_content_manager,
-                handler_name.as_ptr(),
-                ptr::null(), // default world
+                world,
+                &settings.ipc.handler_name,
+                &message_queue,
             );
+            if world.is_none() {
+                ipc::route_fetch_messages(web_view, &message_queue);
+            }
+            user_content::install_bridge(user_content_manager, world, &settings.ipc);
+            let binary = Arc::new(BinaryChannel::new(world, &settings.ipc.global_name));
+            binary::route(web_view, &binary);
 
-            if registered == 0 {
-                tracing::warn!("Failed to register script message handler (may already exist)");
+            if let Some(ref static_assets) = settings.assets {
+                assets::mount(web_view, static_assets);
             } else {
-                tracing::debug!("Registered 'wpe' script message handler");
+                assets::mount_related(web_view, related);
             }
 
-            // Get the WPE view from the WebKitWebView
-            let view = wpe_sys::webkit_web_view_get_wpe_view(web_view);
-            if view.is_null() {
-                wpe_sys::g_object_unref(web_view as *mut _);
-                tracing::error!("Failed to get WPE view from WebKitWebView");
-                return Err(Error::WebViewCreationFailed);
+            // Set initial size, asking the compositor to size the window to match
+            let toplevel = wpe_sys::wpe_view_get_toplevel(view);
+            if toplevel.is_null()
+                || wpe_sys::wpe_toplevel_resize(
+                    toplevel,
+                    settings.width as i32,
+                    settings.height as i32,
+                ) == 0
+            {
+                wpe_sys::wpe_view_resized(view, settings.width as i32, settings.height as i32);
             }
 
-            // Set initial size
-            wpe_sys::wpe_view_resized(view, 1280, 720);
+            // The compositor provides the scale unless the settings force one
+            if let Some(scale_factor) = settings.scale_factor {
+                webview::set_view_scale(view, scale_factor);
+            }
 
             // Make the view visible
             wpe_sys::wpe_view_set_visible(view, 1);
@@ -371,107 +953,44 @@ impl NativeWindow {
             wpe_sys::wpe_view_focus_in(view);
 
             // Set window title via toplevel
-            let toplevel = wpe_sys::wpe_view_get_toplevel(view);
             if !toplevel.is_null() {
-                let title = CString::new("WPE WebView")
-                    .expect("static string has no NUL bytes");
-                wpe_sys::wpe_toplevel_set_title(toplevel, title.as_ptr());
+                if let Ok(title) = CString::new(settings.title.as_str()) {
+                    wpe_sys::wpe_toplevel_set_title(toplevel, title.as_ptr());
+                }
             }
 
             // Connect navigation event signals
-            let event_queue_ptr = Arc::into_raw(event_queue.clone());
-
-            // load-changed signal
-            let signal_name = CString::new("load-changed")
-                .expect("static string has no NUL bytes");
-            let signal_id = wpe_sys::g_signal_connect_data(
-                web_view as *mut _,
-                signal_name.as_ptr(),
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(
-                        *mut wpe_sys::WebKitWebView,
-                        wpe_sys::WebKitLoadEvent,
-                        *mut std::ffi::c_void,
-                    ),
-                    unsafe extern "C" fn(),
-                >(on_load_changed)),
-                event_queue_ptr as *mut _,
-                None,
-                0,
-            );
-            if signal_id > 0 {
-                tracing::debug!("Connected load-changed signal: {}", signal_id);
-            }
-
-            // notify::title signal
-            let signal_name = CString::new("notify::title")
-                .expect("static string has no NUL bytes");
-            let signal_id = wpe_sys::g_signal_connect_data(
-                web_view as *mut _,
-                signal_name.as_ptr(),
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(
-                        *mut wpe_sys::WebKitWebView,
-                        *mut std::ffi::c_void,
-                        *mut std::ffi::c_void,
-                    ),
-                    unsafe extern "C" fn(),
-                >(on_notify_title)),
-                event_queue_ptr as *mut _,
-                None,
-                0,
-            );
-            if signal_id > 0 {
-                tracing::debug!("Connected notify::title signal: {}", signal_id);
-            }
-
-            // notify::uri signal
-            let signal_name = CString::new("notify::uri")
-                .expect("static string has no NUL bytes");
-            let signal_id = wpe_sys::g_signal_connect_data(
-                web_view as *mut _,
-                signal_name.as_ptr(),
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(
-                        *mut wpe_sys::WebKitWebView,
-                        *mut std::ffi::c_void,
-                        *mut std::ffi::c_void,
-                    ),
-                    unsafe extern "C" fn(),
-                >(on_notify_uri)),
-                event_queue_ptr as *mut _,
-                None,
-                0,
-            );
-            if signal_id > 0 {
-                tracing::debug!("Connected notify::uri signal: {}", signal_id);
-            }
-
-            // notify::estimated-load-progress signal
-            let signal_name = CString::new("notify::estimated-load-progress")
-                .expect("static string has no NUL bytes");
-            let signal_id = wpe_sys::g_signal_connect_data(
-                web_view as *mut _,
-                signal_name.as_ptr(),
-                Some(std::mem::transmute::<
-                    unsafe extern "C" fn(
-                        *mut wpe_sys::WebKitWebView,
-                        *mut std::ffi::c_void,
-                        *mut std::ffi::c_void,
-                    ),
-                    unsafe extern "C" fn(),
-                >(on_notify_progress)),
-                event_queue_ptr as *mut _,
-                None,
-                0,
-            );
-            if signal_id > 0 {
-                tracing::debug!("Connected notify::estimated-load-progress signal: {}", signal_id);
-            }
+            let event_queue_ptr = connect_navigation_events(web_view, &event_queue);
 
-            // Note: WPEToplevel doesn't have a close-request signal like GTK
-            // Close handling would need to be done via the compositor protocol
-            // or by checking if the toplevel is still valid
+            let navigation_count_ptr = connect_navigation_counter(web_view, &navigation_count);
+            let subscriptions = Rc::new(SubscriptionHub::new(
+                web_view,
+                settings.script_world.clone(),
+                &settings.ipc.global_name,
+                &navigation_count,
+            ));
+
+            // Filter requests before the navigation policy sees them
+            let document_filter = Rc::new(FilterState::default());
+            let document_filter_ptr = filter::connect(web_view, &document_filter);
+
+            // Consult the navigation policy, once one is set
+            let navigation_policy: PolicySlot = Rc::new(RefCell::new(None));
+            let navigation_policy_ptr = policy::connect(web_view, &navigation_policy);
+
+            // Hand new-window requests to the app
+            let popups = Rc::new(PopupState::default());
+            let popups_ptr = popup::connect(web_view, &popups);
+
+            // Track downloads started by the page or by download_uri()
+            let downloads = Rc::new(DownloadState::default());
+            let (downloads_ptr, download_signal_id) = download::connect(web_view, &downloads);
+
+            // The compositor's close button and window.close() from the page
+            // close the window unless the app vetoes it; window.close() is
+            // also queued as a navigation event
+            let close_state = Rc::new(CloseState::new(&event_queue));
+            let close_state_ptr = connect_close_requests(web_view, view, &close_state);
 
             tracing::info!("Created native WPE window with IPC and navigation events");
 
@@ -481,12 +1000,27 @@ impl NativeWindow {
                 web_view,
                 user_content_manager,
                 settings,
-                should_close: false,
+                close_state,
+                close_state_ptr,
                 ipc: IpcBridge::new(),
                 message_queue,
                 event_queue,
-                message_queue_ptr: queue_ptr,
+                message_handler_ptr,
+                binary,
                 event_queue_ptr,
+                navigation_count,
+                navigation_count_ptr,
+                subscriptions,
+                document_filter,
+                document_filter_ptr,
+                navigation_policy,
+                navigation_policy_ptr,
+                popups,
+                popups_ptr,
+                downloads,
+                downloads_ptr,
+                download_signal_id,
+                user_content: UserContent::new(user_content_manager),
             })
         }
     }
@@ -528,39 +1062,69 @@ impl NativeWindow {
         Ok(())
     }
 
-    /// Load HTML content with the IPC bridge automatically injected.
+    /// Load the page the settings start with, if any.
+    fn load_initial_content(&mut self) -> Result<()> {
+        if let Some(url) = self.settings.initial_url().map(str::to_string) {
+            self.load_url(&url)
+        } else if let Some(html) = self.settings.html.clone() {
+            self.load_html(&html, None)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Load HTML content with the IPC bridge available.
+    #[deprecated(note = "the IPC bridge is installed in every page; use `load_html`")]
     pub fn load_html_with_ipc(&mut self, html: &str, base_url: Option<&str>) -> Result<()> {
-        let html_with_bridge = IpcBridge::inject_bridge(html);
-        self.load_html(&html_with_bridge, base_url)
+        self.load_html(html, base_url)
     }
 
     /// Execute JavaScript in the web view.
-    #[allow(unsafe_code)]
+    ///
+    /// Runs in the isolated script world if one is configured.
     pub fn evaluate_script(&self, script: &str) -> Result<()> {
-        let c_script = CString::new(script).map_err(|e| Error::JavaScriptError(e.to_string()))?;
-
-        // SAFETY: self.web_view is valid, c_script is a valid C string with known length.
-        unsafe {
-            wpe_sys::webkit_web_view_evaluate_javascript(
-                self.web_view,
-                c_script.as_ptr(),
-                script.len() as i64,
-                ptr::null(),
-                ptr::null(),
-                ptr::null_mut(),
-                None,
-                ptr::null_mut(),
-            );
-        }
+        script::run(self.web_view, self.settings.script_world.as_deref(), script)?;
 
         tracing::debug!("Evaluating script ({} bytes)", script.len());
         Ok(())
     }
 
+    /// Execute JavaScript and return its result as a future.
+    ///
+    /// Behaves like [`crate::WebView::evaluate_script_with_result`]; drive the
+    /// future by calling `process_events` or `step`.
+    pub fn evaluate_script_with_result<T: DeserializeOwned>(
+        &self,
+        script: &str,
+    ) -> ScriptFuture<T> {
+        let (future, callback) = ScriptFuture::pending();
+        let world = self.settings.script_world.as_deref();
+        match script::evaluate(self.web_view, world, script, callback) {
+            Ok(()) => future,
+            Err(e) => ScriptFuture::failed(e),
+        }
+    }
+
+    /// Execute JavaScript and pass its (possibly typed) result to `callback`.
+    ///
+    /// The callback runs from `process_events` once evaluation completes.
+    ///
+    /// # Errors
+    /// Returns an error if the script could not be passed to WebKit, in which
+    /// case the callback is never called.
+    pub fn evaluate_script_with_callback<T, F>(&self, script: &str, callback: F) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnOnce(Result<T>) + 'static,
+    {
+        let world = self.settings.script_world.as_deref();
+        script::evaluate(self.web_view, world, script, script::typed_callback(callback))
+    }
+
     /// Send a message to the frontend JavaScript.
     pub fn send_message(&self, message: &BackendMessage) -> Result<()> {
         let json = serde_json::to_string(message)?;
-        let script = format!("window.__wpe_receive({json})");
+        let script = ipc::hook_script(&self.settings.ipc.global_name, "__receive", &json);
         self.evaluate_script(&script)
     }
 
@@ -575,6 +1139,42 @@ impl NativeWindow {
         self.send_message(&message)
     }
 
+    /// Send raw bytes to the frontend, delivered to `wpe.onBytes` as an `ArrayBuffer`.
+    ///
+    /// Data the page hasn't fetched when it navigates away is dropped.
+    pub fn send_bytes(&self, message_type: &str, data: impl Into<Vec<u8>>) -> Result<()> {
+        let script = self.binary.offer_bytes(message_type, data.into());
+        self.evaluate_script(&script)
+    }
+
+    /// Stream bytes from `reader` to the frontend, delivered to `wpe.onStream`
+    /// as a `ReadableStream`.
+    ///
+    /// The reader runs on a background thread once the page requests the data.
+    pub fn send_stream(
+        &self,
+        message_type: &str,
+        reader: impl Read + Send + 'static,
+        length: Option<u64>,
+    ) -> Result<()> {
+        let script = self.binary.offer_stream(message_type, reader, length);
+        self.evaluate_script(&script)
+    }
+
+    /// A handle for publishing to pages subscribed to `topic` with `wpe.subscribe`.
+    #[must_use]
+    pub fn subscription(&self, topic: impl Into<String>) -> Subscription {
+        Subscription::new(&self.subscriptions, topic)
+    }
+
+    /// Receive binary messages sent with `wpe.sendBytes` and `wpe.sendStream`.
+    ///
+    /// Always empty with an isolated script world; see [`crate::binary`].
+    #[must_use]
+    pub fn receive_bytes(&mut self) -> Vec<BinaryMessage> {
+        self.binary.receive()
+    }
+
     /// Get a reference to the IPC bridge.
     #[must_use]
     pub fn ipc(&self) -> &IpcBridge {
@@ -587,6 +1187,26 @@ impl NativeWindow {
         &mut self.ipc
     }
 
+    /// Get the settings this window was created with (including later updates).
+    #[must_use]
+    pub fn settings(&self) -> &WebViewSettings {
+        &self.settings
+    }
+
+    /// Change the WebKit options of the live view.
+    ///
+    /// See [`crate::WebView::update_settings`].
+    #[allow(unsafe_code)]
+    pub fn update_settings<F: FnOnce(&mut RuntimeSettings)>(&mut self, update: F) {
+        let mut runtime = self.settings.runtime_settings();
+        update(&mut runtime);
+        // SAFETY: self.web_view is valid.
+        unsafe {
+            crate::settings::apply(self.web_view, &runtime);
+        }
+        self.settings.set_runtime_settings(runtime);
+    }
+
     /// Set the window title.
     #[allow(unsafe_code)]
     pub fn set_title(&mut self, title: &str) {
@@ -610,6 +1230,18 @@ impl NativeWindow {
         tracing::debug!("Resized to {}x{}", width, height);
     }
 
+    /// Override the device scale factor reported by the compositor.
+    #[allow(unsafe_code)]
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        if scale_factor <= 0.0 {
+            return;
+        }
+        // SAFETY: self.view is valid.
+        unsafe {
+            webview::set_view_scale(self.view, scale_factor);
+        }
+    }
+
     /// Go back in history.
     #[allow(unsafe_code)]
     pub fn go_back(&mut self) {
@@ -673,12 +1305,28 @@ impl NativeWindow {
     /// Check if the window should close.
     #[must_use]
     pub fn should_close(&self) -> bool {
-        self.should_close
+        self.close_state.should_close.get()
     }
 
-    /// Request to close the window.
+    /// Close the window.
+    ///
+    /// This bypasses the [`NativeWindow::on_close_requested`] hook and makes
+    /// a running [`NativeWindow::run`] return.
     pub fn close(&mut self) {
-        self.should_close = true;
+        self.close_state.close();
+    }
+
+    /// Decide whether close requests proceed.
+    ///
+    /// The handler is called when the compositor closes the window or the
+    /// page calls `window.close()`, and returns `false` to keep the window
+    /// open (e.g. to ask about unsaved changes first). Without a handler,
+    /// close requests always proceed.
+    pub fn on_close_requested<F>(&mut self, handler: F)
+    where
+        F: Fn(CloseReason) -> bool + 'static,
+    {
+        *self.close_state.handler.borrow_mut() = Some(Box::new(handler));
     }
 
     /// Process pending events. Returns false if the window should close.
@@ -690,16 +1338,27 @@ impl NativeWindow {
             // Process all pending events without blocking
             while wpe_sys::g_main_context_iteration(ctx, 0) != 0 {}
         }
-        !self.should_close
+        !self.should_close()
     }
 
     /// Run the event loop until the window is closed.
+    ///
+    /// Returns once the window closes, either through [`NativeWindow::close`]
+    /// or an accepted close request. Messages from JavaScript are left queued;
+    /// use [`run_with_router`](Self::run_with_router) to answer them.
     #[allow(unsafe_code)]
     pub fn run(&mut self) {
-        // SAFETY: GLib main loop functions are safe to call.
+        if self.should_close() {
+            return;
+        }
+
+        // SAFETY: GLib main loop functions are safe to call. The loop pointer
+        // is only published to the close state while the loop is alive.
         unsafe {
             let main_loop = wpe_sys::g_main_loop_new(ptr::null_mut(), 0);
+            self.close_state.main_loop.set(main_loop);
             wpe_sys::g_main_loop_run(main_loop);
+            self.close_state.main_loop.set(ptr::null_mut());
             wpe_sys::g_main_loop_unref(main_loop);
         }
     }
@@ -707,29 +1366,160 @@ impl NativeWindow {
     /// Receive all pending messages from JavaScript.
     ///
     /// Returns a vector of messages that were queued since the last call.
+    /// Messages from a page that has since been navigated away from are dropped.
     #[must_use]
     pub fn receive_messages(&mut self) -> Vec<FrontendMessage> {
-        match self.message_queue.lock() {
-            Ok(mut queue) => queue.drain(..).collect(),
-            Err(e) => {
-                tracing::warn!("Failed to lock message queue: {}", e);
-                Vec::new()
+        self.message_queue
+            .take()
+            .into_iter()
+            .filter(|msg| !self.subscriptions.handle(msg))
+            .collect()
+    }
+
+    /// Decide what happens to navigations and new-window requests.
+    ///
+    /// Replaces any previously set policy. Without a policy WebKit's default
+    /// handling applies.
+    pub fn set_navigation_policy(&mut self, policy: impl NavigationPolicy) {
+        *self.navigation_policy.borrow_mut() = Some(Box::new(policy));
+    }
+
+    /// Remove the navigation policy, restoring WebKit's default handling.
+    pub fn clear_navigation_policy(&mut self) {
+        *self.navigation_policy.borrow_mut() = None;
+    }
+
+    /// Filter the documents this page navigates to.
+    ///
+    /// Subresources are not filtered; block them with
+    /// [`add_content_filter`](Self::add_content_filter). See [`crate::filter`].
+    pub fn set_document_filter(&mut self, filter: impl DocumentFilter) {
+        self.document_filter.set(Arc::new(filter));
+    }
+
+    /// Remove the document filter.
+    pub fn clear_document_filter(&mut self) {
+        self.document_filter.clear();
+    }
+
+    /// Apply a compiled content rule list to this page.
+    ///
+    /// Takes effect from the next load.
+    #[allow(unsafe_code)]
+    pub fn add_content_filter(&mut self, filter: &ContentFilter) {
+        // SAFETY: self.user_content_manager belongs to self.web_view, which is valid.
+        unsafe {
+            let user_content_manager = self.user_content_manager;
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_add_filter(user_content_manager, filter.raw());
+            }
+        }
+    }
+
+    /// Stop applying a content rule list.
+    #[allow(unsafe_code)]
+    pub fn remove_content_filter(&mut self, filter: &ContentFilter) {
+        // SAFETY: self.user_content_manager belongs to self.web_view, which is valid.
+        unsafe {
+            let user_content_manager = self.user_content_manager;
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_remove_filter(
+                    user_content_manager,
+                    filter.raw(),
+                );
+            }
+        }
+    }
+
+    /// Stop applying all content rule lists.
+    #[allow(unsafe_code)]
+    pub fn remove_all_content_filters(&mut self) {
+        // SAFETY: self.user_content_manager belongs to self.web_view, which is valid.
+        unsafe {
+            let user_content_manager = self.user_content_manager;
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_remove_all_filters(user_content_manager);
             }
         }
     }
 
+    /// Inject a script into documents loaded from now on.
+    pub fn add_user_script(&mut self, script: &UserScript) -> Result<()> {
+        self.user_content.add_script(script)
+    }
+
+    /// Apply a style sheet to documents loaded from now on.
+    pub fn add_user_style_sheet(&mut self, style_sheet: &UserStyleSheet) -> Result<()> {
+        self.user_content.add_style_sheet(style_sheet)
+    }
+
+    /// Remove all scripts added with [`NativeWindow::add_user_script`].
+    ///
+    /// The IPC bridge stays installed.
+    pub fn clear_user_scripts(&mut self) {
+        self.user_content.clear_scripts();
+    }
+
+    /// Remove all style sheets added with [`NativeWindow::add_user_style_sheet`].
+    pub fn clear_user_style_sheets(&mut self) {
+        self.user_content.clear_style_sheets();
+    }
+
+    /// Handle `window.open()` and `target="_blank"` requests.
+    ///
+    /// The handler returns the window to open, created with
+    /// [`NewWindowRequest::create_native_window`] or
+    /// [`NewWindowRequest::create_webview`], or `None` to deny the request.
+    /// Without a handler every request is denied.
+    pub fn on_create_window<F>(&mut self, handler: F)
+    where
+        F: Fn(&NewWindowRequest) -> Option<NewWindow> + 'static,
+    {
+        self.popups.set_handler(Box::new(handler));
+    }
+
+    /// Take the windows opened by this page since the last call.
+    ///
+    /// Windows not taken are kept alive until this one is dropped.
+    #[must_use]
+    pub fn take_new_windows(&mut self) -> Vec<NewWindow> {
+        self.popups.take_windows()
+    }
+
+    /// Choose where downloads are saved.
+    ///
+    /// The handler receives the download and the filename suggested by the
+    /// server. Without a handler, WebKit saves to the user's download
+    /// directory.
+    pub fn on_download<F>(&mut self, handler: F)
+    where
+        F: Fn(&Download, &str) -> DownloadDestination + 'static,
+    {
+        self.downloads.set_handler(Box::new(handler));
+    }
+
+    /// Start downloading `url` without navigating.
+    ///
+    /// # Errors
+    /// Returns an error if the URL is invalid or WebKit refuses the download.
+    #[allow(unsafe_code)]
+    pub fn download_uri(&mut self, url: &str) -> Result<Download> {
+        // SAFETY: self.web_view is valid and connected to self.downloads in new().
+        unsafe { download::download_uri(self.web_view, &self.downloads, url) }
+    }
+
+    /// Receive all pending download events.
+    #[must_use]
+    pub fn receive_download_events(&mut self) -> Vec<DownloadEvent> {
+        self.downloads.take_events()
+    }
+
     /// Receive all pending navigation events.
     ///
     /// Returns a vector of events that were queued since the last call.
     #[must_use]
     pub fn receive_events(&mut self) -> Vec<NavigationEvent> {
-        match self.event_queue.lock() {
-            Ok(mut queue) => queue.drain(..).collect(),
-            Err(e) => {
-                tracing::warn!("Failed to lock event queue: {}", e);
-                Vec::new()
-            }
-        }
+        drain_events(&self.event_queue)
     }
 
     /// Run a single iteration of the event loop with a message handler.
@@ -758,7 +1548,57 @@ impl NativeWindow {
             }
         }
 
-        !self.should_close
+        !self.should_close()
+    }
+
+    /// Run a single iteration of the event loop, dispatching messages through a router.
+    ///
+    /// Requests are answered automatically with the handler's result, or with
+    /// an error response if the handler failed or the command is unknown.
+    /// Async handlers that completed since the last step are answered too,
+    /// and those still in flight are cancelled when the page navigates away.
+    pub fn step_with_router(&mut self, router: &IpcRouter) -> bool {
+        self.process_events();
+        self.dispatch_with_router(router);
+        !self.should_close()
+    }
+
+    /// Run the event loop until the window is closed, dispatching messages
+    /// through a router.
+    ///
+    /// Unlike a [`step_with_router`](Self::step_with_router) loop this sleeps
+    /// until there is work: a GLib event, a woken async handler, or progress
+    /// reported from another thread.
+    #[allow(unsafe_code)]
+    pub fn run_with_router(&mut self, router: &IpcRouter) {
+        while !self.should_close() {
+            // SAFETY: GLib main context functions are safe to call; we use the default context.
+            unsafe {
+                let ctx = wpe_sys::g_main_context_default();
+                // Block until an event arrives or a router waker fires
+                wpe_sys::g_main_context_iteration(ctx, 1);
+                while wpe_sys::g_main_context_iteration(ctx, 0) != 0 {}
+            }
+            self.dispatch_with_router(router);
+        }
+    }
+
+    /// Dispatch received messages through `router` and send its responses.
+    fn dispatch_with_router(&mut self, router: &IpcRouter) {
+        router.sync_navigation(self.navigation_count.load(Ordering::Acquire));
+
+        let responses: Vec<_> = self
+            .receive_messages()
+            .iter()
+            .filter_map(|msg| router.dispatch(msg))
+            .chain(router.poll_pending())
+            .collect();
+
+        for response in responses {
+            if let Err(e) = self.send_message(&response) {
+                tracing::warn!("Failed to send response: {}", e);
+            }
+        }
     }
 
     /// Get the current URL.
@@ -907,6 +1747,11 @@ impl Drop for NativeWindow {
         unsafe {
             // First, unref GLib objects - this disconnects any signals
             if !self.web_view.is_null() {
+                ipc::unroute_fetch_messages(self.web_view);
+                self.subscriptions.detach();
+                binary::unroute(self.web_view);
+                assets::unmount(self.web_view);
+                download::disconnect(self.web_view, self.download_signal_id);
                 wpe_sys::g_object_unref(self.web_view as *mut _);
             }
             if !self.view.is_null() {
@@ -916,12 +1761,30 @@ impl Drop for NativeWindow {
             // Now reclaim the Arc pointers we leaked for signal handlers.
             // SAFETY: These pointers were created with Arc::into_raw() in new(),
             // and the signals that used them have been disconnected by the unref above.
-            if !self.message_queue_ptr.is_null() {
-                drop(Arc::from_raw(self.message_queue_ptr));
+            if !self.message_handler_ptr.is_null() {
+                drop(Box::from_raw(self.message_handler_ptr));
             }
             if !self.event_queue_ptr.is_null() {
                 drop(Arc::from_raw(self.event_queue_ptr));
             }
+            if !self.navigation_count_ptr.is_null() {
+                drop(Arc::from_raw(self.navigation_count_ptr));
+            }
+            if !self.document_filter_ptr.is_null() {
+                drop(Rc::from_raw(self.document_filter_ptr));
+            }
+            if !self.navigation_policy_ptr.is_null() {
+                drop(Rc::from_raw(self.navigation_policy_ptr));
+            }
+            if !self.popups_ptr.is_null() {
+                drop(Rc::from_raw(self.popups_ptr));
+            }
+            if !self.downloads_ptr.is_null() {
+                drop(Rc::from_raw(self.downloads_ptr));
+            }
+            if !self.close_state_ptr.is_null() {
+                drop(Rc::from_raw(self.close_state_ptr));
+            }
         }
     }
 }
@@ -929,3 +1792,88 @@ impl Drop for NativeWindow {
 // Note: NativeWindow is not Send or Sync because it holds raw pointers
 // to GObject types that are not thread-safe. All operations must be
 // performed on the thread where the window was created.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct NoopWake;
+
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn poll_once(future: &mut LoadFuture) -> Poll<Result<()>> {
+        let waker = Waker::from(Arc::new(NoopWake));
+        let mut cx = Context::from_waker(&waker);
+        Pin::new(future).poll(&mut cx)
+    }
+
+    #[test]
+    fn test_load_future_resolves_when_finished() {
+        let tracker = Arc::new(LoadTracker::default());
+        tracker.start();
+
+        let mut future = LoadFuture::new(Arc::clone(&tracker), Duration::from_secs(30));
+        assert!(poll_once(&mut future).is_pending());
+
+        tracker.finish();
+        assert!(matches!(poll_once(&mut future), Poll::Ready(Ok(()))));
+    }
+
+    #[test]
+    fn test_load_future_times_out() {
+        let tracker = Arc::new(LoadTracker::default());
+        tracker.start();
+
+        let mut future = LoadFuture::new(tracker, Duration::ZERO);
+        assert!(matches!(
+            poll_once(&mut future),
+            Poll::Ready(Err(Error::LoadTimeout))
+        ));
+    }
+
+    #[test]
+    fn test_load_future_ready_when_idle() {
+        let mut future = LoadFuture::new(Arc::new(LoadTracker::default()), Duration::ZERO);
+        assert!(matches!(poll_once(&mut future), Poll::Ready(Ok(()))));
+    }
+
+    #[test]
+    fn test_close_request_without_handler_closes() {
+        let state = CloseState::default();
+        state.request(CloseReason::Compositor);
+        assert!(state.should_close.get());
+    }
+
+    #[test]
+    fn test_close_request_veto() {
+        let state = CloseState::default();
+        *state.handler.borrow_mut() = Some(Box::new(|reason| reason == CloseReason::Compositor));
+
+        state.request(CloseReason::Script);
+        assert!(!state.should_close.get());
+
+        state.request(CloseReason::Compositor);
+        assert!(state.should_close.get());
+    }
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_script_close_queues_event_and_closes() {
+        let events: EventQueue = Arc::new(Mutex::new(VecDeque::new()));
+        let state = CloseState::new(&events);
+
+        // SAFETY: The handler only reads the CloseState behind user_data.
+        unsafe {
+            on_script_close(ptr::null_mut(), &state as *const CloseState as *mut _);
+        }
+
+        assert!(state.should_close.get());
+        assert!(matches!(
+            drain_events(&events).as_slice(),
+            [NavigationEvent::CloseRequested]
+        ));
+    }
+}
@@ -0,0 +1,435 @@
+//! PDF output for full-page captures.
+//!
+//! WPE WebKit has no print operation (`WebKitPrintOperation` only exists in
+//! the GTK port), so [`crate::WebView::capture_to_pdf`] lays the page out at
+//! the printable width, captures it with [`crate::WebView::snapshot_full_page`]
+//! and paginates the image into a PDF. The output is a raster screenshot, not
+//! a print: text is not selectable or searchable, vector content is
+//! rasterized at the view's scale factor, and pages use the screen stylesheet
+//! rather than `@media print`.
+
+use std::fmt::Write as _;
+
+use crate::snapshot::Snapshot;
+
+/// Points (1/72 inch) per CSS pixel (1/96 inch).
+const POINTS_PER_CSS_PIXEL: f64 = 0.75;
+
+/// Default margin on each side: half an inch.
+const DEFAULT_MARGIN: f64 = 36.0;
+
+/// Style injected while printing without backgrounds.
+pub(crate) const HIDE_BACKGROUNDS_SCRIPT: &str = r"(() => {
+    const style = document.createElement('style');
+    style.id = '__wpe_print_no_backgrounds';
+    style.textContent = '*, *::before, *::after { background: transparent !important; } html { background: #fff !important; }';
+    document.documentElement.appendChild(style);
+})();";
+
+/// Removes the style added by [`HIDE_BACKGROUNDS_SCRIPT`].
+pub(crate) const RESTORE_BACKGROUNDS_SCRIPT: &str =
+    "document.getElementById('__wpe_print_no_backgrounds')?.remove();";
+
+/// Paper size in points (1/72 inch).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl PageSize {
+    /// ISO A4 (210 x 297 mm).
+    pub const A4: Self = Self {
+        width: 595.28,
+        height: 841.89,
+    };
+    /// ISO A3 (297 x 420 mm).
+    pub const A3: Self = Self {
+        width: 841.89,
+        height: 1190.55,
+    };
+    /// US Letter (8.5 x 11 in).
+    pub const LETTER: Self = Self {
+        width: 612.0,
+        height: 792.0,
+    };
+    /// US Legal (8.5 x 14 in).
+    pub const LEGAL: Self = Self {
+        width: 612.0,
+        height: 1008.0,
+    };
+
+    /// A custom page size in millimetres.
+    #[must_use]
+    pub fn from_millimeters(width: f64, height: f64) -> Self {
+        Self {
+            width: width * 72.0 / 25.4,
+            height: height * 72.0 / 25.4,
+        }
+    }
+
+    /// A custom page size in inches.
+    #[must_use]
+    pub fn from_inches(width: f64, height: f64) -> Self {
+        Self {
+            width: width * 72.0,
+            height: height * 72.0,
+        }
+    }
+
+    /// The same size in landscape orientation.
+    #[must_use]
+    pub fn landscape(self) -> Self {
+        if self.width < self.height {
+            Self {
+                width: self.height,
+                height: self.width,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        Self::A4
+    }
+}
+
+/// Page margins in points (1/72 inch).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageMargins {
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    pub left: f64,
+}
+
+impl PageMargins {
+    /// The same margin on every side.
+    #[must_use]
+    pub fn uniform(margin: f64) -> Self {
+        Self {
+            top: margin,
+            right: margin,
+            bottom: margin,
+            left: margin,
+        }
+    }
+
+    /// No margins.
+    #[must_use]
+    pub fn none() -> Self {
+        Self::uniform(0.0)
+    }
+}
+
+impl Default for PageMargins {
+    fn default() -> Self {
+        Self::uniform(DEFAULT_MARGIN)
+    }
+}
+
+/// Options for [`crate::WebView::capture_to_pdf`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfOptions {
+    /// Paper size
+    pub page_size: PageSize,
+    /// Margins around the printed content
+    pub margins: PageMargins,
+    /// Print CSS backgrounds (colors and images)
+    pub print_backgrounds: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            margins: PageMargins::default(),
+            print_backgrounds: true,
+        }
+    }
+}
+
+impl PdfOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_page_size(mut self, page_size: PageSize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    #[must_use]
+    pub fn with_margins(mut self, margins: PageMargins) -> Self {
+        self.margins = margins;
+        self
+    }
+
+    #[must_use]
+    pub fn with_print_backgrounds(mut self, enabled: bool) -> Self {
+        self.print_backgrounds = enabled;
+        self
+    }
+
+    /// Printable area in points.
+    fn content_size(&self) -> (f64, f64) {
+        let width = self.page_size.width - self.margins.left - self.margins.right;
+        let height = self.page_size.height - self.margins.top - self.margins.bottom;
+        (width.max(1.0), height.max(1.0))
+    }
+
+    /// Width of the printable area in CSS pixels, used as the layout width.
+    pub(crate) fn content_width_px(&self) -> u32 {
+        (self.content_size().0 / POINTS_PER_CSS_PIXEL).round() as u32
+    }
+}
+
+/// Paginate a full-page capture into a PDF document.
+///
+/// `scale_factor` is the number of image pixels per CSS pixel.
+pub(crate) fn encode(snapshot: &Snapshot, options: &PdfOptions, scale_factor: f64) -> Vec<u8> {
+    let points_per_pixel = POINTS_PER_CSS_PIXEL / scale_factor;
+    let (_, content_height) = options.content_size();
+    let rows_per_page = ((content_height / points_per_pixel).floor() as u32).max(1);
+    let page_count = snapshot.height().div_ceil(rows_per_page).max(1);
+
+    let mut pdf = PdfWriter::new();
+
+    pdf.object(1, |out| {
+        out.extend_from_slice(b"<< /Type /Catalog /Pages 2 0 R >>");
+    });
+
+    let kids = (0..page_count)
+        .map(|page| format!("{} 0 R", page_object_id(page)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    pdf.object(2, |out| {
+        out.extend_from_slice(
+            format!("<< /Type /Pages /Kids [{kids}] /Count {page_count} >>").as_bytes(),
+        );
+    });
+
+    let page = options.page_size;
+    for index in 0..page_count {
+        let first_row = index * rows_per_page;
+        let rows = rows_per_page.min(snapshot.height() - first_row);
+        let id = page_object_id(index);
+
+        pdf.object(id, |out| {
+            out.extend_from_slice(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                     /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                    page.width,
+                    page.height,
+                    id + 2,
+                    id + 1
+                )
+                .as_bytes(),
+            );
+        });
+
+        let width = f64::from(snapshot.width()) * points_per_pixel;
+        let height = f64::from(rows) * points_per_pixel;
+        let x = options.margins.left;
+        let y = page.height - options.margins.top - height;
+        let content = format!("q {width:.3} 0 0 {height:.3} {x:.3} {y:.3} cm /Im0 Do Q");
+        pdf.stream(id + 1, "", content.as_bytes());
+
+        let pixels = rgb_rows(snapshot, first_row, rows);
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6);
+        let dictionary = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {rows} \
+             /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode ",
+            snapshot.width()
+        );
+        pdf.stream(id + 2, &dictionary, &compressed);
+    }
+
+    pdf.finish(1)
+}
+
+/// Object ID of a page; its content stream and image follow it.
+fn page_object_id(page: u32) -> usize {
+    3 + page as usize * 3
+}
+
+/// RGB bytes for `rows` rows of `snapshot`, composited onto white.
+fn rgb_rows(snapshot: &Snapshot, first_row: u32, rows: u32) -> Vec<u8> {
+    let row_bytes = snapshot.width() as usize * 4;
+    let start = first_row as usize * row_bytes;
+    let end = start + rows as usize * row_bytes;
+
+    snapshot.data()[start..end]
+        .chunks_exact(4)
+        .flat_map(|rgba| {
+            let alpha = u32::from(rgba[3]);
+            let over_white =
+                move |c: u8| ((u32::from(c) * alpha + 255 * (255 - alpha)) / 255) as u8;
+            [
+                over_white(rgba[0]),
+                over_white(rgba[1]),
+                over_white(rgba[2]),
+            ]
+        })
+        .collect()
+}
+
+/// Minimal PDF serializer tracking object offsets for the xref table.
+struct PdfWriter {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        Self {
+            out: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Write object `id`. Objects must be written in ID order starting at 1.
+    fn object(&mut self, id: usize, body: impl FnOnce(&mut Vec<u8>)) {
+        debug_assert_eq!(id, self.offsets.len() + 1);
+        self.offsets.push(self.out.len());
+        self.out
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+        body(&mut self.out);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// Write a stream object with extra `dictionary` entries.
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        self.object(id, |out| {
+            out.extend_from_slice(
+                format!("<< {dictionary}/Length {} >>\nstream\n", data.len()).as_bytes(),
+            );
+            out.extend_from_slice(data);
+            out.extend_from_slice(b"\nendstream");
+        });
+    }
+
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let xref_offset = self.out.len();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(xref, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            xref,
+            "trailer\n<< /Size {} /Root {root} 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+            self.offsets.len() + 1
+        );
+        self.out.extend_from_slice(xref.as_bytes());
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|window| *window == needle)
+            .count()
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_page_sizes() {
+        let landscape = PageSize::A4.landscape();
+        assert!(landscape.width > landscape.height);
+        assert_eq!(landscape.landscape(), landscape);
+
+        let letter = PageSize::from_inches(8.5, 11.0);
+        assert_eq!(letter, PageSize::LETTER);
+
+        let a4 = PageSize::from_millimeters(210.0, 297.0);
+        assert!((a4.width - PageSize::A4.width).abs() < 0.01);
+        assert!((a4.height - PageSize::A4.height).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_content_width() {
+        let options = PdfOptions::new()
+            .with_page_size(PageSize::LETTER)
+            .with_margins(PageMargins::uniform(36.0));
+        assert_eq!(options.content_width_px(), 720);
+
+        let options = options.with_margins(PageMargins::none());
+        assert_eq!(options.content_width_px(), 816);
+    }
+
+    #[test]
+    fn test_encode_paginates() {
+        // Each page holds 10 rows: (7.5pt content height) / (0.75pt per pixel)
+        let options = PdfOptions::new()
+            .with_page_size(PageSize {
+                width: 10.0,
+                height: 9.5,
+            })
+            .with_margins(PageMargins {
+                top: 1.0,
+                right: 1.0,
+                bottom: 1.0,
+                left: 1.0,
+            });
+        let snapshot = Snapshot::from_rgba(4, 25, vec![255; 4 * 25 * 4]).unwrap();
+        let pdf = encode(&snapshot, &options, 1.0);
+
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert_eq!(count(&pdf, b"/Count 3"), 1);
+        assert_eq!(count(&pdf, b"/Type /Page "), 3);
+        assert_eq!(count(&pdf, b"/Height 10 "), 2);
+        assert_eq!(count(&pdf, b"/Height 5 "), 1);
+    }
+
+    #[test]
+    fn test_encode_xref_offsets() {
+        let snapshot = Snapshot::from_rgba(2, 2, vec![0; 16]).unwrap();
+        let pdf = encode(&snapshot, &PdfOptions::default(), 2.0);
+
+        let xref = find(&pdf, b"xref\n");
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let offsets: Vec<usize> = table
+            .lines()
+            .skip(3)
+            .take_while(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+
+        assert_eq!(offsets.len(), 5);
+        for (index, offset) in offsets.iter().enumerate() {
+            let header = format!("{} 0 obj", index + 1);
+            assert!(pdf[*offset..].starts_with(header.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_image_composited_on_white() {
+        let snapshot = Snapshot::from_rgba(2, 1, vec![255, 0, 0, 255, 0, 0, 0, 0]).unwrap();
+        let pdf = encode(&snapshot, &PdfOptions::default(), 1.0);
+
+        let start = find(&pdf, b"/FlateDecode ");
+        let data_start = start + find(&pdf[start..], b"stream\n") + "stream\n".len();
+        let data_end = data_start + find(&pdf[data_start..], b"\nendstream");
+        let pixels =
+            miniz_oxide::inflate::decompress_to_vec_zlib(&pdf[data_start..data_end]).unwrap();
+        assert_eq!(pixels, vec![255, 0, 0, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,529 @@
+//! Navigation policy decisions.
+//!
+//! A [`NavigationPolicy`] is consulted through WebKit's `decide-policy`
+//! signal whenever a page navigates or asks for a new window, and decides
+//! whether the navigation goes ahead, is dropped, becomes a download or is
+//! handed to the system's default handler.
+//!
+//! ```rust,ignore
+//! use wpe::{AllowedOrigins, NavigationRequest, PolicyDecision};
+//!
+//! // Keep the kiosk on its own origin, send clicked web and mail links to the desktop
+//! webview.set_navigation_policy(AllowedOrigins::new(["https://kiosk.example.com"]));
+//!
+//! // Or decide with a closure
+//! webview.set_navigation_policy(|request: &NavigationRequest| {
+//!     if request.url.starts_with("mailto:") {
+//!         PolicyDecision::OpenExternally
+//!     } else {
+//!         PolicyDecision::Allow
+//!     }
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::rc::Rc;
+
+/// What caused a navigation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavigationType {
+    /// A link was clicked.
+    LinkClicked,
+    /// A form was submitted.
+    FormSubmitted,
+    /// Back/forward history navigation.
+    BackForward,
+    /// The page was reloaded.
+    Reload,
+    /// A form was resubmitted (e.g. by reloading after a POST).
+    FormResubmitted,
+    /// Anything else, including navigations started from script or Rust.
+    Other,
+}
+
+impl NavigationType {
+    fn from_webkit(navigation_type: wpe_sys::WebKitNavigationType) -> Self {
+        match navigation_type {
+            wpe_sys::WebKitNavigationType_WEBKIT_NAVIGATION_TYPE_LINK_CLICKED => Self::LinkClicked,
+            wpe_sys::WebKitNavigationType_WEBKIT_NAVIGATION_TYPE_FORM_SUBMITTED => {
+                Self::FormSubmitted
+            }
+            wpe_sys::WebKitNavigationType_WEBKIT_NAVIGATION_TYPE_BACK_FORWARD => Self::BackForward,
+            wpe_sys::WebKitNavigationType_WEBKIT_NAVIGATION_TYPE_RELOAD => Self::Reload,
+            wpe_sys::WebKitNavigationType_WEBKIT_NAVIGATION_TYPE_FORM_RESUBMITTED => {
+                Self::FormResubmitted
+            }
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A navigation awaiting a policy decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavigationRequest {
+    /// The URL being navigated to
+    pub url: String,
+    /// What caused the navigation
+    pub navigation_type: NavigationType,
+    /// Whether the navigation was triggered by a user gesture
+    pub user_gesture: bool,
+    /// Whether the navigation wants to open a new window (`target="_blank"`, `window.open`)
+    pub new_window: bool,
+    /// The target frame name of a link or form (`target="..."`), if it has one
+    ///
+    /// WebKit does not report whether a navigation belongs to the main frame
+    /// or a subframe; iframe loads arrive here like any other navigation.
+    pub target_frame: Option<String>,
+}
+
+/// The outcome of a [`NavigationPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyDecision {
+    /// Let the navigation proceed.
+    Allow,
+    /// Drop the navigation.
+    Ignore,
+    /// Download the target instead of displaying it.
+    Download,
+    /// Drop the navigation and open the URL with the system's default handler.
+    OpenExternally,
+}
+
+/// Decides what happens to navigations in a web view.
+///
+/// Implemented for any `Fn(&NavigationRequest) -> PolicyDecision` closure.
+pub trait NavigationPolicy: 'static {
+    /// Decide what to do with a navigation.
+    fn decide(&self, request: &NavigationRequest) -> PolicyDecision;
+}
+
+impl<F> NavigationPolicy for F
+where
+    F: Fn(&NavigationRequest) -> PolicyDecision + 'static,
+{
+    fn decide(&self, request: &NavigationRequest) -> PolicyDecision {
+        self(request)
+    }
+}
+
+/// A policy that keeps navigation inside a set of origins.
+///
+/// Navigations to an allowed origin (and to `about:` URLs and `blob:` URLs
+/// created by an allowed origin) are allowed. Other navigations the user
+/// started, such as clicking a `mailto:` link, are opened with the system's
+/// default handler if their scheme is one of the external schemes (`mailto`,
+/// `tel`, `http` and `https` by default). The rest, including `file:` and
+/// custom schemes, third-party iframes loading and redirects, are ignored so
+/// pages cannot launch arbitrary applications.
+#[derive(Debug, Clone)]
+pub struct AllowedOrigins {
+    origins: Vec<String>,
+    external_schemes: Vec<String>,
+}
+
+/// Schemes [`AllowedOrigins`] hands to the system by default.
+const DEFAULT_EXTERNAL_SCHEMES: [&str; 4] = ["mailto", "tel", "http", "https"];
+
+impl Default for AllowedOrigins {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            external_schemes: DEFAULT_EXTERNAL_SCHEMES.map(str::to_string).to_vec(),
+        }
+    }
+}
+
+impl AllowedOrigins {
+    /// Allow the given origins, e.g. `https://example.com` or `app://localhost`.
+    ///
+    /// Entries may be full URLs; only their origin is used.
+    pub fn new<I, S>(origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            origins: origins
+                .into_iter()
+                .filter_map(|o| origin(o.as_ref()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the schemes user-initiated navigations may open externally.
+    ///
+    /// Replaces the defaults (`mailto`, `tel`, `http`, `https`); pass an
+    /// empty list to never open anything externally.
+    #[must_use]
+    pub fn with_external_schemes<I, S>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.external_schemes = schemes
+            .into_iter()
+            .map(|s| s.as_ref().trim_end_matches(':').to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    /// Whether `url` belongs to an allowed origin.
+    ///
+    /// `blob:` URLs belong to the origin of the page that created them.
+    #[must_use]
+    pub fn allows(&self, url: &str) -> bool {
+        let url = match url.split_once(':') {
+            Some((scheme, inner)) if scheme.eq_ignore_ascii_case("blob") => inner,
+            _ => url,
+        };
+        origin(url).is_some_and(|o| self.origins.contains(&o))
+    }
+
+    /// Whether `scheme` may be opened with the system's default handler.
+    fn opens_externally(&self, scheme: &str) -> bool {
+        self.external_schemes
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(scheme))
+    }
+}
+
+impl NavigationPolicy for AllowedOrigins {
+    fn decide(&self, request: &NavigationRequest) -> PolicyDecision {
+        let scheme = request.url.split(':').next().unwrap_or_default();
+        if self.allows(&request.url) || scheme.eq_ignore_ascii_case("about") {
+            PolicyDecision::Allow
+        } else if request.user_gesture && self.opens_externally(scheme) {
+            PolicyDecision::OpenExternally
+        } else {
+            PolicyDecision::Ignore
+        }
+    }
+}
+
+/// The origin of the page loaded in `web_view`, if it has one.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn page_origin(web_view: *mut wpe_sys::WebKitWebView) -> Option<String> {
+    let uri = wpe_sys::webkit_web_view_get_uri(web_view);
+    if uri.is_null() {
+        return None;
+    }
+    origin(&CStr::from_ptr(uri).to_string_lossy())
+}
+
+/// The `scheme://host[:port]` origin of `url`, normalized to lowercase.
+///
+/// Default ports for `http`, `https`, `ws` and `wss` are dropped. Returns
+/// `None` for URLs without an authority such as `mailto:` or `data:`.
+pub(crate) fn origin(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if scheme.is_empty()
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    {
+        return None;
+    }
+    let scheme = scheme.to_ascii_lowercase();
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+    if host_port.is_empty() {
+        return None;
+    }
+
+    let mut host_port = host_port.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" | "ws" => Some(":80"),
+        "https" | "wss" => Some(":443"),
+        _ => None,
+    };
+    if let Some(port) = default_port {
+        if host_port.ends_with(port) {
+            host_port.truncate(host_port.len() - port.len());
+        }
+    }
+
+    Some(format!("{scheme}://{host_port}"))
+}
+
+/// The policy installed on a web view, shared with the `decide-policy` handler.
+pub(crate) type PolicySlot = Rc<RefCell<Option<Box<dyn NavigationPolicy>>>>;
+
+/// The inner type that `Rc::into_raw` returns a pointer to.
+pub(crate) type PolicySlotInner = RefCell<Option<Box<dyn NavigationPolicy>>>;
+
+/// Signal handler for decide-policy.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_decide_policy(
+    _web_view: *mut wpe_sys::WebKitWebView,
+    decision: *mut wpe_sys::WebKitPolicyDecision,
+    decision_type: wpe_sys::WebKitPolicyDecisionType,
+    user_data: *mut std::ffi::c_void,
+) -> i32 {
+    if user_data.is_null() || decision.is_null() {
+        return 0;
+    }
+
+    let new_window = match decision_type {
+        wpe_sys::WebKitPolicyDecisionType_WEBKIT_POLICY_DECISION_TYPE_NAVIGATION_ACTION => false,
+        wpe_sys::WebKitPolicyDecisionType_WEBKIT_POLICY_DECISION_TYPE_NEW_WINDOW_ACTION => true,
+        // Responses keep WebKit's default handling
+        _ => return 0,
+    };
+
+    let slot = &*(user_data as *const PolicySlotInner);
+    let Ok(policy) = slot.try_borrow() else {
+        tracing::warn!("Navigation policy re-entered, using default handling");
+        return 0;
+    };
+    let Some(policy) = policy.as_ref() else {
+        return 0;
+    };
+
+    let action = wpe_sys::webkit_navigation_policy_decision_get_navigation_action(
+        decision as *mut wpe_sys::WebKitNavigationPolicyDecision,
+    );
+    if action.is_null() {
+        return 0;
+    }
+
+    let uri_request = wpe_sys::webkit_navigation_action_get_request(action);
+    let uri = if uri_request.is_null() {
+        ptr::null()
+    } else {
+        wpe_sys::webkit_uri_request_get_uri(uri_request)
+    };
+    let frame_name = wpe_sys::webkit_navigation_action_get_frame_name(action);
+    let request = NavigationRequest {
+        url: if uri.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(uri).to_string_lossy().into_owned()
+        },
+        navigation_type: NavigationType::from_webkit(
+            wpe_sys::webkit_navigation_action_get_navigation_type(action),
+        ),
+        user_gesture: wpe_sys::webkit_navigation_action_is_user_gesture(action) != 0,
+        new_window,
+        target_frame: (!frame_name.is_null())
+            .then(|| CStr::from_ptr(frame_name).to_string_lossy().into_owned()),
+    };
+
+    let outcome = policy.decide(&request);
+    tracing::debug!("Navigation policy for {}: {:?}", request.url, outcome);
+
+    match outcome {
+        PolicyDecision::Allow => wpe_sys::webkit_policy_decision_use(decision),
+        PolicyDecision::Ignore => wpe_sys::webkit_policy_decision_ignore(decision),
+        PolicyDecision::Download => wpe_sys::webkit_policy_decision_download(decision),
+        PolicyDecision::OpenExternally => {
+            wpe_sys::webkit_policy_decision_ignore(decision);
+            open_externally(&request.url);
+        }
+    }
+
+    1 // TRUE - decision made
+}
+
+/// Open `url` with the system's default handler.
+#[allow(unsafe_code)]
+unsafe fn open_externally(url: &str) {
+    let Ok(c_url) = CString::new(url) else {
+        tracing::warn!("Cannot open URL externally: {}", url);
+        return;
+    };
+
+    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+    if wpe_sys::g_app_info_launch_default_for_uri(c_url.as_ptr(), ptr::null_mut(), &mut error) == 0
+    {
+        if error.is_null() {
+            tracing::warn!("Failed to open {} externally", url);
+        } else {
+            let msg = CStr::from_ptr((*error).message);
+            tracing::warn!("Failed to open {} externally: {:?}", url, msg);
+            wpe_sys::g_error_free(error);
+        }
+    }
+}
+
+/// Consult the policy in `slot` for navigations of `web_view`.
+///
+/// The returned pointer keeps the slot alive for the signal handler and
+/// must be reclaimed with `Rc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect(
+    web_view: *mut wpe_sys::WebKitWebView,
+    slot: &PolicySlot,
+) -> *const PolicySlotInner {
+    let slot_ptr = Rc::into_raw(Rc::clone(slot));
+
+    let signal_name = CString::new("decide-policy").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                *mut wpe_sys::WebKitPolicyDecision,
+                wpe_sys::WebKitPolicyDecisionType,
+                *mut std::ffi::c_void,
+            ) -> i32,
+            unsafe extern "C" fn(),
+        >(on_decide_policy)),
+        slot_ptr as *mut _,
+        None,
+        0,
+    );
+
+    slot_ptr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str) -> NavigationRequest {
+        NavigationRequest {
+            url: url.to_string(),
+            navigation_type: NavigationType::LinkClicked,
+            user_gesture: true,
+            new_window: false,
+            target_frame: None,
+        }
+    }
+
+    #[test]
+    fn test_origin() {
+        assert_eq!(
+            origin("https://Example.com/path?q=1").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            origin("https://example.com:443/").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            origin("http://localhost:8080").as_deref(),
+            Some("http://localhost:8080")
+        );
+        assert_eq!(
+            origin("https://user:pw@example.com#top").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            origin("app://localhost/index.html").as_deref(),
+            Some("app://localhost")
+        );
+        assert_eq!(origin("mailto:someone@example.com"), None);
+        assert_eq!(origin("file:///etc/hosts"), None);
+    }
+
+    #[test]
+    fn test_allowed_origins() {
+        let policy = AllowedOrigins::new(["https://kiosk.example.com/", "app://localhost"]);
+
+        assert_eq!(
+            policy.decide(&request("https://kiosk.example.com/menu")),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            policy.decide(&request("app://localhost/")),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            policy.decide(&request("about:blank")),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            policy.decide(&request("https://evil.example.com/")),
+            PolicyDecision::OpenExternally
+        );
+        assert_eq!(
+            policy.decide(&request("mailto:help@example.com")),
+            PolicyDecision::OpenExternally
+        );
+    }
+
+    #[test]
+    fn test_allowed_origins_external_schemes() {
+        let policy = AllowedOrigins::new(["https://kiosk.example.com"]);
+
+        assert_eq!(
+            policy.decide(&request("tel:+15555550100")),
+            PolicyDecision::OpenExternally
+        );
+        assert_eq!(
+            policy.decide(&request("file:///etc/passwd")),
+            PolicyDecision::Ignore
+        );
+        assert_eq!(
+            policy.decide(&request("myapp://run?cmd=rm")),
+            PolicyDecision::Ignore
+        );
+
+        let policy = policy.with_external_schemes(["mailto:", "MyApp"]);
+        assert_eq!(
+            policy.decide(&request("myapp://open")),
+            PolicyDecision::OpenExternally
+        );
+        assert_eq!(
+            policy.decide(&request("https://evil.example.com/")),
+            PolicyDecision::Ignore
+        );
+    }
+
+    #[test]
+    fn test_allowed_origins_blob_urls() {
+        let policy = AllowedOrigins::new(["https://kiosk.example.com"]);
+
+        assert_eq!(
+            policy.decide(&request("blob:https://kiosk.example.com/0b6c2d0e")),
+            PolicyDecision::Allow
+        );
+
+        let mut foreign = request("blob:https://evil.example.com/0b6c2d0e");
+        assert_eq!(policy.decide(&foreign), PolicyDecision::Ignore);
+        foreign.user_gesture = false;
+        assert_eq!(policy.decide(&foreign), PolicyDecision::Ignore);
+    }
+
+    #[test]
+    fn test_allowed_origins_ignores_iframe_loads() {
+        let policy = AllowedOrigins::new(["https://kiosk.example.com"]);
+
+        // A third-party iframe loading, which no user gesture started
+        let mut embed = request("https://video.example.net/embed/1");
+        embed.navigation_type = NavigationType::Other;
+        embed.user_gesture = false;
+        assert_eq!(policy.decide(&embed), PolicyDecision::Ignore);
+
+        let mut allowed = request("https://kiosk.example.com/frame");
+        allowed.user_gesture = false;
+        assert_eq!(policy.decide(&allowed), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_closure_policy() {
+        let policy = |request: &NavigationRequest| {
+            if request.new_window {
+                PolicyDecision::Ignore
+            } else {
+                PolicyDecision::Allow
+            }
+        };
+
+        let mut popup = request("https://example.com");
+        assert_eq!(policy.decide(&popup), PolicyDecision::Allow);
+        popup.new_window = true;
+        assert_eq!(policy.decide(&popup), PolicyDecision::Ignore);
+    }
+}
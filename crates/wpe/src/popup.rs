@@ -0,0 +1,313 @@
+//! New-window handling through WebKit's `create` signal.
+//!
+//! `window.open()` and `target="_blank"` links ask the embedder for a new
+//! web view. Without a handler the request is denied. With one, the app
+//! decides per request and creates the new view with
+//! [`NewWindowRequest::create_native_window`] or
+//! [`NewWindowRequest::create_webview`], which relate it to the opener as
+//! WebKit requires. Created windows are handed back through
+//! `take_new_windows()` on the opener.
+//!
+//! ```rust,ignore
+//! window.on_create_window(|request| {
+//!     if !request.is_user_gesture() {
+//!         return None;
+//!     }
+//!     let settings = WebViewSettings::new().with_title("Popup");
+//!     request.create_native_window(settings).ok().map(NewWindow::Native)
+//! });
+//!
+//! for popup in window.take_new_windows() {
+//!     popups.push(popup);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::renderer::SharedFrameBuffer;
+use crate::{NativeWindow, Result, WebView, WebViewSettings};
+
+/// Window geometry requested by `window.open()`, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Window features requested by the page (the `window.open()` feature string).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct WindowFeatures {
+    /// Requested position and size, if any
+    pub geometry: Option<WindowGeometry>,
+    pub toolbar_visible: bool,
+    pub statusbar_visible: bool,
+    pub scrollbars_visible: bool,
+    pub menubar_visible: bool,
+    pub locationbar_visible: bool,
+    pub resizable: bool,
+    pub fullscreen: bool,
+}
+
+impl WindowFeatures {
+    /// Read the window properties WebKit recorded for `web_view`.
+    ///
+    /// # Safety
+    /// `web_view` must be a valid `WebKitWebView`.
+    #[allow(unsafe_code)]
+    pub(crate) unsafe fn from_web_view(web_view: *mut wpe_sys::WebKitWebView) -> Self {
+        let properties = wpe_sys::webkit_web_view_get_window_properties(web_view);
+        if properties.is_null() {
+            return Self::default();
+        }
+
+        let mut rect = wpe_sys::WebKitRectangle::default();
+        wpe_sys::webkit_window_properties_get_geometry(properties, &mut rect);
+        let geometry = (rect.width > 0 && rect.height > 0).then_some(WindowGeometry {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        });
+
+        Self {
+            geometry,
+            toolbar_visible: wpe_sys::webkit_window_properties_get_toolbar_visible(properties) != 0,
+            statusbar_visible: wpe_sys::webkit_window_properties_get_statusbar_visible(properties)
+                != 0,
+            scrollbars_visible: wpe_sys::webkit_window_properties_get_scrollbars_visible(
+                properties,
+            ) != 0,
+            menubar_visible: wpe_sys::webkit_window_properties_get_menubar_visible(properties) != 0,
+            locationbar_visible: wpe_sys::webkit_window_properties_get_locationbar_visible(
+                properties,
+            ) != 0,
+            resizable: wpe_sys::webkit_window_properties_get_resizable(properties) != 0,
+            fullscreen: wpe_sys::webkit_window_properties_get_fullscreen(properties) != 0,
+        }
+    }
+}
+
+/// A page's request to open a new window.
+///
+/// The requested [`WindowFeatures`] are only known once the new view is
+/// ready to show; they arrive as
+/// [`NavigationEvent::ReadyToShow`](crate::NavigationEvent::ReadyToShow)
+/// on the new view.
+#[derive(Debug)]
+pub struct NewWindowRequest {
+    url: String,
+    frame_name: Option<String>,
+    user_gesture: bool,
+    /// The web view that asked for the window
+    opener: *mut wpe_sys::WebKitWebView,
+}
+
+impl NewWindowRequest {
+    /// The URL the new window will load.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The target name passed to `window.open()` or the link's `target`.
+    #[must_use]
+    pub fn frame_name(&self) -> Option<&str> {
+        self.frame_name.as_deref()
+    }
+
+    /// Whether the request was triggered by a user gesture.
+    #[must_use]
+    pub fn is_user_gesture(&self) -> bool {
+        self.user_gesture
+    }
+
+    /// Create a native window related to the opener.
+    ///
+    /// Do not load a URL into it; WebKit loads the requested page once
+    /// the window is returned from the handler.
+    ///
+    /// # Errors
+    /// Returns an error if the window cannot be created.
+    pub fn create_native_window(&self, settings: WebViewSettings) -> Result<NativeWindow> {
+        NativeWindow::new_related(settings, self.opener)
+    }
+
+    /// Create a headless web view related to the opener.
+    ///
+    /// # Errors
+    /// Returns an error if the view cannot be created.
+    pub fn create_webview(
+        &self,
+        settings: WebViewSettings,
+        frame_buffer: SharedFrameBuffer,
+    ) -> Result<WebView> {
+        WebView::new_related(settings, frame_buffer, self.opener)
+    }
+}
+
+/// A window created in response to a [`NewWindowRequest`].
+pub enum NewWindow {
+    /// A native window
+    Native(NativeWindow),
+    /// A headless web view
+    WebView(WebView),
+}
+
+impl NewWindow {
+    fn raw_web_view(&self) -> *mut wpe_sys::WebKitWebView {
+        match self {
+            Self::Native(window) => window.raw_web_view(),
+            Self::WebView(view) => view.raw_web_view(),
+        }
+    }
+}
+
+impl std::fmt::Debug for NewWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Native(_) => f.write_str("NewWindow::Native"),
+            Self::WebView(_) => f.write_str("NewWindow::WebView"),
+        }
+    }
+}
+
+/// Handler deciding whether to open a new window.
+pub(crate) type CreateWindowHandler = Box<dyn Fn(&NewWindowRequest) -> Option<NewWindow>>;
+
+/// Popup state shared with the `create` signal handler.
+#[derive(Default)]
+pub(crate) struct PopupState {
+    handler: RefCell<Option<CreateWindowHandler>>,
+    windows: RefCell<VecDeque<NewWindow>>,
+}
+
+impl PopupState {
+    /// Install the handler, replacing any previous one.
+    pub(crate) fn set_handler(&self, handler: CreateWindowHandler) {
+        *self.handler.borrow_mut() = Some(handler);
+    }
+
+    /// Take the windows created since the last call.
+    pub(crate) fn take_windows(&self) -> Vec<NewWindow> {
+        self.windows.borrow_mut().drain(..).collect()
+    }
+}
+
+/// Signal handler for create.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_create(
+    web_view: *mut wpe_sys::WebKitWebView,
+    action: *mut wpe_sys::WebKitNavigationAction,
+    user_data: *mut std::ffi::c_void,
+) -> *mut wpe_sys::WebKitWebView {
+    if user_data.is_null() || action.is_null() {
+        return ptr::null_mut();
+    }
+
+    let state = &*(user_data as *const PopupState);
+    let Ok(handler) = state.handler.try_borrow() else {
+        tracing::warn!("New-window handler re-entered, denying request");
+        return ptr::null_mut();
+    };
+    let Some(handler) = handler.as_ref() else {
+        tracing::debug!("No new-window handler, denying request");
+        return ptr::null_mut();
+    };
+
+    let uri_request = wpe_sys::webkit_navigation_action_get_request(action);
+    let uri = if uri_request.is_null() {
+        ptr::null()
+    } else {
+        wpe_sys::webkit_uri_request_get_uri(uri_request)
+    };
+    let frame_name = wpe_sys::webkit_navigation_action_get_frame_name(action);
+
+    let request = NewWindowRequest {
+        url: if uri.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(uri).to_string_lossy().into_owned()
+        },
+        frame_name: (!frame_name.is_null())
+            .then(|| CStr::from_ptr(frame_name).to_string_lossy().into_owned()),
+        user_gesture: wpe_sys::webkit_navigation_action_is_user_gesture(action) != 0,
+        opener: web_view,
+    };
+
+    let Some(window) = handler(&request) else {
+        tracing::debug!("New window for {} denied", request.url);
+        return ptr::null_mut();
+    };
+
+    // The opener keeps the window alive until the app takes it
+    let new_web_view = window.raw_web_view();
+    state.windows.borrow_mut().push_back(window);
+    tracing::debug!("Opened new window for {}", request.url);
+    new_web_view
+}
+
+/// Handle new-window requests of `web_view` with the handler in `state`.
+///
+/// The returned pointer keeps the state alive for the signal handler and
+/// must be reclaimed with `Rc::from_raw` once `web_view` has been released.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn connect(
+    web_view: *mut wpe_sys::WebKitWebView,
+    state: &Rc<PopupState>,
+) -> *const PopupState {
+    let state_ptr = Rc::into_raw(Rc::clone(state));
+
+    let signal_name = CString::new("create").expect("static string has no NUL bytes");
+    wpe_sys::g_signal_connect_data(
+        web_view as *mut _,
+        signal_name.as_ptr(),
+        Some(std::mem::transmute::<
+            unsafe extern "C" fn(
+                *mut wpe_sys::WebKitWebView,
+                *mut wpe_sys::WebKitNavigationAction,
+                *mut std::ffi::c_void,
+            ) -> *mut wpe_sys::WebKitWebView,
+            unsafe extern "C" fn(),
+        >(on_create)),
+        state_ptr as *mut _,
+        None,
+        0,
+    );
+
+    state_ptr
+}
+
+/// Create a `WebKitWebView`, related to `related` if it is not null.
+///
+/// Related views share a web process, which WebKit requires for views
+/// returned from the `create` signal.
+///
+/// # Safety
+/// `related` must be null or a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn new_web_view(
+    related: *mut wpe_sys::WebKitWebView,
+) -> *mut wpe_sys::WebKitWebView {
+    if related.is_null() {
+        // Pass NULL for backend to use the Platform API
+        return wpe_sys::webkit_web_view_new(ptr::null_mut());
+    }
+
+    let property = CString::new("related-view").expect("static string has no NUL bytes");
+    wpe_sys::g_object_new(
+        wpe_sys::webkit_web_view_get_type(),
+        property.as_ptr(),
+        related,
+        ptr::null::<std::ffi::c_char>(),
+    ) as *mut wpe_sys::WebKitWebView
+}
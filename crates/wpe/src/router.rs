@@ -0,0 +1,1266 @@
+//! Command routing for IPC messages.
+//!
+//! [`IpcRouter`] dispatches [`FrontendMessage`]s to [`MessageHandler`]s by
+//! message type and builds the [`BackendMessage`] that answers a `wpe.call()`
+//! request, including an error response for unknown commands.
+//!
+//! Handlers may also be asynchronous. Their futures are polled on the thread
+//! that drives the router (the GLib/winit loop), so they must not block; move
+//! blocking file or database work to another thread and await its result.
+//!
+//! Async handlers registered with [`IpcRouter::command_async_with_context`]
+//! get a [`CallContext`] to report progress and to notice when the page
+//! aborts the call:
+//!
+//! ```rust,ignore
+//! router.command_async_with_context("export", |path: String, call: CallContext| async move {
+//!     // The worker calls `call.progress(&percent)` and stops once `call.is_cancelled()`
+//!     spawn_export(path, call).await
+//! });
+//! ```
+//!
+//! ```javascript
+//! const controller = new AbortController();
+//! await wpe.call('export', path, {
+//!     signal: controller.signal,
+//!     onProgress: (percent) => bar.value = percent,
+//! });
+//! ```
+//!
+//! Commands can be limited to the origins allowed to call them, so remote
+//! pages can't reach commands meant for the app's own pages. The origin is
+//! taken from WebKit, not from the message; messages only carry one when
+//! the bridge runs in an isolated script world or arrives over `wpe://`, so
+//! restricted commands need `WebViewSettings::with_script_world`:
+//!
+//! ```rust,ignore
+//! router.command("read_file", read_file);
+//! router.allow_origins("read_file", ["app://localhost"]);
+//! ```
+//!
+//! The router can also describe its commands to the frontend as TypeScript,
+//! so a renamed command or field fails the frontend build instead of timing
+//! out at runtime:
+//!
+//! ```rust,ignore
+//! router.command("add", |(a, b): (i64, i64)| Ok(a + b));
+//! std::fs::write("web/src/wpe.d.ts", router.typescript_definitions("wpe"))?;
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::ipc::{BackendMessage, FrontendMessage, MessageHandler};
+use crate::policy;
+use crate::typescript::{self, TraceFn, TsType, TypeRegistry};
+use crate::{Error, Result};
+
+/// Routes frontend messages to handlers registered by message type.
+///
+/// ```rust,ignore
+/// let mut router = IpcRouter::new();
+/// router.command("add", |(a, b): (i64, i64)| Ok(a + b));
+///
+/// WpeApp::with_router(settings, router).run()
+/// ```
+#[derive(Default)]
+pub struct IpcRouter {
+    handlers: HashMap<String, Handler>,
+    /// Origins allowed to call each restricted command
+    origins: HashMap<String, OriginList>,
+    /// Origins allowed to call commands without their own list
+    default_origins: Option<OriginList>,
+    /// Async calls that have not completed yet
+    pending: RefCell<Vec<PendingCall>>,
+    /// Navigation count seen by the last `sync_navigation` call
+    navigation: Cell<u64>,
+    /// Argument and result types of commands, for TypeScript generation
+    signatures: HashMap<String, Signature>,
+    /// Progress reported by async calls since the last `poll_pending`
+    outbox: Outbox,
+    /// Wakes the loop driving the router when a call needs polling
+    loop_waker: LoopWaker,
+}
+
+/// Describes a command's argument and result types.
+#[derive(Default)]
+struct Signature {
+    args: Option<TraceFn>,
+    result: Option<TraceFn>,
+}
+
+/// Sent by the bridge when a `wpe.call()` is aborted.
+const CANCEL: &str = "__wpe_cancel";
+
+#[derive(serde::Deserialize)]
+struct CancelPayload {
+    id: String,
+}
+
+/// Boxed future returned by async handlers.
+type LocalFuture = Pin<Box<dyn Future<Output = Result<serde_json::Value>>>>;
+
+/// Type-erased async handler: validates the payload and starts the call.
+type AsyncHandler = Box<dyn Fn(serde_json::Value, CallContext) -> Result<LocalFuture>>;
+
+/// Progress reports waiting to be sent.
+type Outbox = Arc<Mutex<Vec<BackendMessage>>>;
+
+/// A registered handler.
+enum Handler {
+    Sync(Box<dyn MessageHandler>),
+    Async(AsyncHandler),
+}
+
+/// An in-flight async call.
+struct PendingCall {
+    message_type: String,
+    request_id: Option<String>,
+    /// Origin of the sender, which alone may cancel the call
+    origin: Option<String>,
+    future: LocalFuture,
+    waker: Arc<CallWaker>,
+    cancellation: CancellationToken,
+}
+
+/// Flag telling a handler that its call was cancelled.
+///
+/// Calls are cancelled when the page aborts them or navigates away, which
+/// drops them without a response, or when [`IpcRouter::cancel_pending`] is
+/// called, which answers them with an error. The token is set first, so
+/// work the handler moved to other threads can check it and stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Whether the call has been cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// A call being handled by a [`command_async_with_context`](IpcRouter::command_async_with_context)
+/// handler.
+///
+/// Contexts are `Send`, so they can be moved to worker threads.
+#[derive(Clone)]
+pub struct CallContext {
+    request_id: Option<String>,
+    cancellation: CancellationToken,
+    outbox: Outbox,
+    loop_waker: LoopWaker,
+}
+
+impl CallContext {
+    /// The `wpe.call()` request ID, or `None` for a `wpe.send()` message.
+    #[must_use]
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    /// The call's cancellation token.
+    #[must_use]
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Whether the call has been cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Report intermediate progress to the call's `onProgress` callback.
+    ///
+    /// Reports are sent by the next [`IpcRouter::poll_pending`], ahead of the
+    /// call's response. They are dropped for cancelled calls and messages sent
+    /// without a request ID.
+    ///
+    /// # Errors
+    /// Returns an error if `value` cannot be serialized.
+    pub fn progress<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        let Some(request_id) = &self.request_id else {
+            return Ok(());
+        };
+        if self.is_cancelled() {
+            return Ok(());
+        }
+
+        let message = BackendMessage::progress(request_id.clone(), serde_json::to_value(value)?);
+        if let Ok(mut outbox) = self.outbox.lock() {
+            outbox.push(message);
+        }
+        self.loop_waker.wake();
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for CallContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallContext")
+            .field("request_id", &self.request_id)
+            .field("cancellation", &self.cancellation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Waker that flags its call for polling on the next `poll_pending`.
+///
+/// Waking also wakes the loop driving the router, so a loop blocked in
+/// [`NativeWindow::run_with_router`](crate::NativeWindow::run_with_router)
+/// or waiting for winit events polls the call promptly.
+struct CallWaker {
+    woken: AtomicBool,
+    loop_waker: LoopWaker,
+}
+
+impl Wake for CallWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.loop_waker.wake();
+    }
+}
+
+/// Wakes the event loop that drives a router, from any thread.
+///
+/// Defaults to waking the default GLib main context.
+#[derive(Clone)]
+struct LoopWaker(Arc<dyn Fn() + Send + Sync>);
+
+impl LoopWaker {
+    fn wake(&self) {
+        (self.0)();
+    }
+}
+
+impl Default for LoopWaker {
+    fn default() -> Self {
+        Self(Arc::new(wake_main_context))
+    }
+}
+
+/// Interrupt a blocking iteration of the default GLib main context.
+#[allow(unsafe_code)]
+fn wake_main_context() {
+    // SAFETY: g_main_context_wakeup is thread-safe and the default context
+    // lives for the whole process.
+    unsafe {
+        wpe_sys::g_main_context_wakeup(wpe_sys::g_main_context_default());
+    }
+}
+
+impl IpcRouter {
+    /// Create an empty router.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for its [`MessageHandler::message_type`].
+    ///
+    /// Replaces any handler previously registered for the same type. Its
+    /// argument and result types are `unknown` in generated TypeScript.
+    pub fn register<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: MessageHandler + 'static,
+    {
+        let message_type = handler.message_type().to_string();
+        *self.signature(&message_type) = Signature::default();
+        self.handlers
+            .insert(message_type, Handler::Sync(Box::new(handler)));
+        self
+    }
+
+    /// Register a typed command handler.
+    ///
+    /// The message payload is deserialized into `A` and the returned value is
+    /// serialized as the `wpe.call()` result. Both types are recorded for
+    /// TypeScript generation, which is why `R` must also be deserializable.
+    pub fn command<A, R, F>(&mut self, message_type: impl Into<String>, handler: F) -> &mut Self
+    where
+        A: DeserializeOwned + 'static,
+        R: Serialize + DeserializeOwned + 'static,
+        F: Fn(A) -> Result<R> + 'static,
+    {
+        let message_type = message_type.into();
+        self.register(TypedHandler {
+            message_type: message_type.clone(),
+            handler,
+            _marker: PhantomData,
+        });
+        self.record_signature::<A, R>(&message_type);
+        self
+    }
+
+    /// Register a typed async command handler.
+    ///
+    /// The handler returns a future; the response is sent once it resolves.
+    /// Any number of calls may be in flight at once. Pending calls are dropped
+    /// (and never answered) when the page navigates away, and rejected when
+    /// [`cancel_pending`](Self::cancel_pending) is called.
+    ///
+    /// Futures only make progress while something drives the router:
+    /// `NativeWindow::run_with_router`, a `step_with_router` loop, or
+    /// `WpeApp::with_router`. `NativeWindow::run` does not poll them.
+    pub fn command_async<A, R, F, Fut>(
+        &mut self,
+        message_type: impl Into<String>,
+        handler: F,
+    ) -> &mut Self
+    where
+        A: DeserializeOwned + 'static,
+        R: Serialize + DeserializeOwned + 'static,
+        F: Fn(A) -> Fut + 'static,
+        Fut: Future<Output = Result<R>> + 'static,
+    {
+        let message_type = message_type.into();
+        let name = message_type.clone();
+
+        let handler: AsyncHandler = Box::new(move |payload, _| {
+            let args = parse_args(&name, payload)?;
+            let call = handler(args);
+            Ok(Box::pin(async move { Ok(serde_json::to_value(call.await?)?) }) as LocalFuture)
+        });
+
+        self.record_signature::<A, R>(&message_type);
+        self.handlers.insert(message_type, Handler::Async(handler));
+        self
+    }
+
+    /// Register a typed async command handler that receives a [`CallContext`].
+    ///
+    /// Like [`command_async`](Self::command_async), but the handler can report
+    /// progress to the page and check whether the call was cancelled, e.g.
+    /// from a worker thread doing the actual work.
+    pub fn command_async_with_context<A, R, F, Fut>(
+        &mut self,
+        message_type: impl Into<String>,
+        handler: F,
+    ) -> &mut Self
+    where
+        A: DeserializeOwned + 'static,
+        R: Serialize + DeserializeOwned + 'static,
+        F: Fn(A, CallContext) -> Fut + 'static,
+        Fut: Future<Output = Result<R>> + 'static,
+    {
+        let message_type = message_type.into();
+        let name = message_type.clone();
+
+        let handler: AsyncHandler = Box::new(move |payload, context| {
+            let args = parse_args(&name, payload)?;
+            let call = handler(args, context);
+            Ok(Box::pin(async move { Ok(serde_json::to_value(call.await?)?) }) as LocalFuture)
+        });
+
+        self.record_signature::<A, R>(&message_type);
+        self.handlers.insert(message_type, Handler::Async(handler));
+        self
+    }
+
+    /// Override the result type recorded for `message_type`.
+    ///
+    /// Typed commands record their result type at registration; this is for
+    /// handlers that return `serde_json::Value` or were added with
+    /// [`register`](Self::register), whose results are otherwise `unknown`.
+    /// Nothing checks that `R` matches what the handler returns.
+    pub fn result_type<R>(&mut self, message_type: impl Into<String>) -> &mut Self
+    where
+        R: DeserializeOwned + 'static,
+    {
+        self.signature(&message_type.into()).result = Some(typescript::trace::<R>);
+        self
+    }
+
+    /// Generate a TypeScript declaration file for the registered commands.
+    ///
+    /// Declares the types used by command arguments and results, a `Commands`
+    /// interface mapping each command to them, and the bridge as
+    /// `window[global_name]` (the `IpcConfig::global_name` of the views the
+    /// router serves). Types are described through their `Deserialize`
+    /// implementations, so serde renames and skipped fields are honored.
+    /// Types serde can't describe without data come out as `unknown`:
+    /// `serde_json::Value`, internally tagged and untagged enums,
+    /// `#[serde(flatten)]`, and types parsed from strings.
+    #[must_use]
+    pub fn typescript_definitions(&self, global_name: &str) -> String {
+        let (registry, commands) = self.trace_commands();
+        typescript::definitions(&registry, &commands, global_name)
+    }
+
+    /// Generate a TypeScript module with the declarations and a typed client.
+    ///
+    /// The module exports a `commands` object with one function per command,
+    /// e.g. `commands.add([1, 2])` for `wpe.call("add", [1, 2])`.
+    #[must_use]
+    pub fn typescript_client(&self, global_name: &str) -> String {
+        let (registry, commands) = self.trace_commands();
+        typescript::client(&registry, &commands, global_name)
+    }
+
+    fn trace_commands(&self) -> (TypeRegistry, Vec<typescript::Command>) {
+        let mut registry = TypeRegistry::default();
+        let mut names: Vec<&String> = self.handlers.keys().collect();
+        names.sort();
+
+        let commands = names
+            .into_iter()
+            .map(|name| {
+                let signature = self.signatures.get(name);
+                let mut trace = |f: Option<TraceFn>| match f {
+                    Some(f) => f(&mut registry),
+                    None => TsType::Unknown,
+                };
+                typescript::Command {
+                    name: name.clone(),
+                    args: trace(signature.and_then(|s| s.args)),
+                    result: trace(signature.and_then(|s| s.result)),
+                }
+            })
+            .collect();
+        (registry, commands)
+    }
+
+    /// Record the argument and result types of a typed command.
+    fn record_signature<A, R>(&mut self, message_type: &str)
+    where
+        A: DeserializeOwned + 'static,
+        R: DeserializeOwned + 'static,
+    {
+        *self.signature(message_type) = Signature {
+            args: Some(typescript::trace::<A>),
+            result: Some(typescript::trace::<R>),
+        };
+    }
+
+    fn signature(&mut self, message_type: &str) -> &mut Signature {
+        self.signatures.entry(message_type.to_string()).or_default()
+    }
+
+    /// Wake the loop driving this router with `wake` instead of the default
+    /// GLib main context, e.g. through a winit `EventLoopProxy`.
+    ///
+    /// Applies to calls dispatched after this.
+    pub(crate) fn set_loop_waker<W>(&mut self, wake: W)
+    where
+        W: Fn() + Send + Sync + 'static,
+    {
+        self.loop_waker = LoopWaker(Arc::new(wake));
+    }
+
+    /// Only accept `message_type` from the given origins.
+    ///
+    /// Entries are origins such as `app://localhost`, full URLs (only their
+    /// origin is used), `https://*.example.com` for a domain and its
+    /// subdomains, or `*` for any origin. Calls from other origins, or
+    /// without a known origin (see [`FrontendMessage::origin`]), get an error
+    /// response without reaching the handler.
+    pub fn allow_origins<I, S>(&mut self, message_type: impl Into<String>, origins: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.origins
+            .insert(message_type.into(), OriginList::new(origins));
+        self
+    }
+
+    /// Only accept commands without their own [`allow_origins`](Self::allow_origins)
+    /// list from the given origins.
+    ///
+    /// By default such commands accept any origin.
+    pub fn default_origins<I, S>(&mut self, origins: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.default_origins = Some(OriginList::new(origins));
+        self
+    }
+
+    /// Whether `message` may be dispatched given the origin it came from.
+    #[must_use]
+    pub fn allows(&self, message: &FrontendMessage) -> bool {
+        match self
+            .origins
+            .get(&message.message_type)
+            .or(self.default_origins.as_ref())
+        {
+            Some(list) => list.allows(message.origin.as_deref()),
+            None => true,
+        }
+    }
+
+    /// Check whether a handler is registered for a message type.
+    #[must_use]
+    pub fn contains(&self, message_type: &str) -> bool {
+        self.handlers.contains_key(message_type)
+    }
+
+    /// Dispatch a message to its handler.
+    ///
+    /// Returns the response to send back when the message is a request
+    /// (carries a `_requestId`). Messages without a request ID are
+    /// fire-and-forget; handler errors for them are only logged.
+    ///
+    /// Async handlers are started here and answered later by
+    /// [`poll_pending`](Self::poll_pending), unless their payload is invalid.
+    /// Cancellation messages from aborted `wpe.call()`s are handled here too.
+    #[must_use]
+    pub fn dispatch(&self, message: &FrontendMessage) -> Option<BackendMessage> {
+        if !self.allows(message) {
+            let error = Error::IpcError(format!(
+                "Command '{}' is not allowed from {}",
+                message.message_type,
+                message.origin.as_deref().unwrap_or("an unknown origin")
+            ));
+            return respond(
+                &message.message_type,
+                message.request_id.clone(),
+                Err(error),
+            );
+        }
+
+        if message.message_type == CANCEL {
+            if let Ok(CancelPayload { id }) = serde_json::from_value(message.payload.clone()) {
+                self.cancel(message.origin.as_deref(), &id);
+            } else {
+                tracing::warn!("Invalid {} message", CANCEL);
+            }
+            return None;
+        }
+
+        // Any response would also reach the frame that owns the ID
+        if let Some(request_id) = message.request_id.as_deref() {
+            if self.is_pending(request_id) {
+                tracing::warn!(
+                    "Ignoring '{}' reusing the ID of a pending IPC call",
+                    message.message_type
+                );
+                return None;
+            }
+        }
+
+        let result = match self.handlers.get(&message.message_type) {
+            Some(Handler::Sync(handler)) => handler.handle(message.payload.clone()),
+            Some(Handler::Async(handler)) => {
+                let cancellation = CancellationToken::default();
+                let context = CallContext {
+                    request_id: message.request_id.clone(),
+                    cancellation: cancellation.clone(),
+                    outbox: Arc::clone(&self.outbox),
+                    loop_waker: self.loop_waker.clone(),
+                };
+                match handler(message.payload.clone(), context) {
+                    Ok(future) => {
+                        self.pending.borrow_mut().push(PendingCall {
+                            message_type: message.message_type.clone(),
+                            request_id: message.request_id.clone(),
+                            origin: message.origin.clone(),
+                            future,
+                            waker: Arc::new(CallWaker {
+                                woken: AtomicBool::new(true),
+                                loop_waker: self.loop_waker.clone(),
+                            }),
+                            cancellation,
+                        });
+                        return None;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            None => Err(Error::IpcError(format!(
+                "Unknown command: {}",
+                message.message_type
+            ))),
+        };
+
+        respond(&message.message_type, message.request_id.clone(), result)
+    }
+
+    /// Poll in-flight async calls, returning responses for those that completed.
+    ///
+    /// Call this once per iteration of your event loop; only calls whose
+    /// futures have been woken are polled. Progress reported since the last
+    /// poll is returned ahead of the responses.
+    #[must_use]
+    pub fn poll_pending(&self) -> Vec<BackendMessage> {
+        // Take the calls out so a handler dispatching re-entrantly cannot
+        // observe the list while it is borrowed.
+        let calls = std::mem::take(&mut *self.pending.borrow_mut());
+        let mut still_pending = Vec::with_capacity(calls.len());
+        let mut responses = Vec::new();
+
+        for mut call in calls {
+            if !call.waker.woken.swap(false, Ordering::AcqRel) {
+                still_pending.push(call);
+                continue;
+            }
+
+            let waker = Waker::from(call.waker.clone());
+            let mut cx = Context::from_waker(&waker);
+
+            match call.future.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => {
+                    responses.extend(respond(&call.message_type, call.request_id, result));
+                }
+                Poll::Pending => still_pending.push(call),
+            }
+        }
+
+        let mut pending = self.pending.borrow_mut();
+        still_pending.append(&mut pending);
+        *pending = still_pending;
+
+        let mut messages = match self.outbox.lock() {
+            Ok(mut outbox) => std::mem::take(&mut *outbox),
+            Err(_) => Vec::new(),
+        };
+        messages.append(&mut responses);
+        messages
+    }
+
+    /// Number of async calls still in flight.
+    #[must_use]
+    pub fn pending_count(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Whether an in-flight call answers `request_id`.
+    fn is_pending(&self, request_id: &str) -> bool {
+        self.pending
+            .borrow()
+            .iter()
+            .any(|call| call.request_id.as_deref() == Some(request_id))
+    }
+
+    /// Cancel all in-flight async calls by dropping their futures.
+    ///
+    /// Their unsent progress reports are discarded, and the next
+    /// [`poll_pending`](Self::poll_pending) answers each of them with a
+    /// "Call cancelled" error, rejecting its `wpe.call` promise.
+    pub fn cancel_pending(&self) {
+        let responses: Vec<BackendMessage> = self
+            .drop_pending()
+            .into_iter()
+            .filter_map(|call| {
+                call.request_id
+                    .map(|id| BackendMessage::error_response(id, "Call cancelled"))
+            })
+            .collect();
+        if responses.is_empty() {
+            return;
+        }
+        if let Ok(mut outbox) = self.outbox.lock() {
+            outbox.extend(responses);
+        }
+        self.loop_waker.wake();
+    }
+
+    /// Cancel all in-flight async calls without answering them.
+    fn drop_pending(&self) -> Vec<PendingCall> {
+        let cancelled = std::mem::take(&mut *self.pending.borrow_mut());
+        for call in &cancelled {
+            call.cancellation.cancel();
+        }
+        if let Ok(mut outbox) = self.outbox.lock() {
+            outbox.clear();
+        }
+        if !cancelled.is_empty() {
+            tracing::debug!("Cancelled {} pending IPC calls", cancelled.len());
+        }
+        cancelled
+    }
+
+    /// Cancel the in-flight call `origin` made as `request_id`, if any.
+    ///
+    /// Request IDs are only unique per frame, so a call can only be
+    /// cancelled by a sender with the origin that made it.
+    fn cancel(&self, origin: Option<&str>, request_id: &str) {
+        let cancelled: Vec<PendingCall> = {
+            let mut pending = self.pending.borrow_mut();
+            let (cancelled, kept) = std::mem::take(&mut *pending).into_iter().partition(|call| {
+                call.origin.as_deref() == origin && call.request_id.as_deref() == Some(request_id)
+            });
+            *pending = kept;
+            cancelled
+        };
+        if cancelled.is_empty() {
+            tracing::debug!("No pending IPC call to cancel for {}", request_id);
+            return;
+        }
+
+        for call in &cancelled {
+            call.cancellation.cancel();
+            tracing::debug!("Cancelled IPC call '{}'", call.message_type);
+        }
+        if let Ok(mut outbox) = self.outbox.lock() {
+            outbox.retain(|message| message.response_id.as_deref() != Some(request_id));
+        }
+    }
+
+    /// Cancel in-flight calls if the page navigated since the last sync.
+    ///
+    /// `navigation_count` is the number of committed navigations of the view
+    /// this router serves.
+    pub(crate) fn sync_navigation(&self, navigation_count: u64) {
+        // The page that made the calls is gone, so nothing is answered
+        if self.navigation.replace(navigation_count) != navigation_count {
+            let _ = self.drop_pending();
+        }
+    }
+}
+
+/// Origin patterns a command accepts.
+#[derive(Debug, Clone, Default)]
+struct OriginList {
+    patterns: Vec<String>,
+}
+
+impl OriginList {
+    fn new<I, S>(origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            patterns: origins
+                .into_iter()
+                .filter_map(|o| match o.as_ref() {
+                    "*" => Some("*".to_string()),
+                    other => policy::origin(other),
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether a message from `origin` is accepted.
+    ///
+    /// Messages without an origin (or with an opaque `null` one) only match `*`.
+    fn allows(&self, origin: Option<&str>) -> bool {
+        let origin = origin.and_then(policy::origin);
+        self.patterns.iter().any(|pattern| {
+            pattern == "*"
+                || origin
+                    .as_deref()
+                    .is_some_and(|o| origin_matches(pattern, o))
+        })
+    }
+}
+
+/// Match a normalized origin against a pattern, with `*.` host wildcards.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let (Some((pattern_scheme, pattern_host)), Some((scheme, host))) =
+        (pattern.split_once("://"), origin.split_once("://"))
+    else {
+        return false;
+    };
+    if pattern_scheme != scheme {
+        return false;
+    }
+
+    match pattern_host.strip_prefix("*.") {
+        Some(domain) => {
+            host == domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.'))
+        }
+        None => host == pattern_host,
+    }
+}
+
+/// Build the response for a finished call, if it was a request.
+fn respond(
+    message_type: &str,
+    request_id: Option<String>,
+    result: Result<serde_json::Value>,
+) -> Option<BackendMessage> {
+    let Some(request_id) = request_id else {
+        if let Err(e) = result {
+            tracing::warn!("Handler for '{}' failed: {}", message_type, e);
+        }
+        return None;
+    };
+
+    Some(match result {
+        Ok(value) => BackendMessage::response(request_id, value),
+        Err(e) => BackendMessage::error_response(request_id, error_message(&e)),
+    })
+}
+
+/// Deserialize a command payload, reporting failures to the caller.
+fn parse_args<A: DeserializeOwned>(message_type: &str, payload: serde_json::Value) -> Result<A> {
+    serde_json::from_value(payload)
+        .map_err(|e| Error::IpcError(format!("Invalid payload for '{message_type}': {e}")))
+}
+
+/// Message reported to JavaScript for a failed call.
+fn error_message(error: &Error) -> String {
+    match error {
+        Error::IpcError(message) => message.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Adapter turning a typed closure into a [`MessageHandler`].
+struct TypedHandler<A, R, F> {
+    message_type: String,
+    handler: F,
+    _marker: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R, F> MessageHandler for TypedHandler<A, R, F>
+where
+    A: DeserializeOwned,
+    R: Serialize,
+    F: Fn(A) -> Result<R>,
+{
+    fn message_type(&self) -> &str {
+        &self.message_type
+    }
+
+    fn handle(&self, payload: serde_json::Value) -> Result<serde_json::Value> {
+        let args = parse_args(&self.message_type, payload)?;
+        let ret = (self.handler)(args)?;
+        Ok(serde_json::to_value(ret)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::rc::Rc;
+
+    fn request(message_type: &str, payload: serde_json::Value) -> FrontendMessage {
+        FrontendMessage {
+            message_type: message_type.to_string(),
+            payload,
+            request_id: Some("req1".to_string()),
+            origin: None,
+        }
+    }
+
+    fn request_from(message_type: &str, origin: &str) -> FrontendMessage {
+        FrontendMessage {
+            origin: Some(origin.to_string()),
+            ..request(message_type, serde_json::Value::Null)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Greet {
+        name: String,
+    }
+
+    #[test]
+    fn test_router_typed_command() {
+        let mut router = IpcRouter::new();
+        router.command("greet", |args: Greet| Ok(format!("Hello, {}!", args.name)));
+
+        let response = router
+            .dispatch(&request("greet", serde_json::json!({"name": "WPE"})))
+            .unwrap();
+        assert_eq!(response.response_id, Some("req1".to_string()));
+        assert_eq!(response.result, Some(serde_json::json!("Hello, WPE!")));
+        assert!(response.error.is_none());
+    }
+
+    #[test]
+    fn test_router_unknown_command() {
+        let router = IpcRouter::new();
+        let response = router
+            .dispatch(&request("missing", serde_json::Value::Null))
+            .unwrap();
+        assert!(response.result.is_none());
+        assert_eq!(response.error, Some("Unknown command: missing".to_string()));
+    }
+
+    #[test]
+    fn test_router_invalid_payload() {
+        let mut router = IpcRouter::new();
+        router.command("greet", |args: Greet| Ok(args.name));
+
+        let response = router
+            .dispatch(&request("greet", serde_json::json!({"nom": 1})))
+            .unwrap();
+        assert!(response
+            .error
+            .unwrap()
+            .starts_with("Invalid payload for 'greet'"));
+    }
+
+    #[test]
+    fn test_router_handler_error() {
+        let mut router = IpcRouter::new();
+        router.command("fail", |(): ()| -> Result<()> {
+            Err(Error::IpcError("nope".to_string()))
+        });
+
+        let response = router
+            .dispatch(&request("fail", serde_json::Value::Null))
+            .unwrap();
+        assert_eq!(response.error, Some("nope".to_string()));
+    }
+
+    #[test]
+    fn test_router_fire_and_forget() {
+        let mut router = IpcRouter::new();
+        router.command("log", |_: serde_json::Value| Ok(()));
+
+        let message = FrontendMessage {
+            message_type: "log".to_string(),
+            payload: serde_json::json!("hi"),
+            request_id: None,
+            origin: None,
+        };
+        assert!(router.dispatch(&message).is_none());
+    }
+
+    #[test]
+    fn test_router_origin_restrictions() {
+        let mut router = IpcRouter::new();
+        router.command("read", |(): ()| Ok("secret".to_string()));
+        router.command("ping", |(): ()| Ok("pong".to_string()));
+        router.allow_origins(
+            "read",
+            ["app://localhost/index.html", "https://*.example.com"],
+        );
+
+        let allowed = router
+            .dispatch(&request_from("read", "app://localhost"))
+            .unwrap();
+        assert_eq!(allowed.result, Some(serde_json::json!("secret")));
+        assert!(router.allows(&request_from("read", "https://example.com")));
+        assert!(router.allows(&request_from("read", "https://api.example.com:443")));
+        assert!(!router.allows(&request_from("read", "https://badexample.com")));
+        assert!(!router.allows(&request_from("read", "http://example.com")));
+        assert!(!router.allows(&request("read", serde_json::Value::Null)));
+
+        let denied = router
+            .dispatch(&request_from("read", "https://evil.test"))
+            .unwrap();
+        assert!(denied.result.is_none());
+        assert_eq!(
+            denied.error.as_deref(),
+            Some("Command 'read' is not allowed from https://evil.test")
+        );
+
+        // Unrestricted commands accept any origin until a default is set
+        assert!(router.allows(&request_from("ping", "https://evil.test")));
+        router.default_origins(["app://localhost"]);
+        assert!(!router.allows(&request_from("ping", "https://evil.test")));
+        assert!(router.allows(&request_from("ping", "app://localhost")));
+
+        router.allow_origins("ping", ["*"]);
+        assert!(router.allows(&request("ping", serde_json::Value::Null)));
+    }
+
+    #[test]
+    fn test_router_rejects_forged_origin() {
+        let mut router = IpcRouter::new();
+        router.command("read", |(): ()| Ok("secret".to_string()));
+        router.allow_origins("read", ["app://localhost"]);
+
+        // A page claiming the app's origin in the message itself
+        let forged: FrontendMessage =
+            serde_json::from_str(r#"{"type":"read","_requestId":"1","_origin":"app://localhost"}"#)
+                .unwrap();
+        let response = router.dispatch(&forged).unwrap();
+        assert!(response.result.is_none());
+        assert_eq!(
+            response.error.as_deref(),
+            Some("Command 'read' is not allowed from an unknown origin")
+        );
+    }
+
+    struct Ping;
+
+    impl MessageHandler for Ping {
+        fn message_type(&self) -> &str {
+            "ping"
+        }
+
+        fn handle(&self, _payload: serde_json::Value) -> Result<serde_json::Value> {
+            Ok(serde_json::json!("pong"))
+        }
+    }
+
+    #[test]
+    fn test_router_register_message_handler() {
+        let mut router = IpcRouter::new();
+        router.register(Ping);
+        assert!(router.contains("ping"));
+
+        let response = router
+            .dispatch(&request("ping", serde_json::Value::Null))
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("pong")));
+    }
+
+    /// Future that stays pending until its flag is set.
+    struct Gate(Rc<Cell<bool>>);
+
+    impl Future for Gate {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0.get() {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn test_router_async_command() {
+        let mut router = IpcRouter::new();
+        router.command_async("double", |n: i64| async move { Ok(n * 2) });
+
+        assert!(router
+            .dispatch(&request("double", serde_json::json!(21)))
+            .is_none());
+        assert_eq!(router.pending_count(), 1);
+
+        let responses = router.poll_pending();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].result, Some(serde_json::json!(42)));
+        assert_eq!(router.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_router_async_in_flight() {
+        let open = Rc::new(Cell::new(false));
+        let gate = open.clone();
+
+        let mut router = IpcRouter::new();
+        router.command_async("wait", move |(): ()| {
+            let gate = Gate(gate.clone());
+            async move {
+                gate.await;
+                Ok("done".to_string())
+            }
+        });
+
+        let _ = router.dispatch(&request("wait", serde_json::Value::Null));
+        let second = FrontendMessage {
+            request_id: Some("req2".to_string()),
+            ..request("wait", serde_json::Value::Null)
+        };
+        let _ = router.dispatch(&second);
+        assert!(router.poll_pending().is_empty());
+        assert_eq!(router.pending_count(), 2);
+
+        // A request reusing a pending ID is ignored
+        assert!(router
+            .dispatch(&request("wait", serde_json::Value::Null))
+            .is_none());
+        assert_eq!(router.pending_count(), 2);
+
+        open.set(true);
+        assert_eq!(router.poll_pending().len(), 2);
+    }
+
+    #[test]
+    fn test_router_progress_and_cancel() {
+        let contexts = Rc::new(RefCell::new(Vec::new()));
+        let seen = contexts.clone();
+
+        let mut router = IpcRouter::new();
+        router.command_async_with_context("export", move |(): (), call: CallContext| {
+            call.progress(&0).unwrap();
+            seen.borrow_mut().push(call);
+            std::future::pending::<Result<()>>()
+        });
+
+        let _ = router.dispatch(&request("export", serde_json::Value::Null));
+        let call = contexts.borrow()[0].clone();
+        call.progress(&50).unwrap();
+
+        let messages = router.poll_pending();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.progress));
+        assert_eq!(messages[1].response_id.as_deref(), Some("req1"));
+        assert_eq!(messages[1].payload, serde_json::json!(50));
+        assert_eq!(
+            serde_json::to_value(&messages[1]).unwrap(),
+            serde_json::json!({"type": "progress", "payload": 50, "_responseId": "req1", "progress": true})
+        );
+
+        // Aborting in JS cancels the call and drops later progress
+        call.progress(&75).unwrap();
+        let cancel = FrontendMessage {
+            message_type: CANCEL.to_string(),
+            payload: serde_json::json!({"id": "req1"}),
+            request_id: None,
+            origin: None,
+        };
+        assert!(router.dispatch(&cancel).is_none());
+        assert!(call.is_cancelled());
+        assert_eq!(router.pending_count(), 0);
+        call.progress(&100).unwrap();
+        assert!(router.poll_pending().is_empty());
+    }
+
+    #[test]
+    fn test_router_progress_precedes_response() {
+        let mut router = IpcRouter::new();
+        router.command_async_with_context("import", |n: u32, call: CallContext| async move {
+            for i in 0..n {
+                call.progress(&i)?;
+            }
+            Ok(n)
+        });
+
+        let _ = router.dispatch(&request("import", serde_json::json!(2)));
+        let messages = router.poll_pending();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].progress && messages[1].progress);
+        assert_eq!(messages[2].result, Some(serde_json::json!(2)));
+        assert!(!messages[2].progress);
+    }
+
+    #[test]
+    fn test_router_async_invalid_payload() {
+        let mut router = IpcRouter::new();
+        router.command_async("double", |n: i64| async move { Ok(n * 2) });
+
+        let response = router
+            .dispatch(&request("double", serde_json::json!("x")))
+            .unwrap();
+        assert!(response.error.is_some());
+        assert_eq!(router.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_router_cancel_on_navigation() {
+        let mut router = IpcRouter::new();
+        router.command_async("never", |(): ()| async {
+            std::future::pending::<Result<()>>().await
+        });
+
+        let _ = router.dispatch(&request("never", serde_json::Value::Null));
+        router.sync_navigation(0);
+        assert_eq!(router.pending_count(), 1);
+
+        router.sync_navigation(1);
+        assert_eq!(router.pending_count(), 0);
+        assert!(router.poll_pending().is_empty());
+    }
+
+    #[test]
+    fn test_router_cancel_pending_rejects_calls() {
+        let mut router = IpcRouter::new();
+        router.command_async("never", |(): ()| async {
+            std::future::pending::<Result<()>>().await
+        });
+
+        let _ = router.dispatch(&request("never", serde_json::Value::Null));
+        router.cancel_pending();
+        assert_eq!(router.pending_count(), 0);
+
+        let messages = router.poll_pending();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].response_id.as_deref(), Some("req1"));
+        assert_eq!(messages[0].error.as_deref(), Some("Call cancelled"));
+        assert!(router.poll_pending().is_empty());
+    }
+
+    #[test]
+    fn test_router_cancel_requires_same_sender() {
+        let mut router = IpcRouter::new();
+        router.command_async("never", |(): ()| async {
+            std::future::pending::<Result<()>>().await
+        });
+        let _ = router.dispatch(&request_from("never", "app://localhost"));
+
+        let cancel_from = |origin: &str| FrontendMessage {
+            message_type: CANCEL.to_string(),
+            payload: serde_json::json!({"id": "req1"}),
+            request_id: None,
+            origin: Some(origin.to_string()),
+        };
+        assert!(router.dispatch(&cancel_from("https://evil.test")).is_none());
+        assert_eq!(router.pending_count(), 1);
+        assert!(router.dispatch(&cancel_from("app://localhost")).is_none());
+        assert_eq!(router.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_router_typescript() {
+        #[derive(Serialize, Deserialize)]
+        struct Entry {
+            #[serde(rename = "fileName")]
+            name: String,
+            size: Option<u64>,
+        }
+
+        let mut router = IpcRouter::new();
+        router.command("greet", |args: Greet| Ok(args.name));
+        router.command("ping", |(): ()| Ok(()));
+        router.command("list-dir", |path: String| {
+            Ok(vec![Entry {
+                name: path,
+                size: None,
+            }])
+        });
+        router.command("log", |_: String| Ok(serde_json::Value::Null));
+        router.result_type::<u32>("log");
+
+        let definitions = router.typescript_definitions("wpe");
+        assert!(definitions.contains("export interface Greet {\n    name: string;\n}\n"));
+        assert!(definitions.contains(
+            "export interface Entry {\n    fileName: string;\n    size: number | null;\n}\n"
+        ));
+        assert!(definitions.contains(
+            "export interface Commands {\n    greet: { args: Greet; result: string };\n    \
+             \"list-dir\": { args: string; result: Entry[] };\n    \
+             log: { args: string; result: number };\n    \
+             ping: { args: null; result: null };\n}\n"
+        ));
+        assert!(definitions.contains("interface Window {\n        wpe: Wpe;"));
+
+        let client = router.typescript_client("wpe");
+        assert!(client.starts_with(&definitions));
+        assert!(client.contains(
+            "    greet: (payload: Commands[\"greet\"][\"args\"], options?: CallOptions) => \
+             window.wpe.call(\"greet\", payload, options),\n"
+        ));
+        assert!(client.contains(
+            "    ping: (options?: CallOptions) => window.wpe.call(\"ping\", null, options),\n"
+        ));
+
+        let client = router.typescript_client("editor-bridge");
+        assert!(client.contains("interface Window {\n        \"editor-bridge\": Wpe;"));
+        assert!(client.contains("window[\"editor-bridge\"].call(\"ping\", null, options)"));
+        assert!(!client.contains("window.wpe"));
+
+        // Re-registering drops the recorded types
+        router.register(Ping);
+        assert!(router
+            .typescript_definitions("wpe")
+            .contains("    ping: { args: unknown; result: unknown };\n"));
+    }
+}
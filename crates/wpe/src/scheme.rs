@@ -0,0 +1,656 @@
+//! Custom URI scheme handlers.
+//!
+//! Schemes are registered on the default `WebKitWebContext`, so they are
+//! shared by every web view in the process. Handlers run on the GLib main
+//! thread whenever WebKit loads a URI with the registered scheme, once the
+//! request body (if any) has been read without blocking the loop.
+//!
+//! ```rust,ignore
+//! wpe::register_uri_scheme("myapp", |request: &wpe::SchemeRequest| {
+//!     match request.path() {
+//!         "/" | "/index.html" => wpe::SchemeResponse::new("<h1>Hello</h1>", "text/html"),
+//!         _ => wpe::SchemeResponse::not_found(),
+//!     }
+//! })?;
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::io::{Read, Write};
+use std::os::fd::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::ptr;
+use std::sync::Mutex;
+
+use crate::{Error, Result};
+
+/// Schemes handled by WebKit itself or reserved by this crate.
+const RESERVED_SCHEMES: &[&str] = &[
+    "about",
+    "blob",
+    "data",
+    "file",
+    "http",
+    "https",
+    "javascript",
+    "ws",
+    "wss",
+    crate::ipc::IPC_SCHEME,
+    crate::assets::ASSETS_SCHEME,
+];
+
+/// Schemes registered so far on the default web context.
+static REGISTERED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A request for a URI with a custom scheme.
+#[derive(Debug, Clone)]
+pub struct SchemeRequest {
+    uri: String,
+    path: String,
+    method: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    web_view: *mut wpe_sys::WebKitWebView,
+    page_origin: Option<String>,
+}
+
+impl SchemeRequest {
+    /// The full requested URI.
+    #[must_use]
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// The path component of the URI.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The HTTP method (`GET` for navigations and most subresources).
+    #[must_use]
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// All request headers.
+    #[must_use]
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Look up a request header (case-insensitive).
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The request body (empty for requests without one).
+    #[must_use]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The web view that issued the request.
+    pub(crate) fn web_view(&self) -> *mut wpe_sys::WebKitWebView {
+        self.web_view
+    }
+
+    /// The origin of the page loaded in the requesting web view when the
+    /// request was made.
+    pub(crate) fn page_origin(&self) -> Option<&str> {
+        self.page_origin.as_deref()
+    }
+
+    /// Copy the request data, except the body, out of a `WebKitURISchemeRequest`.
+    #[allow(unsafe_code)]
+    unsafe fn from_raw(request: *mut wpe_sys::WebKitURISchemeRequest) -> Self {
+        let method = copy_str(wpe_sys::webkit_uri_scheme_request_get_http_method(request));
+        let web_view = wpe_sys::webkit_uri_scheme_request_get_web_view(request);
+        let page_origin = if web_view.is_null() {
+            None
+        } else {
+            crate::policy::page_origin(web_view)
+        };
+
+        Self {
+            uri: copy_str(wpe_sys::webkit_uri_scheme_request_get_uri(request)),
+            path: copy_str(wpe_sys::webkit_uri_scheme_request_get_path(request)),
+            method: if method.is_empty() {
+                "GET".to_string()
+            } else {
+                method
+            },
+            headers: read_headers(wpe_sys::webkit_uri_scheme_request_get_http_headers(request)),
+            body: Vec::new(),
+            web_view,
+            page_origin,
+        }
+    }
+}
+
+/// The body of a [`SchemeResponse`].
+pub enum ResponseBody {
+    /// The complete body, held in memory.
+    Bytes(Vec<u8>),
+    /// A body read incrementally on a background thread.
+    Stream {
+        reader: Box<dyn Read + Send>,
+        /// Total length in bytes, if known.
+        length: Option<u64>,
+    },
+}
+
+impl std::fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Self::Stream { length, .. } => {
+                f.debug_struct("Stream").field("length", length).finish()
+            }
+        }
+    }
+}
+
+/// A response to a [`SchemeRequest`].
+#[derive(Debug)]
+pub struct SchemeResponse {
+    status: u16,
+    content_type: String,
+    headers: Vec<(String, String)>,
+    body: ResponseBody,
+}
+
+impl SchemeResponse {
+    /// Create a `200 OK` response with the given body and MIME type.
+    #[must_use]
+    pub fn new(body: impl Into<Vec<u8>>, content_type: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type: content_type.into(),
+            headers: Vec::new(),
+            body: ResponseBody::Bytes(body.into()),
+        }
+    }
+
+    /// Create a `200 OK` response whose body is streamed from `reader`.
+    ///
+    /// The reader runs on a background thread, so it may block.
+    #[must_use]
+    pub fn stream(reader: impl Read + Send + 'static, content_type: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type: content_type.into(),
+            headers: Vec::new(),
+            body: ResponseBody::Stream {
+                reader: Box::new(reader),
+                length: None,
+            },
+        }
+    }
+
+    /// Create an empty response with the given status code.
+    #[must_use]
+    pub fn empty(status: u16) -> Self {
+        Self::new(Vec::new(), "text/plain").with_status(status)
+    }
+
+    /// Create an empty `404 Not Found` response.
+    #[must_use]
+    pub fn not_found() -> Self {
+        Self::empty(404)
+    }
+
+    /// Set the HTTP status code.
+    #[must_use]
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Set the MIME type of the body.
+    #[must_use]
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = content_type.into();
+        self
+    }
+
+    /// Add a response header.
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the total length of a streamed body.
+    ///
+    /// Has no effect on in-memory bodies, whose length is always known.
+    #[must_use]
+    pub fn with_length(mut self, total: u64) -> Self {
+        if let ResponseBody::Stream { ref mut length, .. } = self.body {
+            *length = Some(total);
+        }
+        self
+    }
+
+    /// The HTTP status code.
+    #[must_use]
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The MIME type of the body.
+    #[must_use]
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The response headers.
+    #[must_use]
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The response body.
+    #[must_use]
+    pub fn body(&self) -> &ResponseBody {
+        &self.body
+    }
+}
+
+/// Trait for handling requests to a custom URI scheme.
+///
+/// Implemented for any `Fn(&SchemeRequest) -> SchemeResponse` closure.
+pub trait SchemeHandler: 'static {
+    /// Produce the response for a request.
+    fn handle(&self, request: &SchemeRequest) -> SchemeResponse;
+}
+
+impl<F> SchemeHandler for F
+where
+    F: Fn(&SchemeRequest) -> SchemeResponse + 'static,
+{
+    fn handle(&self, request: &SchemeRequest) -> SchemeResponse {
+        self(request)
+    }
+}
+
+/// Register a handler for a custom URI scheme such as `myapp`.
+///
+/// The scheme is registered on the default web context, so it applies to
+/// every web view, and is marked as secure and CORS-enabled. Register schemes
+/// before loading any URI that uses them.
+///
+/// # Errors
+/// Returns an error if the scheme name is invalid, reserved (including `wpe`,
+/// which is used by the IPC bridge, and `app`, which serves
+/// `WebViewSettings::with_assets`), or already registered.
+pub fn register_uri_scheme(scheme: &str, handler: impl SchemeHandler) -> Result<()> {
+    validate_scheme(scheme)?;
+    if RESERVED_SCHEMES.contains(&scheme) {
+        return Err(Error::UriSchemeError(format!(
+            "Scheme '{scheme}' is reserved"
+        )));
+    }
+    register(scheme, Box::new(handler))
+}
+
+/// Register a scheme handler without checking the reserved list.
+#[allow(unsafe_code)]
+pub(crate) fn register(scheme: &str, handler: Box<dyn SchemeHandler>) -> Result<()> {
+    let c_scheme = CString::new(scheme)
+        .map_err(|_| Error::UriSchemeError(format!("Invalid scheme: {scheme}")))?;
+
+    {
+        let mut registered = REGISTERED
+            .lock()
+            .map_err(|e| Error::UriSchemeError(e.to_string()))?;
+        if registered.iter().any(|s| s == scheme) {
+            return Err(Error::UriSchemeError(format!(
+                "Scheme '{scheme}' is already registered"
+            )));
+        }
+        registered.push(scheme.to_string());
+    }
+
+    // Double-box so the fat trait object pointer fits in a gpointer.
+    let user_data = Box::into_raw(Box::new(handler));
+
+    // SAFETY: The default web context lives for the rest of the process.
+    // user_data is only reclaimed by on_scheme_destroy when the context drops it.
+    unsafe {
+        let context = wpe_sys::webkit_web_context_get_default();
+        wpe_sys::webkit_web_context_register_uri_scheme(
+            context,
+            c_scheme.as_ptr(),
+            Some(on_scheme_request),
+            user_data as *mut _,
+            Some(on_scheme_destroy),
+        );
+
+        let security_manager = wpe_sys::webkit_web_context_get_security_manager(context);
+        if !security_manager.is_null() {
+            wpe_sys::webkit_security_manager_register_uri_scheme_as_secure(
+                security_manager,
+                c_scheme.as_ptr(),
+            );
+            wpe_sys::webkit_security_manager_register_uri_scheme_as_cors_enabled(
+                security_manager,
+                c_scheme.as_ptr(),
+            );
+        }
+    }
+
+    tracing::debug!("Registered URI scheme: {}", scheme);
+    Ok(())
+}
+
+/// Check that a scheme name follows RFC 3986 (`ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`).
+fn validate_scheme(scheme: &str) -> Result<()> {
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::UriSchemeError(format!("Invalid scheme: {scheme}")))
+    }
+}
+
+/// Callback for requests to a registered scheme.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_scheme_request(
+    request: *mut wpe_sys::WebKitURISchemeRequest,
+    user_data: *mut std::ffi::c_void,
+) {
+    if request.is_null() || user_data.is_null() {
+        return;
+    }
+
+    let handler = user_data as *const Box<dyn SchemeHandler>;
+    let scheme_request = SchemeRequest::from_raw(request);
+
+    let body = wpe_sys::webkit_uri_scheme_request_get_http_body(request);
+    if body.is_null() {
+        respond(&**handler, request, &scheme_request);
+        return;
+    }
+
+    // Read the body into memory asynchronously, then answer from on_body_read
+    let output = wpe_sys::g_memory_output_stream_new_resizable();
+    let pending = Box::new(PendingBody {
+        request: wpe_sys::g_object_ref(request as *mut _) as *mut _,
+        scheme_request,
+        handler,
+        body,
+    });
+    wpe_sys::g_output_stream_splice_async(
+        output,
+        body,
+        wpe_sys::GOutputStreamSpliceFlags_G_OUTPUT_STREAM_SPLICE_CLOSE_SOURCE
+            | wpe_sys::GOutputStreamSpliceFlags_G_OUTPUT_STREAM_SPLICE_CLOSE_TARGET,
+        0, // G_PRIORITY_DEFAULT
+        ptr::null_mut(),
+        Some(on_body_read),
+        Box::into_raw(pending) as *mut _,
+    );
+}
+
+/// A request waiting for its body to be read.
+struct PendingBody {
+    /// Reference to the WebKit request, released once it is answered
+    request: *mut wpe_sys::WebKitURISchemeRequest,
+    scheme_request: SchemeRequest,
+    /// The scheme's handler, which lives as long as the default web context
+    handler: *const Box<dyn SchemeHandler>,
+    /// The body stream, released once it has been read
+    body: *mut wpe_sys::GInputStream,
+}
+
+/// Completion callback for reading a request body.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_body_read(
+    source: *mut wpe_sys::GObject,
+    result: *mut wpe_sys::GAsyncResult,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+    let mut pending = Box::from_raw(user_data as *mut PendingBody);
+    let output = source as *mut wpe_sys::GOutputStream;
+
+    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+    if wpe_sys::g_output_stream_splice_finish(output, result, &mut error) < 0 && !error.is_null() {
+        let msg = CStr::from_ptr((*error).message);
+        tracing::warn!("Failed to read scheme request body: {:?}", msg);
+        wpe_sys::g_error_free(error);
+    }
+
+    let memory = output as *mut wpe_sys::GMemoryOutputStream;
+    let data = wpe_sys::g_memory_output_stream_get_data(memory);
+    let size = wpe_sys::g_memory_output_stream_get_data_size(memory) as usize;
+    if !data.is_null() && size > 0 {
+        pending.scheme_request.body = std::slice::from_raw_parts(data as *const u8, size).to_vec();
+    }
+    wpe_sys::g_object_unref(output as *mut _);
+    wpe_sys::g_object_unref(pending.body as *mut _);
+
+    respond(&**pending.handler, pending.request, &pending.scheme_request);
+    wpe_sys::g_object_unref(pending.request as *mut _);
+}
+
+/// Run `handler` for a request and complete it with the response.
+#[allow(unsafe_code)]
+unsafe fn respond(
+    handler: &dyn SchemeHandler,
+    request: *mut wpe_sys::WebKitURISchemeRequest,
+    scheme_request: &SchemeRequest,
+) {
+    let response = handler.handle(scheme_request);
+
+    tracing::trace!(
+        "{} {} -> {}",
+        scheme_request.method,
+        scheme_request.uri,
+        response.status
+    );
+
+    finish(request, response);
+}
+
+/// Destroy notify for a scheme handler's user data.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_scheme_destroy(user_data: *mut std::ffi::c_void) {
+    if !user_data.is_null() {
+        drop(Box::from_raw(user_data as *mut Box<dyn SchemeHandler>));
+    }
+}
+
+/// Complete a request with a response.
+#[allow(unsafe_code)]
+unsafe fn finish(request: *mut wpe_sys::WebKitURISchemeRequest, response: SchemeResponse) {
+    let (stream, length) = match response.body {
+        ResponseBody::Bytes(bytes) => {
+            let length = bytes.len() as i64;
+            let g_bytes = wpe_sys::g_bytes_new(bytes.as_ptr() as *const _, bytes.len() as u64);
+            let stream = wpe_sys::g_memory_input_stream_new_from_bytes(g_bytes);
+            wpe_sys::g_bytes_unref(g_bytes);
+            (stream, length)
+        }
+        ResponseBody::Stream { reader, length } => match spawn_stream(reader) {
+            Ok(fd) => (
+                wpe_sys::g_unix_input_stream_new(fd, 1),
+                length.map_or(-1, |l| l as i64),
+            ),
+            Err(e) => {
+                tracing::error!("Failed to stream scheme response: {}", e);
+                let g_bytes = wpe_sys::g_bytes_new(ptr::null(), 0);
+                let stream = wpe_sys::g_memory_input_stream_new_from_bytes(g_bytes);
+                wpe_sys::g_bytes_unref(g_bytes);
+                (stream, 0)
+            }
+        },
+    };
+
+    let scheme_response = wpe_sys::webkit_uri_scheme_response_new(stream, length);
+    wpe_sys::g_object_unref(stream as *mut _);
+
+    wpe_sys::webkit_uri_scheme_response_set_status(
+        scheme_response,
+        u32::from(response.status),
+        ptr::null(),
+    );
+
+    if let Ok(content_type) = CString::new(response.content_type) {
+        wpe_sys::webkit_uri_scheme_response_set_content_type(
+            scheme_response,
+            content_type.as_ptr(),
+        );
+    }
+
+    if !response.headers.is_empty() {
+        let headers = wpe_sys::soup_message_headers_new(
+            wpe_sys::SoupMessageHeadersType_SOUP_MESSAGE_HEADERS_RESPONSE,
+        );
+        for (name, value) in &response.headers {
+            if let (Ok(name), Ok(value)) =
+                (CString::new(name.as_str()), CString::new(value.as_str()))
+            {
+                wpe_sys::soup_message_headers_append(headers, name.as_ptr(), value.as_ptr());
+            }
+        }
+        // Takes ownership of the headers.
+        wpe_sys::webkit_uri_scheme_response_set_http_headers(scheme_response, headers);
+    }
+
+    wpe_sys::webkit_uri_scheme_request_finish_with_response(request, scheme_response);
+    wpe_sys::g_object_unref(scheme_response as *mut _);
+}
+
+/// Copy a streamed body into a socket on a background thread.
+///
+/// Returns the read end of the socket, which the caller takes ownership of.
+fn spawn_stream(mut reader: Box<dyn Read + Send>) -> std::io::Result<i32> {
+    let (read_end, mut write_end) = UnixStream::pair()?;
+
+    std::thread::Builder::new()
+        .name("wpe-scheme-stream".to_string())
+        .spawn(move || {
+            // WebKit closes the read end if the load is cancelled, which
+            // surfaces here as a write error.
+            if let Err(e) = std::io::copy(&mut reader, &mut write_end) {
+                tracing::debug!("Scheme response stream ended early: {}", e);
+            }
+            let _ = write_end.flush();
+        })?;
+
+    Ok(read_end.into_raw_fd())
+}
+
+/// Copy a string owned by WebKit (or return an empty string for NULL).
+#[allow(unsafe_code)]
+unsafe fn copy_str(ptr: *const std::ffi::c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+/// Collect the name/value pairs from a `SoupMessageHeaders`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn read_headers(headers: *mut wpe_sys::SoupMessageHeaders) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    if headers.is_null() {
+        return pairs;
+    }
+
+    let mut iter = std::mem::MaybeUninit::<wpe_sys::SoupMessageHeadersIter>::uninit();
+    wpe_sys::soup_message_headers_iter_init(iter.as_mut_ptr(), headers);
+
+    let mut name: *const std::ffi::c_char = ptr::null();
+    let mut value: *const std::ffi::c_char = ptr::null();
+    while wpe_sys::soup_message_headers_iter_next(iter.as_mut_ptr(), &mut name, &mut value) != 0 {
+        pairs.push((copy_str(name), copy_str(value)));
+    }
+
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_scheme() {
+        assert!(validate_scheme("app").is_ok());
+        assert!(validate_scheme("my-app+v1.2").is_ok());
+        assert!(validate_scheme("").is_err());
+        assert!(validate_scheme("1app").is_err());
+        assert!(validate_scheme("App").is_err());
+        assert!(validate_scheme("app://").is_err());
+    }
+
+    #[test]
+    fn test_reserved_schemes_rejected() {
+        assert!(matches!(
+            register_uri_scheme("wpe", |_: &SchemeRequest| SchemeResponse::not_found()),
+            Err(Error::UriSchemeError(_))
+        ));
+        assert!(matches!(
+            register_uri_scheme("https", |_: &SchemeRequest| SchemeResponse::not_found()),
+            Err(Error::UriSchemeError(_))
+        ));
+        assert!(matches!(
+            register_uri_scheme("app", |_: &SchemeRequest| SchemeResponse::not_found()),
+            Err(Error::UriSchemeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_response_builder() {
+        let response = SchemeResponse::new("<h1>Hi</h1>", "text/html")
+            .with_status(201)
+            .with_header("Cache-Control", "no-cache");
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.content_type(), "text/html");
+        assert_eq!(
+            response.headers(),
+            &[("Cache-Control".to_string(), "no-cache".to_string())]
+        );
+        assert!(matches!(response.body(), ResponseBody::Bytes(b) if b == b"<h1>Hi</h1>"));
+    }
+
+    #[test]
+    fn test_stream_length() {
+        let response = SchemeResponse::stream(std::io::empty(), "video/mp4").with_length(42);
+        assert!(matches!(
+            response.body(),
+            ResponseBody::Stream {
+                length: Some(42),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_request_header_lookup() {
+        let request = SchemeRequest {
+            uri: "app://localhost/".to_string(),
+            path: "/".to_string(),
+            method: "GET".to_string(),
+            headers: vec![("Range".to_string(), "bytes=0-9".to_string())],
+            body: Vec::new(),
+            web_view: ptr::null_mut(),
+            page_origin: None,
+        };
+
+        assert_eq!(request.header("range"), Some("bytes=0-9"));
+        assert_eq!(request.header("accept"), None);
+    }
+}
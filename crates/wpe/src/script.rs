@@ -0,0 +1,410 @@
+//! JavaScript evaluation with results.
+//!
+//! This module wraps `webkit_web_view_evaluate_javascript` with a completion
+//! callback, converting the resulting `JSCValue` to JSON and reporting
+//! exceptions thrown by the script as [`Error::JavaScriptException`].
+//!
+//! Results are delivered on the GLib main context, so they only arrive while
+//! the context is being iterated (`WebView::spin`, `NativeWindow::process_events`,
+//! and friends).
+//!
+//! Scripts run in the page's main world unless the web view was created with
+//! an isolated script world (`WebViewSettings::with_script_world`).
+
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use serde::de::DeserializeOwned;
+
+use crate::{Error, Result};
+
+/// Callback invoked with the JSON result of an evaluation.
+pub(crate) type ScriptCallback = Box<dyn FnOnce(Result<serde_json::Value>)>;
+
+/// Completion state shared between a pending evaluation and its future.
+struct Completion {
+    result: Option<Result<serde_json::Value>>,
+    waker: Option<Waker>,
+}
+
+/// A future resolving to the result of a JavaScript evaluation.
+///
+/// Returned by `WebView::evaluate_script_with_result` and
+/// `NativeWindow::evaluate_script_with_result`. The GLib main context must
+/// keep being iterated for the future to make progress.
+#[must_use = "futures do nothing unless awaited"]
+pub struct ScriptFuture<T> {
+    state: Arc<Mutex<Completion>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ScriptFuture<T> {
+    /// Create a pending future and the callback that completes it.
+    pub(crate) fn pending() -> (Self, ScriptCallback) {
+        let state = Arc::new(Mutex::new(Completion {
+            result: None,
+            waker: None,
+        }));
+
+        let completer = state.clone();
+        let callback: ScriptCallback = Box::new(move |result| {
+            if let Ok(mut completion) = completer.lock() {
+                completion.result = Some(result);
+                if let Some(waker) = completion.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+
+        (
+            Self {
+                state,
+                _marker: PhantomData,
+            },
+            callback,
+        )
+    }
+
+    /// Create a future that is already resolved with an error.
+    pub(crate) fn failed(error: Error) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(Completion {
+                result: Some(Err(error)),
+                waker: None,
+            })),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Future for ScriptFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Ok(mut completion) = self.state.lock() else {
+            return Poll::Ready(Err(Error::JavaScriptError(
+                "evaluation state poisoned".to_string(),
+            )));
+        };
+
+        if let Some(result) = completion.result.take() {
+            return Poll::Ready(result.and_then(|value| Ok(serde_json::from_value(value)?)));
+        }
+
+        completion.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Wrap a typed callback so it can receive the raw JSON result.
+pub(crate) fn typed_callback<T, F>(callback: F) -> ScriptCallback
+where
+    T: DeserializeOwned,
+    F: FnOnce(Result<T>) + 'static,
+{
+    Box::new(move |result: Result<serde_json::Value>| {
+        callback(result.and_then(|value| Ok(serde_json::from_value(value)?)));
+    })
+}
+
+/// Convert an optional script world name for WebKit (`None` is the main world).
+pub(crate) fn world_name(world: Option<&str>) -> Result<Option<CString>> {
+    world
+        .map(|name| CString::new(name).map_err(|e| Error::JavaScriptError(e.to_string())))
+        .transpose()
+}
+
+/// Evaluate a script in a web view without waiting for its result.
+///
+/// # Errors
+/// Returns an error if the script or world name contains interior NUL bytes.
+#[allow(unsafe_code)]
+pub(crate) fn run(
+    web_view: *mut wpe_sys::WebKitWebView,
+    world: Option<&str>,
+    script: &str,
+) -> Result<()> {
+    let c_script = CString::new(script).map_err(|e| Error::JavaScriptError(e.to_string()))?;
+    let c_world = world_name(world)?;
+
+    // SAFETY: web_view is valid for the caller's lifetime; c_script and c_world
+    // are valid C strings (or null) for the duration of the call.
+    unsafe {
+        wpe_sys::webkit_web_view_evaluate_javascript(
+            web_view,
+            c_script.as_ptr(),
+            script.len() as i64,
+            c_world.as_ref().map_or(ptr::null(), |c| c.as_ptr()),
+            ptr::null(),
+            ptr::null_mut(),
+            None,
+            ptr::null_mut(),
+        );
+    }
+    Ok(())
+}
+
+/// Evaluate a script in a web view, invoking `callback` with its result.
+///
+/// # Errors
+/// Returns an error if the script or world name contains interior NUL bytes.
+/// In that case the callback is dropped without being called.
+#[allow(unsafe_code)]
+pub(crate) fn evaluate(
+    web_view: *mut wpe_sys::WebKitWebView,
+    world: Option<&str>,
+    script: &str,
+    callback: ScriptCallback,
+) -> Result<()> {
+    let c_script = CString::new(script).map_err(|e| Error::JavaScriptError(e.to_string()))?;
+    let c_world = world_name(world)?;
+
+    // Double-box so the fat trait object pointer fits in a gpointer.
+    let user_data = Box::into_raw(Box::new(callback));
+
+    // SAFETY: web_view is valid for the caller's lifetime and the pending GTask
+    // keeps its own reference. user_data is reclaimed exactly once in
+    // on_evaluate_finished.
+    unsafe {
+        wpe_sys::webkit_web_view_evaluate_javascript(
+            web_view,
+            c_script.as_ptr(),
+            script.len() as i64,
+            c_world.as_ref().map_or(ptr::null(), |c| c.as_ptr()),
+            ptr::null(),
+            ptr::null_mut(),
+            Some(on_evaluate_finished),
+            user_data as *mut _,
+        );
+    }
+
+    tracing::debug!("Evaluating script with result ({} bytes)", script.len());
+    Ok(())
+}
+
+/// Completion handler for `webkit_web_view_evaluate_javascript`.
+#[allow(unsafe_code)]
+unsafe extern "C" fn on_evaluate_finished(
+    source: *mut wpe_sys::GObject,
+    result: *mut wpe_sys::GAsyncResult,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+
+    let callback = Box::from_raw(user_data as *mut ScriptCallback);
+
+    let mut error: *mut wpe_sys::GError = ptr::null_mut();
+    let value = wpe_sys::webkit_web_view_evaluate_javascript_finish(
+        source as *mut wpe_sys::WebKitWebView,
+        result,
+        &mut error,
+    );
+
+    let outcome = if value.is_null() {
+        Err(error_from_gerror(error))
+    } else {
+        let json = value_to_json(value);
+        wpe_sys::g_object_unref(value as *mut _);
+        json
+    };
+
+    callback(outcome);
+}
+
+/// Convert a `JSCValue` to a JSON value.
+#[allow(unsafe_code)]
+unsafe fn value_to_json(value: *mut wpe_sys::JSCValue) -> Result<serde_json::Value> {
+    if wpe_sys::jsc_value_is_undefined(value) != 0 {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let json_ptr = wpe_sys::jsc_value_to_json(value, 0);
+    if json_ptr.is_null() {
+        return Err(Error::JavaScriptError(
+            "Script result is not JSON-serializable".to_string(),
+        ));
+    }
+
+    let json = CStr::from_ptr(json_ptr).to_string_lossy().into_owned();
+    wpe_sys::g_free(json_ptr as *mut _);
+
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Convert (and free) a `GError` from an evaluation into an [`Error`].
+#[allow(unsafe_code)]
+unsafe fn error_from_gerror(error: *mut wpe_sys::GError) -> Error {
+    if error.is_null() {
+        return Error::JavaScriptError("Unknown error".to_string());
+    }
+
+    let message = CStr::from_ptr((*error).message)
+        .to_string_lossy()
+        .into_owned();
+    let is_exception = (*error).domain == wpe_sys::webkit_javascript_error_quark()
+        && (*error).code
+            == wpe_sys::WebKitJavascriptError_WEBKIT_JAVASCRIPT_ERROR_SCRIPT_FAILED as i32;
+    wpe_sys::g_error_free(error);
+
+    if is_exception {
+        parse_exception(&message)
+    } else {
+        Error::JavaScriptError(message)
+    }
+}
+
+/// Parse an exception message reported by WebKit.
+///
+/// WebKit formats exceptions as `sourceURL:line:column: message`, omitting
+/// the location when it is unknown.
+fn parse_exception(report: &str) -> Error {
+    let mut search_from = 0;
+
+    while let Some(offset) = report[search_from..].find(": ") {
+        let split = search_from + offset;
+        let location = &report[..split];
+
+        if let Some((source_url, line)) = parse_location(location) {
+            return Error::JavaScriptException {
+                message: report[split + 2..].to_string(),
+                line,
+                source_url: source_url.to_string(),
+            };
+        }
+
+        search_from = split + 2;
+    }
+
+    Error::JavaScriptException {
+        message: report.to_string(),
+        line: 0,
+        source_url: String::new(),
+    }
+}
+
+/// Split `sourceURL:line[:column]` into the URL and line number.
+fn parse_location(location: &str) -> Option<(&str, u32)> {
+    if location.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let (rest, last) = location.rsplit_once(':')?;
+    let last: u32 = last.parse().ok()?;
+
+    // If the segment before the last number is also numeric, the last one was the column.
+    match rest.rsplit_once(':') {
+        Some((source_url, line)) if line.parse::<u32>().is_ok() => {
+            Some((source_url, line.parse().ok()?))
+        }
+        _ => Some((rest, last)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct NoopWake;
+
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn noop_waker() -> Waker {
+        Waker::from(Arc::new(NoopWake))
+    }
+
+    #[test]
+    fn test_world_name() {
+        assert!(world_name(None).unwrap().is_none());
+        let name = world_name(Some("app")).unwrap().unwrap();
+        assert_eq!(name.to_str().unwrap(), "app");
+        assert!(world_name(Some("a\0b")).is_err());
+    }
+
+    #[test]
+    fn test_parse_exception_with_location() {
+        let err = parse_exception(
+            "https://example.com:8080/app.js:12:5: ReferenceError: Can't find variable: foo",
+        );
+        match err {
+            Error::JavaScriptException {
+                message,
+                line,
+                source_url,
+            } => {
+                assert_eq!(message, "ReferenceError: Can't find variable: foo");
+                assert_eq!(line, 12);
+                assert_eq!(source_url, "https://example.com:8080/app.js");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_exception_without_column() {
+        let err = parse_exception("about:blank:3: TypeError: null is not an object");
+        match err {
+            Error::JavaScriptException {
+                line, source_url, ..
+            } => {
+                assert_eq!(line, 3);
+                assert_eq!(source_url, "about:blank");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_exception_without_location() {
+        let err = parse_exception("Error: boom");
+        match err {
+            Error::JavaScriptException {
+                message,
+                line,
+                source_url,
+            } => {
+                assert_eq!(message, "Error: boom");
+                assert_eq!(line, 0);
+                assert!(source_url.is_empty());
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_script_future_resolves_typed() {
+        let (future, callback) = ScriptFuture::<u32>::pending();
+        callback(Ok(serde_json::json!(42)));
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(value)) => assert_eq!(value, 42),
+            other => panic!("unexpected poll result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_script_future_failed() {
+        let future =
+            ScriptFuture::<serde_json::Value>::failed(Error::JavaScriptError("nope".into()));
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        assert!(matches!(
+            future.as_mut().poll(&mut cx),
+            Poll::Ready(Err(Error::JavaScriptError(_)))
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Mapping of [`WebViewSettings`] onto `WebKitSettings`.
+//!
+//! [`RuntimeSettings`] holds the subset that can change on a live view.
+
+use std::ffi::CString;
+use std::ptr;
+
+use crate::WebViewSettings;
+
+/// When media may start playing without user interaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoplayPolicy {
+    /// Media may autoplay.
+    #[default]
+    Allow,
+    /// Media only plays in response to a user gesture.
+    RequireUserGesture,
+}
+
+/// Whether WebKit composites pages on the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HardwareAccelerationPolicy {
+    /// Always use hardware acceleration.
+    #[default]
+    Always,
+    /// Never use hardware acceleration (software rendering only).
+    Never,
+}
+
+impl HardwareAccelerationPolicy {
+    fn to_webkit(self) -> wpe_sys::WebKitHardwareAccelerationPolicy {
+        match self {
+            Self::Always => {
+                wpe_sys::WebKitHardwareAccelerationPolicy_WEBKIT_HARDWARE_ACCELERATION_POLICY_ALWAYS
+            }
+            Self::Never => {
+                wpe_sys::WebKitHardwareAccelerationPolicy_WEBKIT_HARDWARE_ACCELERATION_POLICY_NEVER
+            }
+        }
+    }
+}
+
+/// The WebKit options of [`WebViewSettings`], which `update_settings` can
+/// change on a live view.
+///
+/// Creation-time settings such as the size, assets, script world and IPC
+/// configuration are not part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RuntimeSettings {
+    /// Enable developer tools
+    pub developer_tools: bool,
+    /// Enable JavaScript
+    pub javascript_enabled: bool,
+    /// User agent string
+    pub user_agent: Option<String>,
+    /// Enable WebGL
+    pub webgl_enabled: bool,
+    /// Media autoplay policy
+    pub autoplay_policy: AutoplayPolicy,
+    /// Default (proportional) font family
+    pub default_font_family: Option<String>,
+    /// Monospace font family
+    pub monospace_font_family: Option<String>,
+    /// Default font size in CSS pixels
+    pub default_font_size: Option<u32>,
+    /// Default monospace font size in CSS pixels
+    pub default_monospace_font_size: Option<u32>,
+    /// Enable smooth scrolling
+    pub smooth_scrolling: bool,
+    /// Allow `file://` pages to access other `file://` URLs
+    pub local_file_access: bool,
+    /// Allow JavaScript to read and write the clipboard
+    pub javascript_clipboard: bool,
+    /// Hardware acceleration policy
+    pub hardware_acceleration: HardwareAccelerationPolicy,
+}
+
+impl WebViewSettings {
+    /// The options that can change after the view is created.
+    #[must_use]
+    pub fn runtime_settings(&self) -> RuntimeSettings {
+        RuntimeSettings {
+            developer_tools: self.developer_tools,
+            javascript_enabled: self.javascript_enabled,
+            user_agent: self.user_agent.clone(),
+            webgl_enabled: self.webgl_enabled,
+            autoplay_policy: self.autoplay_policy,
+            default_font_family: self.default_font_family.clone(),
+            monospace_font_family: self.monospace_font_family.clone(),
+            default_font_size: self.default_font_size,
+            default_monospace_font_size: self.default_monospace_font_size,
+            smooth_scrolling: self.smooth_scrolling,
+            local_file_access: self.local_file_access,
+            javascript_clipboard: self.javascript_clipboard,
+            hardware_acceleration: self.hardware_acceleration,
+        }
+    }
+
+    /// Replace the runtime options, keeping the creation-time ones.
+    pub(crate) fn set_runtime_settings(&mut self, runtime: RuntimeSettings) {
+        self.developer_tools = runtime.developer_tools;
+        self.javascript_enabled = runtime.javascript_enabled;
+        self.user_agent = runtime.user_agent;
+        self.webgl_enabled = runtime.webgl_enabled;
+        self.autoplay_policy = runtime.autoplay_policy;
+        self.default_font_family = runtime.default_font_family;
+        self.monospace_font_family = runtime.monospace_font_family;
+        self.default_font_size = runtime.default_font_size;
+        self.default_monospace_font_size = runtime.default_monospace_font_size;
+        self.smooth_scrolling = runtime.smooth_scrolling;
+        self.local_file_access = runtime.local_file_access;
+        self.javascript_clipboard = runtime.javascript_clipboard;
+        self.hardware_acceleration = runtime.hardware_acceleration;
+    }
+}
+
+/// Apply the WebKit options in `settings` to a web view.
+///
+/// Font options left as `None` keep WebKit's current value.
+///
+/// # Safety
+/// `web_view` must be a valid `WebKitWebView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn apply(web_view: *mut wpe_sys::WebKitWebView, settings: &RuntimeSettings) {
+    let webkit_settings = wpe_sys::webkit_web_view_get_settings(web_view);
+    if webkit_settings.is_null() {
+        tracing::warn!("Web view has no WebKitSettings");
+        return;
+    }
+
+    wpe_sys::webkit_settings_set_enable_javascript(
+        webkit_settings,
+        i32::from(settings.javascript_enabled),
+    );
+    wpe_sys::webkit_settings_set_enable_developer_extras(
+        webkit_settings,
+        i32::from(settings.developer_tools),
+    );
+    wpe_sys::webkit_settings_set_enable_webgl(webkit_settings, i32::from(settings.webgl_enabled));
+    wpe_sys::webkit_settings_set_media_playback_requires_user_gesture(
+        webkit_settings,
+        i32::from(settings.autoplay_policy == AutoplayPolicy::RequireUserGesture),
+    );
+    wpe_sys::webkit_settings_set_enable_smooth_scrolling(
+        webkit_settings,
+        i32::from(settings.smooth_scrolling),
+    );
+    wpe_sys::webkit_settings_set_allow_file_access_from_file_urls(
+        webkit_settings,
+        i32::from(settings.local_file_access),
+    );
+    wpe_sys::webkit_settings_set_javascript_can_access_clipboard(
+        webkit_settings,
+        i32::from(settings.javascript_clipboard),
+    );
+    wpe_sys::webkit_settings_set_hardware_acceleration_policy(
+        webkit_settings,
+        settings.hardware_acceleration.to_webkit(),
+    );
+
+    // NULL restores WebKit's default user agent
+    let user_agent = settings
+        .user_agent
+        .as_deref()
+        .and_then(|ua| CString::new(ua).ok());
+    wpe_sys::webkit_settings_set_user_agent(
+        webkit_settings,
+        user_agent.as_ref().map_or(ptr::null(), |ua| ua.as_ptr()),
+    );
+
+    if let Some(family) = settings
+        .default_font_family
+        .as_deref()
+        .and_then(|f| CString::new(f).ok())
+    {
+        wpe_sys::webkit_settings_set_default_font_family(webkit_settings, family.as_ptr());
+    }
+    if let Some(family) = settings
+        .monospace_font_family
+        .as_deref()
+        .and_then(|f| CString::new(f).ok())
+    {
+        wpe_sys::webkit_settings_set_monospace_font_family(webkit_settings, family.as_ptr());
+    }
+    if let Some(size) = settings.default_font_size {
+        wpe_sys::webkit_settings_set_default_font_size(webkit_settings, size);
+    }
+    if let Some(size) = settings.default_monospace_font_size {
+        wpe_sys::webkit_settings_set_default_monospace_font_size(webkit_settings, size);
+    }
+
+    tracing::debug!("Applied WebKit settings");
+}
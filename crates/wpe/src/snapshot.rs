@@ -0,0 +1,297 @@
+//! Page snapshots taken from the rendered frame buffer.
+
+use std::ops::Range;
+
+use crate::{Error, Result};
+
+/// The part of the view to capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SnapshotRegion {
+    /// The whole visible viewport.
+    #[default]
+    Visible,
+    /// A rectangle of the viewport, in logical pixels.
+    ///
+    /// The rectangle is clipped to the viewport.
+    Area {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// A captured image of a web view.
+///
+/// Pixels are stored as 8-bit RGBA with straight (non-premultiplied) alpha,
+/// row-major from the top-left corner, with no row padding. Dimensions are
+/// in physical pixels, so a view with a scale factor of 2.0 produces an
+/// image twice its logical size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Create a snapshot from RGBA pixel data.
+    ///
+    /// # Errors
+    /// Returns an error if `data` is not `width * height * 4` bytes long.
+    pub fn from_rgba(width: u32, height: u32, data: Vec<u8>) -> Result<Self> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(Error::SnapshotFailed(format!(
+                "expected {} bytes of RGBA data for {}x{}, got {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                data.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Image width in pixels.
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Image height in pixels.
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The RGBA pixel data.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Take ownership of the RGBA pixel data.
+    #[must_use]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The RGBA value of the pixel at (`x`, `y`), if it is inside the image.
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        let mut rgba = [0; 4];
+        rgba.copy_from_slice(&self.data[offset..offset + 4]);
+        Some(rgba)
+    }
+
+    /// Encode the snapshot as a PNG image.
+    ///
+    /// # Errors
+    /// Returns an error if encoding fails.
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| Error::SnapshotFailed(e.to_string()))?;
+        writer
+            .write_image_data(&self.data)
+            .map_err(|e| Error::SnapshotFailed(e.to_string()))?;
+        writer
+            .finish()
+            .map_err(|e| Error::SnapshotFailed(e.to_string()))?;
+        Ok(out)
+    }
+
+    /// Encode the snapshot as PNG and write it to `path`.
+    ///
+    /// # Errors
+    /// Returns an error if encoding or writing the file fails.
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let png = self.to_png()?;
+        std::fs::write(path.as_ref(), png)
+            .map_err(|e| Error::SnapshotFailed(format!("{}: {}", path.as_ref().display(), e)))
+    }
+
+    /// Append `rows` of `other` below this image.
+    ///
+    /// # Errors
+    /// Returns an error if the widths differ or `rows` lies outside `other`.
+    pub(crate) fn append_rows(&mut self, other: &Snapshot, rows: Range<u32>) -> Result<()> {
+        if other.width != self.width || rows.start > rows.end || rows.end > other.height {
+            return Err(Error::SnapshotFailed(format!(
+                "cannot append rows {}..{} of a {}x{} capture to a {}px wide one",
+                rows.start, rows.end, other.width, other.height, self.width
+            )));
+        }
+        let row_bytes = self.width as usize * 4;
+        self.data.extend_from_slice(
+            &other.data[rows.start as usize * row_bytes..rows.end as usize * row_bytes],
+        );
+        self.height += rows.end - rows.start;
+        Ok(())
+    }
+}
+
+/// Copy `region` out of a frame buffer of `0xAARRGGBB` pixels.
+///
+/// `region` is converted from logical to physical pixels with `scale_factor`
+/// and clipped to the frame.
+pub(crate) fn from_argb(
+    pixels: &[u32],
+    frame_width: u32,
+    frame_height: u32,
+    region: SnapshotRegion,
+    scale_factor: f64,
+) -> Result<Snapshot> {
+    let (x, y, width, height) = match region {
+        SnapshotRegion::Visible => (0, 0, frame_width, frame_height),
+        SnapshotRegion::Area {
+            x,
+            y,
+            width,
+            height,
+        } => {
+            let x = crate::webview::to_physical(x, scale_factor).min(frame_width);
+            let y = crate::webview::to_physical(y, scale_factor).min(frame_height);
+            let width = crate::webview::to_physical(width, scale_factor).min(frame_width - x);
+            let height = crate::webview::to_physical(height, scale_factor).min(frame_height - y);
+            (x, y, width, height)
+        }
+    };
+
+    if width == 0 || height == 0 {
+        return Err(Error::SnapshotFailed(
+            "region does not intersect the view".to_string(),
+        ));
+    }
+
+    let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in y..y + height {
+        let start = row as usize * frame_width as usize + x as usize;
+        for &pixel in &pixels[start..start + width as usize] {
+            data.extend_from_slice(&argb_to_rgba(pixel));
+        }
+    }
+
+    Snapshot::from_rgba(width, height, data)
+}
+
+/// Convert a premultiplied `0xAARRGGBB` pixel to straight RGBA bytes.
+fn argb_to_rgba(pixel: u32) -> [u8; 4] {
+    let [b, g, r, a] = pixel.to_le_bytes();
+    match a {
+        0 => [0, 0, 0, 0],
+        255 => [r, g, b, a],
+        _ => {
+            let unpremultiply =
+                |c: u8| ((u32::from(c) * 255 + u32::from(a) / 2) / u32::from(a)).min(255) as u8;
+            [unpremultiply(r), unpremultiply(g), unpremultiply(b), a]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_argb_to_rgba() {
+        assert_eq!(argb_to_rgba(0xFF11_2233), [0x11, 0x22, 0x33, 0xFF]);
+        assert_eq!(argb_to_rgba(0x0012_3456), [0, 0, 0, 0]);
+        assert_eq!(argb_to_rgba(0x8040_2000), [0x80, 0x40, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_from_argb_regions() {
+        // 4x2 frame where each pixel's red channel is its index
+        let pixels: Vec<u32> = (0..8).map(|i| 0xFF00_0000 | (i << 16)).collect();
+
+        let full = from_argb(&pixels, 4, 2, SnapshotRegion::Visible, 1.0).unwrap();
+        assert_eq!((full.width(), full.height()), (4, 2));
+        assert_eq!(full.pixel(3, 1), Some([7, 0, 0, 255]));
+        assert_eq!(full.pixel(4, 0), None);
+
+        let area = SnapshotRegion::Area {
+            x: 1,
+            y: 1,
+            width: 10,
+            height: 10,
+        };
+        let clipped = from_argb(&pixels, 4, 2, area, 1.0).unwrap();
+        assert_eq!((clipped.width(), clipped.height()), (3, 1));
+        assert_eq!(clipped.pixel(0, 0), Some([5, 0, 0, 255]));
+
+        let outside = SnapshotRegion::Area {
+            x: 4,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        assert!(from_argb(&pixels, 4, 2, outside, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_from_argb_scaled_region() {
+        let pixels: Vec<u32> = (0..16).map(|i| 0xFF00_0000 | (i << 16)).collect();
+        let area = SnapshotRegion::Area {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 1,
+        };
+        let snapshot = from_argb(&pixels, 4, 4, area, 2.0).unwrap();
+        assert_eq!((snapshot.width(), snapshot.height()), (2, 2));
+        assert_eq!(snapshot.pixel(0, 0), Some([10, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_from_rgba_checks_length() {
+        assert!(Snapshot::from_rgba(2, 2, vec![0; 16]).is_ok());
+        assert!(Snapshot::from_rgba(2, 2, vec![0; 15]).is_err());
+    }
+
+    #[test]
+    fn test_append_rows() {
+        // 1x3 tile where each pixel's red channel is its row
+        let tile =
+            Snapshot::from_rgba(1, 3, vec![0, 0, 0, 255, 1, 0, 0, 255, 2, 0, 0, 255]).unwrap();
+        let mut stitched = Snapshot::from_rgba(1, 0, Vec::new()).unwrap();
+        stitched.append_rows(&tile, 0..3).unwrap();
+        stitched.append_rows(&tile, 1..3).unwrap();
+
+        assert_eq!(stitched.height(), 5);
+        assert_eq!(stitched.pixel(0, 3), Some([1, 0, 0, 255]));
+        assert_eq!(stitched.pixel(0, 4), Some([2, 0, 0, 255]));
+
+        assert!(stitched.append_rows(&tile, 2..4).is_err());
+        let wide = Snapshot::from_rgba(2, 1, vec![0; 8]).unwrap();
+        assert!(stitched.append_rows(&wide, 0..1).is_err());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png_roundtrip() {
+        let snapshot = Snapshot::from_rgba(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 128]).unwrap();
+        let png = snapshot.to_png().unwrap();
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(&buf[..info.buffer_size()], snapshot.data());
+    }
+}
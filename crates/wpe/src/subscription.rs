@@ -0,0 +1,432 @@
+//! Topic subscriptions pushed from Rust to JavaScript.
+//!
+//! Pages subscribe to topics with `wpe.subscribe`; Rust publishes through a
+//! [`Subscription`] handle. Values for topics no page is subscribed to are
+//! dropped, and subscriptions end when the page navigates away.
+//!
+//! ```javascript
+//! const unsubscribe = wpe.subscribe('telemetry', (sample) => chart.push(sample));
+//! ```
+//!
+//! ```rust,ignore
+//! let telemetry = window.subscription("telemetry");
+//! loop {
+//!     telemetry.emit(&read_sensors())?;
+//!     window.step_with_router(&router);
+//! }
+//! ```
+//!
+//! Delivery is flow-controlled: at most one batch is in flight until the page
+//! acknowledges it, and values emitted meanwhile are coalesced so only the
+//! latest value per topic is delivered. A fast producer therefore costs one
+//! script evaluation per page update rather than one per value. Acks arrive
+//! with the page's other messages, so keep receiving messages (directly or
+//! through a router) while emitting. A batch that fails to evaluate is not
+//! waited for, and one still unacknowledged after [`ACK_TIMEOUT`] no longer
+//! holds back the next emission.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ipc::{self, FrontendMessage};
+use crate::native::NavigationCounter;
+use crate::script;
+use crate::Result;
+
+/// Sent by the bridge when a page subscribes to a topic.
+const SUBSCRIBE: &str = "__wpe_subscribe";
+/// Sent by the bridge when a page's last callback for a topic unsubscribes.
+const UNSUBSCRIBE: &str = "__wpe_unsubscribe";
+/// Sent by the bridge once a batch has been delivered.
+const ACK: &str = "__wpe_ack";
+
+/// How long a batch may go unacknowledged before the next one is sent anyway.
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct TopicPayload {
+    topic: String,
+}
+
+#[derive(Deserialize)]
+struct AckPayload {
+    seq: u64,
+}
+
+/// A batch of events for the bridge's `__publish` hook.
+#[derive(Serialize)]
+struct Batch<'a> {
+    seq: u64,
+    events: &'a [(String, serde_json::Value)],
+}
+
+/// A batch sent to the page and not acknowledged yet.
+#[derive(Debug, Clone, Copy)]
+struct InFlight {
+    seq: u64,
+    sent: Instant,
+}
+
+/// Subscription state for one web view.
+pub(crate) struct SubscriptionHub {
+    web_view: Cell<*mut wpe_sys::WebKitWebView>,
+    world: Option<String>,
+    /// Global the bridge is installed as
+    global: String,
+    navigation: NavigationCounter,
+    navigation_seen: Cell<u64>,
+    /// Topics the current page is subscribed to
+    topics: RefCell<HashSet<String>>,
+    /// Latest value per topic, in order of first emission
+    pending: RefCell<Vec<(String, serde_json::Value)>>,
+    /// The batch awaiting an ack, shared with its evaluation callback
+    in_flight: Rc<Cell<Option<InFlight>>>,
+    next_seq: Cell<u64>,
+}
+
+impl SubscriptionHub {
+    /// Publish to `web_view`'s page through the bridge installed as
+    /// `window[global]`, evaluating scripts in `world`.
+    pub(crate) fn new(
+        web_view: *mut wpe_sys::WebKitWebView,
+        world: Option<String>,
+        global: &str,
+        navigation: &NavigationCounter,
+    ) -> Self {
+        Self {
+            web_view: Cell::new(web_view),
+            world,
+            global: global.to_string(),
+            navigation_seen: Cell::new(navigation.load(Ordering::Acquire)),
+            navigation: Arc::clone(navigation),
+            topics: RefCell::new(HashSet::new()),
+            pending: RefCell::new(Vec::new()),
+            in_flight: Rc::new(Cell::new(None)),
+            next_seq: Cell::new(0),
+        }
+    }
+
+    /// Stop publishing; called before the web view is released.
+    pub(crate) fn detach(&self) {
+        self.web_view.set(std::ptr::null_mut());
+        self.reset();
+    }
+
+    /// Apply a subscription control message.
+    ///
+    /// Returns `false` for ordinary messages, which the caller passes on.
+    pub(crate) fn handle(&self, message: &FrontendMessage) -> bool {
+        let kind = message.message_type.as_str();
+        if ![SUBSCRIBE, UNSUBSCRIBE, ACK].contains(&kind) {
+            return false;
+        }
+        self.sync_navigation();
+
+        match kind {
+            SUBSCRIBE | UNSUBSCRIBE => {
+                let Ok(TopicPayload { topic }) = serde_json::from_value(message.payload.clone())
+                else {
+                    tracing::warn!("Invalid {} message", kind);
+                    return true;
+                };
+                if kind == SUBSCRIBE {
+                    tracing::debug!("Page subscribed to '{}'", topic);
+                    self.topics.borrow_mut().insert(topic);
+                } else {
+                    tracing::debug!("Page unsubscribed from '{}'", topic);
+                    self.pending.borrow_mut().retain(|(t, _)| *t != topic);
+                    self.topics.borrow_mut().remove(&topic);
+                }
+            }
+            _ => {
+                if let Ok(AckPayload { seq }) = serde_json::from_value(message.payload.clone()) {
+                    if self.in_flight_seq() == Some(seq) {
+                        self.in_flight.set(None);
+                        self.flush();
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether the current page is subscribed to `topic`.
+    fn is_subscribed(&self, topic: &str) -> bool {
+        self.sync_navigation();
+        self.topics.borrow().contains(topic)
+    }
+
+    /// Queue `value` for `topic`, replacing any undelivered value, and flush.
+    fn emit(&self, topic: &str, value: serde_json::Value) {
+        if !self.is_subscribed(topic) {
+            return;
+        }
+
+        {
+            let mut pending = self.pending.borrow_mut();
+            match pending.iter_mut().find(|(t, _)| t == topic) {
+                Some((_, slot)) => *slot = value,
+                None => pending.push((topic.to_string(), value)),
+            }
+        }
+        self.flush();
+    }
+
+    /// Send the pending values unless a batch is still in flight.
+    fn flush(&self) {
+        let Some((seq, script)) = self.take_batch() else {
+            return;
+        };
+        let web_view = self.web_view.get();
+        if web_view.is_null() {
+            return;
+        }
+
+        // The page never acks a batch it didn't run, so stop waiting for it
+        let in_flight = Rc::clone(&self.in_flight);
+        let done: script::ScriptCallback = Box::new(move |result| {
+            if let Err(e) = result {
+                tracing::warn!("Failed to publish subscription batch: {}", e);
+                if in_flight.get().is_some_and(|batch| batch.seq == seq) {
+                    in_flight.set(None);
+                }
+            }
+        });
+        if let Err(e) = script::evaluate(web_view, self.world.as_deref(), &script, done) {
+            tracing::warn!("Failed to publish subscription batch: {}", e);
+            self.in_flight.set(None);
+        }
+    }
+
+    /// Sequence number of the batch awaiting an ack.
+    fn in_flight_seq(&self) -> Option<u64> {
+        self.in_flight.get().map(|batch| batch.seq)
+    }
+
+    /// Build the script for the next batch, mark it in flight and return
+    /// its sequence number with it.
+    ///
+    /// Pending values are only taken once the batch has been serialized.
+    fn take_batch(&self) -> Option<(u64, String)> {
+        if let Some(batch) = self.in_flight.get() {
+            if batch.sent.elapsed() < ACK_TIMEOUT {
+                return None;
+            }
+            tracing::warn!("Subscription batch {} was never acknowledged", batch.seq);
+        }
+        if self.pending.borrow().is_empty() {
+            return None;
+        }
+
+        let seq = self.next_seq.get();
+        let batch = match serde_json::to_string(&Batch {
+            seq,
+            events: &self.pending.borrow(),
+        }) {
+            Ok(batch) => batch,
+            Err(e) => {
+                tracing::warn!("Failed to serialize subscription batch: {}", e);
+                return None;
+            }
+        };
+
+        self.pending.borrow_mut().clear();
+        self.next_seq.set(seq.wrapping_add(1));
+        self.in_flight.set(Some(InFlight {
+            seq,
+            sent: Instant::now(),
+        }));
+        Some((seq, ipc::hook_script(&self.global, "__publish", &batch)))
+    }
+
+    /// Drop the previous page's subscriptions after a navigation.
+    fn sync_navigation(&self) {
+        let count = self.navigation.load(Ordering::Acquire);
+        if self.navigation_seen.replace(count) != count {
+            self.reset();
+        }
+    }
+
+    fn reset(&self) {
+        self.topics.borrow_mut().clear();
+        self.pending.borrow_mut().clear();
+        self.in_flight.set(None);
+    }
+}
+
+/// A handle for publishing values to pages subscribed to a topic.
+///
+/// Created with `WebView::subscription` or `NativeWindow::subscription`.
+/// Handles are cheap to clone and outlive their web view harmlessly.
+#[derive(Clone)]
+pub struct Subscription {
+    hub: Rc<SubscriptionHub>,
+    topic: String,
+}
+
+impl Subscription {
+    pub(crate) fn new(hub: &Rc<SubscriptionHub>, topic: impl Into<String>) -> Self {
+        Self {
+            hub: Rc::clone(hub),
+            topic: topic.into(),
+        }
+    }
+
+    /// The topic this handle publishes to.
+    #[must_use]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Whether the current page is subscribed to the topic.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.hub.is_subscribed(&self.topic)
+    }
+
+    /// Publish `value` to the page's `wpe.subscribe` callbacks.
+    ///
+    /// Dropped if the page isn't subscribed; coalesced with other undelivered
+    /// values for the topic while the previous batch is in flight.
+    ///
+    /// # Errors
+    /// Returns an error if `value` cannot be serialized.
+    pub fn emit<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.hub.emit(&self.topic, value);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    fn control(kind: &str, payload: serde_json::Value) -> FrontendMessage {
+        FrontendMessage {
+            message_type: kind.to_string(),
+            payload,
+            request_id: None,
+            origin: None,
+        }
+    }
+
+    fn detached_hub(navigation: &NavigationCounter) -> Rc<SubscriptionHub> {
+        Rc::new(SubscriptionHub::new(
+            std::ptr::null_mut(),
+            None,
+            "wpe",
+            navigation,
+        ))
+    }
+
+    #[test]
+    fn test_subscription_coalesces_until_ack() {
+        let navigation: NavigationCounter = Arc::new(AtomicU64::new(0));
+        let hub = detached_hub(&navigation);
+        let telemetry = Subscription::new(&hub, "telemetry");
+
+        // Not subscribed yet: dropped
+        telemetry.emit(&1).unwrap();
+        assert!(!telemetry.is_active());
+        assert!(hub.pending.borrow().is_empty());
+
+        assert!(hub.handle(&control(
+            SUBSCRIBE,
+            serde_json::json!({"topic": "telemetry"})
+        )));
+        assert!(telemetry.is_active());
+
+        // First value goes out immediately, later ones wait for the ack
+        telemetry.emit(&2).unwrap();
+        assert_eq!(hub.in_flight_seq(), Some(0));
+        telemetry.emit(&3).unwrap();
+        telemetry.emit(&4).unwrap();
+        assert_eq!(
+            *hub.pending.borrow(),
+            [("telemetry".to_string(), serde_json::json!(4))]
+        );
+
+        // A stale ack is ignored; the right one sends the coalesced value
+        hub.handle(&control(ACK, serde_json::json!({"seq": 7})));
+        assert_eq!(hub.in_flight_seq(), Some(0));
+        hub.handle(&control(ACK, serde_json::json!({"seq": 0})));
+        assert_eq!(hub.in_flight_seq(), Some(1));
+        assert!(hub.pending.borrow().is_empty());
+
+        // Ordinary messages are passed on
+        assert!(!hub.handle(&control("ping", serde_json::Value::Null)));
+    }
+
+    #[test]
+    fn test_subscription_ends_on_navigation() {
+        let navigation: NavigationCounter = Arc::new(AtomicU64::new(0));
+        let hub = detached_hub(&navigation);
+        let topic = Subscription::new(&hub, "news");
+
+        hub.handle(&control(SUBSCRIBE, serde_json::json!({"topic": "news"})));
+        assert!(topic.is_active());
+
+        navigation.fetch_add(1, Ordering::AcqRel);
+        assert!(!topic.is_active());
+
+        hub.handle(&control(SUBSCRIBE, serde_json::json!({"topic": "news"})));
+        hub.handle(&control(UNSUBSCRIBE, serde_json::json!({"topic": "news"})));
+        assert!(!topic.is_active());
+    }
+
+    #[test]
+    fn test_batch_script() {
+        let navigation: NavigationCounter = Arc::new(AtomicU64::new(0));
+        let hub = detached_hub(&navigation);
+        hub.pending
+            .borrow_mut()
+            .push(("a".to_string(), serde_json::json!({"x": 1})));
+
+        assert_eq!(
+            hub.take_batch(),
+            Some((
+                0,
+                r#"window["wpe"].__publish({"seq":0,"events":[["a",{"x":1}]]})"#.to_string()
+            ))
+        );
+        assert!(hub.take_batch().is_none());
+    }
+
+    #[test]
+    fn test_unacknowledged_batch_times_out() {
+        let navigation: NavigationCounter = Arc::new(AtomicU64::new(0));
+        let hub = detached_hub(&navigation);
+        hub.pending
+            .borrow_mut()
+            .push(("a".to_string(), serde_json::json!(1)));
+        assert!(hub.take_batch().is_some());
+
+        hub.pending
+            .borrow_mut()
+            .push(("a".to_string(), serde_json::json!(2)));
+        assert!(hub.take_batch().is_none());
+
+        // The ack for batch 0 was lost
+        hub.in_flight.set(Some(InFlight {
+            seq: 0,
+            sent: Instant::now() - ACK_TIMEOUT,
+        }));
+        assert_eq!(hub.take_batch().map(|(seq, _)| seq), Some(1));
+    }
+}
@@ -0,0 +1,961 @@
+//! TypeScript definitions for IPC commands.
+//!
+//! Types are described by tracing their `Deserialize` implementations with a
+//! deserializer that records what it is asked for, so the output follows
+//! serde's view of a type: renamed fields and variants, `rename_all`, skipped
+//! fields and transparent newtypes come out the way they travel over IPC.
+//!
+//! Derived structs, tuples, sequences, maps and externally tagged enums
+//! (serde's default) are supported, including recursion through sequences
+//! and enum variants. Types that can't be traced are emitted as `unknown`:
+//! internally tagged, adjacently tagged and untagged enums, `#[serde(flatten)]`,
+//! types parsed from strings (such as `IpAddr`) and structs that contain
+//! themselves through an `Option` or a map.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// Upper bound on tracing passes per type, for deeply nested enums.
+const MAX_PASSES: usize = 64;
+
+/// Traces a type into a registry.
+pub(crate) type TraceFn = fn(&mut TypeRegistry) -> TsType;
+
+/// A TypeScript type expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TsType {
+    Unknown,
+    Null,
+    Boolean,
+    Number,
+    String,
+    Array(Box<TsType>),
+    Tuple(Vec<TsType>),
+    /// An object with string keys and values of the given type
+    Record(Box<TsType>),
+    Nullable(Box<TsType>),
+    /// A declared type
+    Named(&'static str),
+}
+
+impl TsType {
+    /// Whether the type refers to the declaration `name`.
+    fn mentions(&self, name: &str) -> bool {
+        match self {
+            Self::Named(n) => *n == name,
+            Self::Array(t) | Self::Record(t) | Self::Nullable(t) => t.mentions(name),
+            Self::Tuple(types) => types.iter().any(|t| t.mentions(name)),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TsType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => f.write_str("unknown"),
+            Self::Null => f.write_str("null"),
+            Self::Boolean => f.write_str("boolean"),
+            Self::Number => f.write_str("number"),
+            Self::String => f.write_str("string"),
+            Self::Array(t) if matches!(**t, Self::Nullable(_)) => write!(f, "({t})[]"),
+            Self::Array(t) => write!(f, "{t}[]"),
+            Self::Tuple(types) => {
+                f.write_str("[")?;
+                for (i, t) in types.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{t}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Record(t) => write!(f, "Record<string, {t}>"),
+            Self::Nullable(t) if matches!(**t, Self::Null | Self::Nullable(_)) => write!(f, "{t}"),
+            Self::Nullable(t) => write!(f, "{t} | null"),
+            Self::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+/// A named type.
+#[derive(Debug, Clone, PartialEq)]
+enum Declaration {
+    Interface(Vec<(&'static str, TsType)>),
+    Alias(TsType),
+    Enum {
+        variants: Vec<(&'static str, VariantState)>,
+        /// Last variant chosen once all have been traced
+        cursor: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum VariantState {
+    Unexplored,
+    /// Needs another variant of the same enum to be traced first
+    Deferred,
+    Done(VariantShape),
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum VariantShape {
+    Unit,
+    Newtype(TsType),
+    Tuple(Vec<TsType>),
+    Struct(Vec<(&'static str, TsType)>),
+}
+
+impl VariantShape {
+    fn mentions(&self, name: &str) -> bool {
+        match self {
+            Self::Unit => false,
+            Self::Newtype(t) => t.mentions(name),
+            Self::Tuple(types) => types.iter().any(|t| t.mentions(name)),
+            Self::Struct(fields) => fields.iter().any(|(_, t)| t.mentions(name)),
+        }
+    }
+}
+
+/// Declarations collected while tracing.
+#[derive(Debug, Default)]
+pub(crate) struct TypeRegistry {
+    declarations: BTreeMap<&'static str, Declaration>,
+    /// Named types being traced, innermost last
+    stack: Vec<&'static str>,
+    /// Type whose recursion aborted the current trace
+    recursion: Option<&'static str>,
+}
+
+impl TypeRegistry {
+    fn declare(&mut self, name: &'static str, declaration: Declaration) {
+        match self.declarations.get(name) {
+            None => {
+                self.declarations.insert(name, declaration);
+            }
+            Some(existing) if *existing != declaration => {
+                tracing::warn!("Conflicting TypeScript declarations for '{}'", name);
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Pick the variant of `name` to trace next.
+    fn choose_variant(
+        &mut self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        recursive: bool,
+    ) -> Option<usize> {
+        let declaration = self
+            .declarations
+            .entry(name)
+            .or_insert_with(|| Declaration::Enum {
+                variants: variants
+                    .iter()
+                    .map(|v| (*v, VariantState::Unexplored))
+                    .collect(),
+                cursor: 0,
+            });
+        let Declaration::Enum { variants, cursor } = declaration else {
+            return None;
+        };
+        let position = |want: fn(&VariantState) -> bool| variants.iter().position(|(_, s)| want(s));
+
+        if recursive {
+            // Any variant that was built without recursing into this enum
+            return variants.iter().position(
+                |(_, s)| matches!(s, VariantState::Done(shape) if !shape.mentions(name)),
+            );
+        }
+        if let Some(i) = position(|s| *s == VariantState::Unexplored) {
+            return Some(i);
+        }
+        let has_done = position(|s| matches!(s, VariantState::Done(_))).is_some();
+        if has_done {
+            if let Some(i) = position(|s| *s == VariantState::Deferred) {
+                return Some(i);
+            }
+        }
+
+        // Rotate through the built variants to reach enums nested in them
+        for _ in 0..variants.len() {
+            *cursor = (*cursor + 1) % variants.len();
+            if matches!(variants[*cursor].1, VariantState::Done(_)) {
+                return Some(*cursor);
+            }
+        }
+        None
+    }
+
+    fn record_variant(&mut self, name: &'static str, index: usize, shape: Option<VariantShape>) {
+        let recursion = self.recursion;
+        let Some(Declaration::Enum { variants, .. }) = self.declarations.get_mut(name) else {
+            return;
+        };
+        let has_done = variants
+            .iter()
+            .any(|(_, s)| matches!(s, VariantState::Done(_)));
+        let state = &mut variants[index].1;
+
+        *state = match shape {
+            Some(shape) => VariantState::Done(shape),
+            None if recursion == Some(name) && !has_done && *state == VariantState::Unexplored => {
+                self.recursion = None;
+                VariantState::Deferred
+            }
+            None => VariantState::Failed,
+        };
+    }
+
+    /// Whether tracing again could describe more enum variants.
+    fn needs_another_pass(&self) -> bool {
+        self.declarations.values().any(|declaration| {
+            let Declaration::Enum { variants, .. } = declaration else {
+                return false;
+            };
+            let has_done = variants
+                .iter()
+                .any(|(_, s)| matches!(s, VariantState::Done(_)));
+            variants.iter().any(|(_, s)| match s {
+                VariantState::Unexplored => true,
+                VariantState::Deferred => has_done,
+                _ => false,
+            })
+        })
+    }
+}
+
+/// Describe `T`, adding the types it uses to `registry`.
+pub(crate) fn trace<T: DeserializeOwned>(registry: &mut TypeRegistry) -> TsType {
+    let mut traced = TsType::Unknown;
+
+    for _ in 0..MAX_PASSES {
+        registry.stack.clear();
+        registry.recursion = None;
+
+        let mut ty = TsType::Unknown;
+        match T::deserialize(Tracer {
+            registry: &mut *registry,
+            ty: &mut ty,
+        }) {
+            Ok(_) => traced = ty,
+            Err(e) => tracing::debug!("Could not trace {}: {}", std::any::type_name::<T>(), e),
+        }
+
+        if !registry.needs_another_pass() {
+            break;
+        }
+    }
+    traced
+}
+
+/// A command's name and types, for rendering.
+pub(crate) struct Command {
+    pub(crate) name: String,
+    pub(crate) args: TsType,
+    pub(crate) result: TsType,
+}
+
+const HEADER: &str =
+    "// Generated from the IPC commands registered with wpe's IpcRouter. Do not edit.\n";
+
+const BRIDGE: &str = r#"/** Options for `wpe.call`. */
+export interface CallOptions {
+    /** Milliseconds before the call is rejected; 0 or null waits indefinitely. */
+    timeout?: number | null;
+    /** Cancels the call in Rust when aborted. */
+    signal?: AbortSignal;
+    /** Receives the handler's progress reports. */
+    onProgress?: (progress: unknown) => void;
+}
+
+/** The IPC bridge, typed by `Commands`. */
+export interface Wpe {
+    call<K extends keyof Commands>(type: K, payload: Commands[K]["args"], options?: CallOptions): Promise<Commands[K]["result"]>;
+    send<K extends keyof Commands>(type: K, payload: Commands[K]["args"]): void;
+    onMessage(callback: (message: unknown) => void): void;
+    subscribe(topic: string, callback: (value: unknown) => void): () => void;
+    sendBytes(type: string, data: BufferSource | Blob): Promise<void>;
+    sendStream(type: string, source: Blob | ReadableStream<Uint8Array>, chunkSize?: number): Promise<void>;
+    onBytes(callback: (type: string, data: ArrayBuffer) => void): void;
+    onStream(callback: (type: string, stream: ReadableStream<Uint8Array>, length: number | null) => void): void;
+    hasNativeHandler(): boolean;
+}
+"#;
+
+/// Render a `.d.ts` file declaring the traced types and commands, with the
+/// bridge installed as `window[global]`.
+pub(crate) fn definitions(registry: &TypeRegistry, commands: &[Command], global: &str) -> String {
+    let mut out = String::from(HEADER);
+
+    for (name, declaration) in &registry.declarations {
+        out.push('\n');
+        render_declaration(&mut out, name, declaration);
+    }
+
+    out.push_str("\n/** Argument and result types of each IPC command. */\n");
+    if commands.is_empty() {
+        out.push_str("export interface Commands {}\n");
+    } else {
+        out.push_str("export interface Commands {\n");
+        for command in commands {
+            let _ = writeln!(
+                out,
+                "    {}: {{ args: {}; result: {} }};",
+                property(&command.name),
+                command.args,
+                command.result
+            );
+        }
+        out.push_str("}\n");
+    }
+
+    out.push('\n');
+    out.push_str(BRIDGE);
+    let _ = write!(
+        out,
+        "\ndeclare global {{\n    interface Window {{\n        {}: Wpe;\n    }}\n}}\n",
+        property(global)
+    );
+    out
+}
+
+/// Render a TypeScript module with the definitions and a typed `commands` client.
+pub(crate) fn client(registry: &TypeRegistry, commands: &[Command], global: &str) -> String {
+    let mut out = definitions(registry, commands, global);
+    let bridge = if property(global) == global {
+        format!("window.{global}")
+    } else {
+        format!("window[{}]", property(global))
+    };
+
+    let _ = write!(
+        out,
+        "\n/** Typed wrappers around `{bridge}.call`. */\nexport const commands = {{\n"
+    );
+    for command in commands {
+        let name = serde_json::Value::String(command.name.clone());
+        let _ = if command.args == TsType::Null {
+            writeln!(
+                out,
+                "    {}: (options?: CallOptions) => {bridge}.call({name}, null, options),",
+                property(&command.name)
+            )
+        } else {
+            writeln!(
+                out,
+                "    {}: (payload: Commands[{name}][\"args\"], options?: CallOptions) => \
+                 {bridge}.call({name}, payload, options),",
+                property(&command.name)
+            )
+        };
+    }
+    out.push_str("};\n");
+    out
+}
+
+fn render_declaration(out: &mut String, name: &str, declaration: &Declaration) {
+    match declaration {
+        Declaration::Interface(fields) => {
+            let _ = writeln!(out, "export interface {name} {{");
+            for (field, ty) in fields {
+                let _ = writeln!(out, "    {}: {ty};", property(field));
+            }
+            out.push_str("}\n");
+        }
+        Declaration::Alias(ty) => {
+            let _ = writeln!(out, "export type {name} = {ty};");
+        }
+        Declaration::Enum { variants, .. } => {
+            let _ = write!(out, "export type {name} =");
+            if variants.is_empty() {
+                out.push_str(" never");
+            }
+            for (variant, state) in variants {
+                let key = property(variant);
+                let _ = match state {
+                    VariantState::Done(VariantShape::Unit) => {
+                        write!(out, "\n    | {}", serde_json::Value::from(*variant))
+                    }
+                    VariantState::Done(VariantShape::Newtype(ty)) => {
+                        write!(out, "\n    | {{ {key}: {ty} }}")
+                    }
+                    VariantState::Done(VariantShape::Tuple(types)) => {
+                        write!(out, "\n    | {{ {key}: {} }}", TsType::Tuple(types.clone()))
+                    }
+                    VariantState::Done(VariantShape::Struct(fields)) => {
+                        let body: Vec<_> = fields
+                            .iter()
+                            .map(|(field, ty)| format!("{}: {ty}", property(field)))
+                            .collect();
+                        write!(out, "\n    | {{ {key}: {{ {} }} }}", body.join("; "))
+                    }
+                    _ => write!(out, "\n    | {{ {key}: unknown }}"),
+                };
+            }
+            out.push_str(";\n");
+        }
+    }
+}
+
+/// An object property name, quoted unless it is a plain identifier.
+fn property(name: &str) -> String {
+    let mut chars = name.chars();
+    let identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if identifier {
+        name.to_string()
+    } else {
+        serde_json::Value::from(name).to_string()
+    }
+}
+
+/// Error raised while tracing.
+#[derive(Debug)]
+pub(crate) struct TraceError(String);
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+fn recursive(name: &str) -> TraceError {
+    TraceError(format!(
+        "'{name}' contains itself outside of a sequence or enum"
+    ))
+}
+
+/// Deserializer that records the type it is asked to produce into `ty`.
+struct Tracer<'a> {
+    registry: &'a mut TypeRegistry,
+    ty: &'a mut TsType,
+}
+
+macro_rules! trace_primitive {
+    ($($method:ident => $ty:ident, $visit:ident($($value:expr)?);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+                *self.ty = TsType::$ty;
+                visitor.$visit($($value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Tracer<'_> {
+    type Error = TraceError;
+
+    trace_primitive! {
+        deserialize_bool => Boolean, visit_bool(false);
+        deserialize_i8 => Number, visit_i8(0);
+        deserialize_i16 => Number, visit_i16(0);
+        deserialize_i32 => Number, visit_i32(0);
+        deserialize_i64 => Number, visit_i64(0);
+        deserialize_i128 => Number, visit_i128(0);
+        deserialize_u8 => Number, visit_u8(0);
+        deserialize_u16 => Number, visit_u16(0);
+        deserialize_u32 => Number, visit_u32(0);
+        deserialize_u64 => Number, visit_u64(0);
+        deserialize_u128 => Number, visit_u128(0);
+        deserialize_f32 => Number, visit_f32(0.0);
+        deserialize_f64 => Number, visit_f64(0.0);
+        deserialize_char => String, visit_char('\0');
+        deserialize_str => String, visit_str("");
+        deserialize_string => String, visit_string(String::new());
+        deserialize_identifier => String, visit_str("");
+        deserialize_unit => Null, visit_unit();
+    }
+
+    /// Self-describing types such as `serde_json::Value` accept anything.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        *self.ty = TsType::Unknown;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        *self.ty = TsType::Array(Box::new(TsType::Number));
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        *self.ty = TsType::Array(Box::new(TsType::Number));
+        visitor.visit_byte_buf(Vec::new())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut inner = TsType::Unknown;
+        let value = visitor.visit_some(Tracer {
+            registry: self.registry,
+            ty: &mut inner,
+        })?;
+        *self.ty = TsType::Nullable(Box::new(inner));
+        Ok(value)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        *self.ty = TsType::Null;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let registry = self.registry;
+        if registry.stack.contains(&name) {
+            registry.recursion = Some(name);
+            return Err(recursive(name));
+        }
+
+        registry.stack.push(name);
+        let mut inner = TsType::Unknown;
+        let value = visitor.visit_newtype_struct(Tracer {
+            registry: &mut *registry,
+            ty: &mut inner,
+        });
+        registry.stack.pop();
+
+        let value = value?;
+        registry.declare(name, Declaration::Alias(inner));
+        *self.ty = TsType::Named(name);
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut types = Vec::new();
+        let value = visitor.visit_seq(SeqTracer {
+            registry: self.registry,
+            types: &mut types,
+            remaining: 1,
+            sample: true,
+        })?;
+        *self.ty = TsType::Array(Box::new(types.pop().unwrap_or(TsType::Unknown)));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut types = Vec::with_capacity(len);
+        let value = visitor.visit_seq(SeqTracer {
+            registry: self.registry,
+            types: &mut types,
+            remaining: len,
+            sample: false,
+        })?;
+        *self.ty = TsType::Tuple(types);
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let registry = self.registry;
+        let mut tuple = TsType::Unknown;
+        let value = Tracer {
+            registry: &mut *registry,
+            ty: &mut tuple,
+        }
+        .deserialize_tuple(len, visitor)?;
+        registry.declare(name, Declaration::Alias(tuple));
+        *self.ty = TsType::Named(name);
+        Ok(value)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut value_type = TsType::Unknown;
+        let value = visitor.visit_map(EntryTracer {
+            registry: self.registry,
+            value: &mut value_type,
+            state: EntryState::Key,
+        })?;
+        *self.ty = TsType::Record(Box::new(value_type));
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let registry = self.registry;
+        if registry.stack.contains(&name) {
+            registry.recursion = Some(name);
+            return Err(recursive(name));
+        }
+
+        registry.stack.push(name);
+        let mut types = Vec::with_capacity(fields.len());
+        let value = visitor.visit_map(FieldTracer {
+            registry: &mut *registry,
+            fields,
+            types: &mut types,
+        });
+        registry.stack.pop();
+
+        let value = value?;
+        registry.declare(
+            name,
+            Declaration::Interface(fields.iter().copied().zip(types).collect()),
+        );
+        *self.ty = TsType::Named(name);
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let registry = self.registry;
+        let recursive_use = registry.stack.contains(&name);
+        let Some(index) = registry.choose_variant(name, variants, recursive_use) else {
+            registry.recursion = Some(name);
+            return Err(recursive(name));
+        };
+
+        if !recursive_use {
+            registry.stack.push(name);
+        }
+        let mut shape = None;
+        let value = visitor.visit_enum(VariantTracer {
+            registry: &mut *registry,
+            variant: variants[index],
+            shape: &mut shape,
+        });
+        if !recursive_use {
+            registry.stack.pop();
+            registry.record_variant(name, index, value.as_ref().ok().and(shape));
+        }
+
+        *self.ty = TsType::Named(name);
+        value
+    }
+}
+
+/// Sequence access yielding traced elements.
+///
+/// In `sample` mode (variable-length sequences) one element is traced, and a
+/// recursive element type ends the sequence instead of failing.
+struct SeqTracer<'a> {
+    registry: &'a mut TypeRegistry,
+    types: &'a mut Vec<TsType>,
+    remaining: usize,
+    sample: bool,
+}
+
+impl<'de> SeqAccess<'de> for SeqTracer<'_> {
+    type Error = TraceError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TraceError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let mut ty = TsType::Unknown;
+        let result = seed.deserialize(Tracer {
+            registry: &mut *self.registry,
+            ty: &mut ty,
+        });
+        match result {
+            Ok(value) => {
+                self.types.push(ty);
+                Ok(Some(value))
+            }
+            Err(e) => match self.registry.recursion.take() {
+                Some(name) if self.sample => {
+                    self.types.push(TsType::Named(name));
+                    Ok(None)
+                }
+                recursion => {
+                    self.registry.recursion = recursion;
+                    Err(e)
+                }
+            },
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+enum EntryState {
+    Key,
+    Value,
+    Done,
+}
+
+/// Map access yielding one traced entry.
+struct EntryTracer<'a> {
+    registry: &'a mut TypeRegistry,
+    value: &'a mut TsType,
+    state: EntryState,
+}
+
+impl<'de> MapAccess<'de> for EntryTracer<'_> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        if !matches!(self.state, EntryState::Key) {
+            return Ok(None);
+        }
+        self.state = EntryState::Value;
+
+        // JSON object keys are always strings
+        let mut key = TsType::Unknown;
+        seed.deserialize(Tracer {
+            registry: &mut *self.registry,
+            ty: &mut key,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TraceError> {
+        self.state = EntryState::Done;
+        seed.deserialize(Tracer {
+            registry: &mut *self.registry,
+            ty: &mut *self.value,
+        })
+    }
+}
+
+/// Map access yielding each struct field once.
+struct FieldTracer<'a> {
+    registry: &'a mut TypeRegistry,
+    fields: &'static [&'static str],
+    types: &'a mut Vec<TsType>,
+}
+
+impl<'de> MapAccess<'de> for FieldTracer<'_> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        let Some(field) = self.fields.get(self.types.len()) else {
+            return Ok(None);
+        };
+        seed.deserialize(field.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut ty = TsType::Unknown;
+        let value = seed.deserialize(Tracer {
+            registry: &mut *self.registry,
+            ty: &mut ty,
+        })?;
+        self.types.push(ty);
+        Ok(value)
+    }
+}
+
+/// Enum access choosing one variant and recording its shape.
+struct VariantTracer<'a> {
+    registry: &'a mut TypeRegistry,
+    variant: &'static str,
+    shape: &'a mut Option<VariantShape>,
+}
+
+impl<'de> EnumAccess<'de> for VariantTracer<'_> {
+    type Error = TraceError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), TraceError> {
+        let value = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantTracer<'_> {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<(), TraceError> {
+        *self.shape = Some(VariantShape::Unit);
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, TraceError> {
+        let mut ty = TsType::Unknown;
+        let value = seed.deserialize(Tracer {
+            registry: self.registry,
+            ty: &mut ty,
+        })?;
+        *self.shape = Some(VariantShape::Newtype(ty));
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut types = Vec::with_capacity(len);
+        let value = visitor.visit_seq(SeqTracer {
+            registry: self.registry,
+            types: &mut types,
+            remaining: len,
+            sample: false,
+        })?;
+        *self.shape = Some(VariantShape::Tuple(types));
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut types = Vec::with_capacity(fields.len());
+        let value = visitor.visit_map(FieldTracer {
+            registry: self.registry,
+            fields,
+            types: &mut types,
+        })?;
+        *self.shape = Some(VariantShape::Struct(
+            fields.iter().copied().zip(types).collect(),
+        ));
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    #[serde(rename_all = "camelCase")]
+    struct Tree {
+        node_id: u32,
+        label: Option<String>,
+        children: Vec<Tree>,
+        tags: HashMap<String, bool>,
+        #[serde(skip)]
+        cache: u8,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Expr {
+        Add(Box<Expr>, Box<Expr>),
+        Num(f64),
+        #[serde(rename = "nil")]
+        Nil,
+        Call {
+            name: String,
+            args: Vec<Expr>,
+        },
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Meters(f64);
+
+    fn render(registry: &TypeRegistry) -> String {
+        definitions(registry, &[], "wpe")
+    }
+
+    #[test]
+    fn test_trace_struct() {
+        let mut registry = TypeRegistry::default();
+        assert_eq!(trace::<Tree>(&mut registry), TsType::Named("Tree"));
+        assert!(render(&registry).contains(
+            "export interface Tree {\n    nodeId: number;\n    label: string | null;\n    \
+             children: Tree[];\n    tags: Record<string, boolean>;\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_trace_recursive_enum() {
+        let mut registry = TypeRegistry::default();
+        assert_eq!(trace::<Vec<Expr>>(&mut registry).to_string(), "Expr[]");
+        assert!(render(&registry).contains(
+            "export type Expr =\n    | { Add: [Expr, Expr] }\n    | { Num: number }\n    \
+             | \"nil\"\n    | { Call: { name: string; args: Expr[] } };\n"
+        ));
+    }
+
+    #[test]
+    fn test_trace_primitives() {
+        let mut registry = TypeRegistry::default();
+        assert_eq!(
+            trace::<(i64, String)>(&mut registry).to_string(),
+            "[number, string]"
+        );
+        assert_eq!(trace::<()>(&mut registry).to_string(), "null");
+        assert_eq!(
+            trace::<serde_json::Value>(&mut registry).to_string(),
+            "unknown"
+        );
+        assert_eq!(
+            trace::<Vec<Option<bool>>>(&mut registry).to_string(),
+            "(boolean | null)[]"
+        );
+        assert_eq!(trace::<Meters>(&mut registry).to_string(), "Meters");
+        assert!(render(&registry).contains("export type Meters = number;\n"));
+    }
+
+    #[test]
+    fn test_property_names() {
+        assert_eq!(property("read_file"), "read_file");
+        assert_eq!(property("read-file"), "\"read-file\"");
+        assert_eq!(property("2d"), "\"2d\"");
+    }
+}
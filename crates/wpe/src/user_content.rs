@@ -0,0 +1,446 @@
+//! User scripts and style sheets injected into every page.
+//!
+//! Unlike scripts evaluated after a load, user content is attached to the
+//! web view's user content manager and injected by WebKit into each
+//! matching document, including pages loaded with `load_url`. The IPC
+//! bridge ([`crate::ipc::JS_BRIDGE`]) is installed this way at document
+//! start in the top frame of every web view.
+//!
+//! ```rust,ignore
+//! use wpe::{InjectedFrames, InjectionTime, UserScript, UserStyleSheet};
+//!
+//! webview.add_user_script(
+//!     &UserScript::new("window.kiosk = { id: 7 };")
+//!         .with_injection_time(InjectionTime::DocumentStart)
+//!         .with_allow_pattern("https://kiosk.example.com/*"),
+//! )?;
+//! webview.add_user_style_sheet(&UserStyleSheet::new("::-webkit-scrollbar { display: none; }"))?;
+//! ```
+//!
+//! Patterns use WebKit's match pattern syntax, e.g. `https://*.example.com/*`.
+
+use std::ffi::CString;
+use std::ptr;
+
+use crate::ipc::IpcConfig;
+use crate::{Error, Result};
+
+/// When a user script runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InjectionTime {
+    /// Before any of the document's own scripts.
+    DocumentStart,
+    /// After the document has been parsed, before subresources finish loading.
+    #[default]
+    DocumentEnd,
+}
+
+/// Which frames user content is injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InjectedFrames {
+    /// Only the top-level document.
+    #[default]
+    TopFrame,
+    /// The top-level document and all iframes.
+    AllFrames,
+}
+
+impl InjectedFrames {
+    fn to_webkit(self) -> wpe_sys::WebKitUserContentInjectedFrames {
+        match self {
+            Self::TopFrame => {
+                wpe_sys::WebKitUserContentInjectedFrames_WEBKIT_USER_CONTENT_INJECT_TOP_FRAME
+            }
+            Self::AllFrames => {
+                wpe_sys::WebKitUserContentInjectedFrames_WEBKIT_USER_CONTENT_INJECT_ALL_FRAMES
+            }
+        }
+    }
+}
+
+/// The cascade level of a user style sheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StyleLevel {
+    /// Like a browser user style sheet; page styles take precedence.
+    User,
+    /// Like a page style sheet; overrides page styles of equal specificity.
+    #[default]
+    Author,
+}
+
+/// A script injected into matching documents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserScript {
+    source: String,
+    injection_time: InjectionTime,
+    frames: InjectedFrames,
+    world: Option<String>,
+    allow_patterns: Vec<String>,
+    block_patterns: Vec<String>,
+}
+
+impl UserScript {
+    /// A script run at document end in the top frame of every page.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            injection_time: InjectionTime::default(),
+            frames: InjectedFrames::default(),
+            world: None,
+            allow_patterns: Vec::new(),
+            block_patterns: Vec::new(),
+        }
+    }
+
+    /// Set when the script runs.
+    #[must_use]
+    pub fn with_injection_time(mut self, injection_time: InjectionTime) -> Self {
+        self.injection_time = injection_time;
+        self
+    }
+
+    /// Set which frames the script runs in.
+    #[must_use]
+    pub fn with_frames(mut self, frames: InjectedFrames) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// Run the script in an isolated script world instead of the page's.
+    #[must_use]
+    pub fn with_world(mut self, name: impl Into<String>) -> Self {
+        self.world = Some(name.into());
+        self
+    }
+
+    /// Only run in documents whose URL matches `pattern`.
+    ///
+    /// Can be called repeatedly; a document matching any pattern qualifies.
+    #[must_use]
+    pub fn with_allow_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.allow_patterns.push(pattern.into());
+        self
+    }
+
+    /// Never run in documents whose URL matches `pattern`.
+    #[must_use]
+    pub fn with_block_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.block_patterns.push(pattern.into());
+        self
+    }
+
+    /// The script source.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Create the `WebKitUserScript`, owned by the caller.
+    #[allow(unsafe_code)]
+    fn to_raw(&self) -> Result<*mut wpe_sys::WebKitUserScript> {
+        let source = to_cstring(&self.source)?;
+        let world = self.world.as_deref().map(to_cstring).transpose()?;
+        let allow = PatternList::new(&self.allow_patterns)?;
+        let block = PatternList::new(&self.block_patterns)?;
+
+        let injection_time = match self.injection_time {
+            InjectionTime::DocumentStart => {
+                wpe_sys::WebKitUserScriptInjectionTime_WEBKIT_USER_SCRIPT_INJECT_AT_DOCUMENT_START
+            }
+            InjectionTime::DocumentEnd => {
+                wpe_sys::WebKitUserScriptInjectionTime_WEBKIT_USER_SCRIPT_INJECT_AT_DOCUMENT_END
+            }
+        };
+
+        // SAFETY: all strings are valid for the call and copied by WebKit.
+        Ok(unsafe {
+            match world {
+                Some(world) => wpe_sys::webkit_user_script_new_for_world(
+                    source.as_ptr(),
+                    self.frames.to_webkit(),
+                    injection_time,
+                    world.as_ptr(),
+                    allow.as_ptr(),
+                    block.as_ptr(),
+                ),
+                None => wpe_sys::webkit_user_script_new(
+                    source.as_ptr(),
+                    self.frames.to_webkit(),
+                    injection_time,
+                    allow.as_ptr(),
+                    block.as_ptr(),
+                ),
+            }
+        })
+    }
+}
+
+/// A style sheet applied to matching documents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserStyleSheet {
+    source: String,
+    level: StyleLevel,
+    frames: InjectedFrames,
+    allow_patterns: Vec<String>,
+    block_patterns: Vec<String>,
+}
+
+impl UserStyleSheet {
+    /// An author-level style sheet applied to the top frame of every page.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            level: StyleLevel::default(),
+            frames: InjectedFrames::default(),
+            allow_patterns: Vec::new(),
+            block_patterns: Vec::new(),
+        }
+    }
+
+    /// Set the cascade level.
+    #[must_use]
+    pub fn with_level(mut self, level: StyleLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Set which frames the style sheet applies to.
+    #[must_use]
+    pub fn with_frames(mut self, frames: InjectedFrames) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// Only apply to documents whose URL matches `pattern`.
+    #[must_use]
+    pub fn with_allow_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.allow_patterns.push(pattern.into());
+        self
+    }
+
+    /// Never apply to documents whose URL matches `pattern`.
+    #[must_use]
+    pub fn with_block_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.block_patterns.push(pattern.into());
+        self
+    }
+
+    /// The CSS source.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Create the `WebKitUserStyleSheet`, owned by the caller.
+    #[allow(unsafe_code)]
+    fn to_raw(&self) -> Result<*mut wpe_sys::WebKitUserStyleSheet> {
+        let source = to_cstring(&self.source)?;
+        let allow = PatternList::new(&self.allow_patterns)?;
+        let block = PatternList::new(&self.block_patterns)?;
+
+        let level = match self.level {
+            StyleLevel::User => wpe_sys::WebKitUserStyleLevel_WEBKIT_USER_STYLE_LEVEL_USER,
+            StyleLevel::Author => wpe_sys::WebKitUserStyleLevel_WEBKIT_USER_STYLE_LEVEL_AUTHOR,
+        };
+
+        // SAFETY: all strings are valid for the call and copied by WebKit.
+        Ok(unsafe {
+            wpe_sys::webkit_user_style_sheet_new(
+                source.as_ptr(),
+                self.frames.to_webkit(),
+                level,
+                allow.as_ptr(),
+                block.as_ptr(),
+            )
+        })
+    }
+}
+
+/// A NULL-terminated array of C strings, or NULL when empty.
+struct PatternList {
+    _strings: Vec<CString>,
+    pointers: Vec<*const std::ffi::c_char>,
+}
+
+impl PatternList {
+    fn new(patterns: &[String]) -> Result<Self> {
+        let strings = patterns
+            .iter()
+            .map(|p| to_cstring(p))
+            .collect::<Result<Vec<_>>>()?;
+        let mut pointers: Vec<_> = strings.iter().map(|s| s.as_ptr()).collect();
+        if !pointers.is_empty() {
+            pointers.push(ptr::null());
+        }
+        Ok(Self {
+            _strings: strings,
+            pointers,
+        })
+    }
+
+    fn as_ptr(&self) -> *const *const std::ffi::c_char {
+        if self.pointers.is_empty() {
+            ptr::null()
+        } else {
+            self.pointers.as_ptr()
+        }
+    }
+}
+
+fn to_cstring(s: &str) -> Result<CString> {
+    CString::new(s).map_err(|e| Error::JavaScriptError(e.to_string()))
+}
+
+/// User content added to a web view by the app.
+///
+/// Built-in scripts (the IPC bridge) are added to the
+/// user content manager directly, so clearing app content leaves them alone.
+pub(crate) struct UserContent {
+    manager: *mut wpe_sys::WebKitUserContentManager,
+    scripts: Vec<*mut wpe_sys::WebKitUserScript>,
+    style_sheets: Vec<*mut wpe_sys::WebKitUserStyleSheet>,
+}
+
+impl UserContent {
+    /// Track app content added to `manager`.
+    pub(crate) fn new(manager: *mut wpe_sys::WebKitUserContentManager) -> Self {
+        Self {
+            manager,
+            scripts: Vec::new(),
+            style_sheets: Vec::new(),
+        }
+    }
+
+    /// Add a script, effective from the next document load.
+    #[allow(unsafe_code)]
+    pub(crate) fn add_script(&mut self, script: &UserScript) -> Result<()> {
+        let raw = script.to_raw()?;
+        // SAFETY: manager is valid for the web view's lifetime; raw is ours.
+        unsafe {
+            wpe_sys::webkit_user_content_manager_add_script(self.manager, raw);
+        }
+        self.scripts.push(raw);
+        Ok(())
+    }
+
+    /// Add a style sheet, effective from the next document load.
+    #[allow(unsafe_code)]
+    pub(crate) fn add_style_sheet(&mut self, style_sheet: &UserStyleSheet) -> Result<()> {
+        let raw = style_sheet.to_raw()?;
+        // SAFETY: manager is valid for the web view's lifetime; raw is ours.
+        unsafe {
+            wpe_sys::webkit_user_content_manager_add_style_sheet(self.manager, raw);
+        }
+        self.style_sheets.push(raw);
+        Ok(())
+    }
+
+    /// Remove all scripts added with [`UserContent::add_script`].
+    #[allow(unsafe_code)]
+    pub(crate) fn clear_scripts(&mut self) {
+        for raw in self.scripts.drain(..) {
+            // SAFETY: raw was added to manager by add_script and is still ours.
+            unsafe {
+                wpe_sys::webkit_user_content_manager_remove_script(self.manager, raw);
+                wpe_sys::webkit_user_script_unref(raw);
+            }
+        }
+    }
+
+    /// Remove all style sheets added with [`UserContent::add_style_sheet`].
+    #[allow(unsafe_code)]
+    pub(crate) fn clear_style_sheets(&mut self) {
+        for raw in self.style_sheets.drain(..) {
+            // SAFETY: raw was added to manager by add_style_sheet and is still ours.
+            unsafe {
+                wpe_sys::webkit_user_content_manager_remove_style_sheet(self.manager, raw);
+                wpe_sys::webkit_user_style_sheet_unref(raw);
+            }
+        }
+    }
+}
+
+impl Drop for UserContent {
+    #[allow(unsafe_code)]
+    fn drop(&mut self) {
+        // The manager keeps its own references; only release ours
+        // SAFETY: every pointer was created by to_raw and is owned by us.
+        unsafe {
+            for raw in self.scripts.drain(..) {
+                wpe_sys::webkit_user_script_unref(raw);
+            }
+            for raw in self.style_sheets.drain(..) {
+                wpe_sys::webkit_user_style_sheet_unref(raw);
+            }
+        }
+    }
+}
+
+/// Install the IPC bridge in every document loaded by `manager`'s web view.
+///
+/// Only the top frame gets the bridge, so iframes (including third-party
+/// ones) have no `wpe` API and replies never reach the wrong frame. With a
+/// `world`, the bridge only exists in that isolated script world.
+///
+/// # Safety
+/// `manager` must be a valid `WebKitUserContentManager`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn install_bridge(
+    manager: *mut wpe_sys::WebKitUserContentManager,
+    world: Option<&str>,
+    config: &IpcConfig,
+) {
+    let mut bridge = UserScript::new(config.bridge_script())
+        .with_injection_time(InjectionTime::DocumentStart)
+        .with_frames(InjectedFrames::TopFrame);
+    if let Some(world) = world {
+        bridge = bridge.with_world(world);
+    }
+
+    match bridge.to_raw() {
+        Ok(raw) => {
+            wpe_sys::webkit_user_content_manager_add_script(manager, raw);
+            wpe_sys::webkit_user_script_unref(raw);
+            tracing::debug!("Installed IPC bridge user script");
+        }
+        Err(e) => tracing::error!("Failed to install IPC bridge: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_script_builder() {
+        let script = UserScript::new("1 + 1")
+            .with_injection_time(InjectionTime::DocumentStart)
+            .with_frames(InjectedFrames::AllFrames)
+            .with_world("app")
+            .with_allow_pattern("https://example.com/*")
+            .with_block_pattern("https://example.com/admin/*");
+
+        assert_eq!(script.source(), "1 + 1");
+        assert_eq!(script.injection_time, InjectionTime::DocumentStart);
+        assert_eq!(script.frames, InjectedFrames::AllFrames);
+        assert_eq!(script.world.as_deref(), Some("app"));
+        assert_eq!(script.allow_patterns, ["https://example.com/*"]);
+        assert_eq!(script.block_patterns, ["https://example.com/admin/*"]);
+
+        let defaults = UserStyleSheet::new("body {}");
+        assert_eq!(defaults.level, StyleLevel::Author);
+        assert_eq!(defaults.frames, InjectedFrames::TopFrame);
+    }
+
+    #[test]
+    fn test_pattern_list() {
+        let empty = PatternList::new(&[]).unwrap();
+        assert!(empty.as_ptr().is_null());
+
+        let list = PatternList::new(&["a://*".to_string(), "b://*".to_string()]).unwrap();
+        assert_eq!(list.pointers.len(), 3);
+        assert!(list.pointers[2].is_null());
+
+        assert!(PatternList::new(&["bad\0pattern".to_string()]).is_err());
+    }
+}
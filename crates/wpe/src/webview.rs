@@ -1,10 +1,36 @@
 //! WPE WebKit WebView implementation using the Platform API.
 
+use std::cell::RefCell;
 use std::ffi::CString;
+use std::io::Read;
 use std::ptr;
-use std::sync::Once;
-
+use std::rc::Rc;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::assets::{self, StaticAssets};
+use crate::binary::{self, BinaryChannel, BinaryMessage};
+use crate::content_filter::ContentFilter;
+use crate::download::{self, Download, DownloadDestination, DownloadEvent, DownloadState};
+use crate::filter::{self, FilterState, DocumentFilter};
+use crate::ipc::{self, FrontendMessage, IpcConfig, MessageHandlerState, MessageQueue, MessageQueueInner};
+use crate::native::{
+    self, EventQueue, EventQueueInner, LoadFuture, LoadTracker, NavigationCounter, NavigationEvent,
+};
+use crate::policy::{self, NavigationPolicy, PolicySlot, PolicySlotInner};
+use crate::popup::{self, NewWindow, NewWindowRequest, PopupState};
 use crate::renderer::SharedFrameBuffer;
+use crate::script::{self, ScriptFuture};
+use crate::settings::{
+    self as webkit_settings, AutoplayPolicy, HardwareAccelerationPolicy, RuntimeSettings,
+};
+use crate::snapshot::{self, Snapshot, SnapshotRegion};
+use crate::subscription::{Subscription, SubscriptionHub};
+use crate::user_content::{self, UserContent, UserScript, UserStyleSheet};
 use crate::{Error, Result};
 
 static INIT: Once = Once::new();
@@ -67,8 +93,51 @@ pub fn initialize() -> Result<()> {
     }
 }
 
+/// Default viewport width in logical pixels.
+const DEFAULT_WIDTH: u32 = 1280;
+
+/// Default viewport height in logical pixels.
+const DEFAULT_HEIGHT: u32 = 720;
+
+/// Default window title.
+const DEFAULT_TITLE: &str = "WPE WebView";
+
+/// How long [`WebView::snapshot`] waits for the page to finish loading.
+const SNAPSHOT_LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long [`WebView::snapshot`] waits for a new frame once loaded.
+///
+/// Static pages may not repaint after load, in which case the last frame is used.
+const SNAPSHOT_FRAME_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a full-page capture waits for a frame at the document size.
+const FULL_PAGE_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tallest frame a full-page capture renders at once, in physical pixels.
+///
+/// Taller documents are scrolled through and captured in tiles of this height.
+const FULL_PAGE_MAX_HEIGHT: u32 = 16384;
+
+/// Most tiles a full-page capture stitches together.
+const FULL_PAGE_MAX_TILES: u32 = 16;
+
+/// Evaluates to the scrollable height of the document in CSS pixels.
+const DOCUMENT_HEIGHT_SCRIPT: &str = "Math.ceil(Math.max(\
+    document.documentElement.scrollHeight, \
+    document.body ? document.body.scrollHeight : 0))";
+
+/// Evaluates to the vertical scroll offset of the document in CSS pixels.
+const SCROLL_Y_SCRIPT: &str = "Math.round(window.scrollY)";
+
+/// Script scrolling the document to `y` CSS pixels and evaluating to the
+/// offset it actually reached.
+fn scroll_to_script(y: u32) -> String {
+    format!("window.scrollTo({{ top: {y}, behavior: \"instant\" }}); {SCROLL_Y_SCRIPT}")
+}
+
 /// Settings for creating a WebView.
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct WebViewSettings {
     /// Initial URL to load
     pub url: Option<String>,
@@ -80,6 +149,40 @@ pub struct WebViewSettings {
     pub javascript_enabled: bool,
     /// User agent string
     pub user_agent: Option<String>,
+    /// Static assets served on `app://localhost/`
+    pub assets: Option<StaticAssets>,
+    /// Initial viewport width in logical pixels
+    pub width: u32,
+    /// Initial viewport height in logical pixels
+    pub height: u32,
+    /// Device scale factor (`None` uses the platform's scale, or 1.0 if it has none)
+    pub scale_factor: Option<f64>,
+    /// Window title
+    pub title: String,
+    /// Enable WebGL
+    pub webgl_enabled: bool,
+    /// Media autoplay policy
+    pub autoplay_policy: AutoplayPolicy,
+    /// Default (proportional) font family
+    pub default_font_family: Option<String>,
+    /// Monospace font family
+    pub monospace_font_family: Option<String>,
+    /// Default font size in CSS pixels
+    pub default_font_size: Option<u32>,
+    /// Default monospace font size in CSS pixels
+    pub default_monospace_font_size: Option<u32>,
+    /// Enable smooth scrolling
+    pub smooth_scrolling: bool,
+    /// Allow `file://` pages to access other `file://` URLs
+    pub local_file_access: bool,
+    /// Allow JavaScript to read and write the clipboard
+    pub javascript_clipboard: bool,
+    /// Hardware acceleration policy
+    pub hardware_acceleration: HardwareAccelerationPolicy,
+    /// Isolated script world for the IPC bridge and evaluated scripts
+    pub script_world: Option<String>,
+    /// IPC bridge handler name, global name and call timeout
+    pub ipc: IpcConfig,
 }
 
 impl Default for WebViewSettings {
@@ -90,6 +193,23 @@ impl Default for WebViewSettings {
             developer_tools: false,
             javascript_enabled: true,
             user_agent: None,
+            assets: None,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            scale_factor: None,
+            title: DEFAULT_TITLE.to_string(),
+            webgl_enabled: true,
+            autoplay_policy: AutoplayPolicy::default(),
+            default_font_family: None,
+            monospace_font_family: None,
+            default_font_size: None,
+            default_monospace_font_size: None,
+            smooth_scrolling: true,
+            local_file_access: false,
+            javascript_clipboard: false,
+            hardware_acceleration: HardwareAccelerationPolicy::default(),
+            script_world: None,
+            ipc: IpcConfig::default(),
         }
     }
 }
@@ -117,11 +237,175 @@ impl WebViewSettings {
         self.developer_tools = enabled;
         self
     }
+
+    #[must_use]
+    pub fn with_javascript_enabled(mut self, enabled: bool) -> Self {
+        self.javascript_enabled = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_webgl(mut self, enabled: bool) -> Self {
+        self.webgl_enabled = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn with_autoplay_policy(mut self, policy: AutoplayPolicy) -> Self {
+        self.autoplay_policy = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_default_font_family(mut self, family: impl Into<String>) -> Self {
+        self.default_font_family = Some(family.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_monospace_font_family(mut self, family: impl Into<String>) -> Self {
+        self.monospace_font_family = Some(family.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_default_font_size(mut self, size: u32) -> Self {
+        self.default_font_size = Some(size);
+        self
+    }
+
+    #[must_use]
+    pub fn with_default_monospace_font_size(mut self, size: u32) -> Self {
+        self.default_monospace_font_size = Some(size);
+        self
+    }
+
+    #[must_use]
+    pub fn with_smooth_scrolling(mut self, enabled: bool) -> Self {
+        self.smooth_scrolling = enabled;
+        self
+    }
+
+    /// Allow `file://` pages to load other `file://` URLs (disabled by default).
+    #[must_use]
+    pub fn with_local_file_access(mut self, enabled: bool) -> Self {
+        self.local_file_access = enabled;
+        self
+    }
+
+    /// Allow JavaScript to access the clipboard without a user gesture.
+    #[must_use]
+    pub fn with_javascript_clipboard(mut self, enabled: bool) -> Self {
+        self.javascript_clipboard = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn with_hardware_acceleration(mut self, policy: HardwareAccelerationPolicy) -> Self {
+        self.hardware_acceleration = policy;
+        self
+    }
+
+    /// Serve `assets` on `app://localhost/` and load it on startup.
+    ///
+    /// An explicit `with_url` still takes precedence for the first load.
+    #[must_use]
+    pub fn with_assets(mut self, assets: StaticAssets) -> Self {
+        self.assets = Some(assets);
+        self
+    }
+
+    /// Set the initial viewport size in logical pixels.
+    #[must_use]
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        self
+    }
+
+    /// Force a device scale factor instead of using the platform's.
+    ///
+    /// Non-positive values are ignored.
+    #[must_use]
+    pub fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+        if scale_factor > 0.0 {
+            self.scale_factor = Some(scale_factor);
+        }
+        self
+    }
+
+    /// Set the window title.
+    #[must_use]
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Run the IPC bridge and evaluated scripts in an isolated script world.
+    ///
+    /// Scripts in the world share the DOM with the page but not its
+    /// JavaScript globals, so page scripts cannot see `window.wpe` or post
+    /// IPC messages. The `wpe://message` fetch fallback is disabled.
+    #[must_use]
+    pub fn with_script_world(mut self, name: impl Into<String>) -> Self {
+        self.script_world = Some(name.into());
+        self
+    }
+
+    /// Configure the IPC bridge installed in every page.
+    #[must_use]
+    pub fn with_ipc(mut self, config: IpcConfig) -> Self {
+        self.ipc = config;
+        self
+    }
+
+    /// The URL to load on startup, if any.
+    pub(crate) fn initial_url(&self) -> Option<&str> {
+        self.url
+            .as_deref()
+            .or_else(|| self.assets.as_ref().map(|_| assets::ASSETS_URL))
+    }
+}
+
+/// Convert a logical length to physical pixels.
+pub(crate) fn to_physical(length: u32, scale_factor: f64) -> u32 {
+    (f64::from(length) * scale_factor).round() as u32
+}
+
+/// Convert a physical length to logical pixels.
+pub(crate) fn to_logical(length: u32, scale_factor: f64) -> u32 {
+    (f64::from(length) / scale_factor).round() as u32
+}
+
+/// Apply a device scale factor to a WPE view through its toplevel.
+///
+/// # Safety
+/// `view` must be a valid `WPEView`.
+#[allow(unsafe_code)]
+pub(crate) unsafe fn set_view_scale(view: *mut wpe_sys::WPEView, scale_factor: f64) {
+    let toplevel = wpe_sys::wpe_view_get_toplevel(view);
+    if toplevel.is_null() {
+        tracing::warn!("View has no toplevel, cannot apply scale factor {}", scale_factor);
+        return;
+    }
+
+    wpe_sys::wpe_toplevel_scale_changed(toplevel, scale_factor);
+    tracing::debug!("Scale factor set to {}", scale_factor);
 }
 
 /// Callback data for render_buffer signal
 struct RenderContext {
     frame_buffer: SharedFrameBuffer,
+    /// Number of frames rendered so far
+    frames: u64,
+    /// Physical size of the last rendered frame
+    frame_size: (u32, u32),
 }
 
 /// Signal handler for render-buffer
@@ -170,6 +454,8 @@ unsafe extern "C" fn on_render_buffer(
         // Assume BGRA format with 4 bytes per pixel
         let stride = width * 4;
         ctx.frame_buffer.copy_from_shm(data_ptr, width, height, stride);
+        ctx.frames += 1;
+        ctx.frame_size = (width, height);
         tracing::trace!("Copied {} bytes to frame buffer", size);
     }
 
@@ -193,29 +479,88 @@ pub struct WebView {
     web_view: *mut wpe_sys::WebKitWebView,
     /// Render context for callbacks
     render_ctx: *mut RenderContext,
-    /// Current width
+    /// Current width in logical pixels
     width: u32,
-    /// Current height
+    /// Current height in logical pixels
     height: u32,
+    /// Device scale factor
+    scale_factor: f64,
     /// Settings used to create this view
     settings: WebViewSettings,
     /// Signal handler ID for render-buffer
     render_signal_id: u64,
+    /// Message queue for incoming messages from JavaScript
+    message_queue: MessageQueue,
+    /// Raw pointer to the script message handler state (for signal handler cleanup)
+    message_handler_ptr: *mut MessageHandlerState,
+    /// Binary messages from JavaScript and data waiting to be fetched by it
+    binary: Arc<BinaryChannel>,
+    /// Count of committed navigations (for cancelling async IPC calls)
+    navigation_count: NavigationCounter,
+    /// Raw pointer to the navigation counter (for signal handler cleanup)
+    navigation_count_ptr: *const AtomicU64,
+    /// Topics the page subscribed to and values waiting to be published
+    subscriptions: Rc<SubscriptionHub>,
+    /// Event queue for navigation events
+    event_queue: EventQueue,
+    /// Raw pointer to event queue (for signal handler cleanup)
+    event_queue_ptr: *const EventQueueInner,
+    /// Load state for `wait_for_load`
+    load_tracker: Arc<LoadTracker>,
+    /// Raw pointer to the load tracker (for signal handler cleanup)
+    load_tracker_ptr: *const LoadTracker,
+    /// Document filter consulted for navigations
+    document_filter: Rc<FilterState>,
+    /// Raw pointer to the filter state (for signal handler cleanup)
+    document_filter_ptr: *const FilterState,
+    /// Navigation policy consulted by the decide-policy handler
+    navigation_policy: PolicySlot,
+    /// Raw pointer to the navigation policy (for signal handler cleanup)
+    navigation_policy_ptr: *const PolicySlotInner,
+    /// New-window handler and the windows it created
+    popups: Rc<PopupState>,
+    /// Raw pointer to the popup state (for signal handler cleanup)
+    popups_ptr: *const PopupState,
+    /// Tracked downloads and their queued events
+    downloads: Rc<DownloadState>,
+    /// Raw pointer to the download state (for signal handler cleanup)
+    downloads_ptr: *const DownloadState,
+    /// Signal handler ID for the network session's download-started
+    download_signal_id: u64,
+    /// User scripts and style sheets added by the app
+    user_content: UserContent,
 }
 
 impl WebView {
     /// Create a new WebView with the given settings and shared frame buffer.
-    #[allow(unsafe_code)]
     pub fn new(settings: WebViewSettings, frame_buffer: SharedFrameBuffer) -> Result<Self> {
+        Self::new_related(settings, frame_buffer, ptr::null_mut())
+    }
+
+    /// Create a WebView sharing a web process with `related` (if not null).
+    #[allow(unsafe_code)]
+    pub(crate) fn new_related(
+        settings: WebViewSettings,
+        frame_buffer: SharedFrameBuffer,
+        related: *mut wpe_sys::WebKitWebView,
+    ) -> Result<Self> {
         initialize()?;
+        if settings.assets.is_some() {
+            assets::register_scheme()?;
+        }
 
-        let width = 1280u32;
-        let height = 720u32;
+        let width = settings.width;
+        let height = settings.height;
+        let scale_factor = settings.scale_factor.unwrap_or(1.0);
 
-        frame_buffer.resize(width, height);
+        frame_buffer.resize(to_physical(width, scale_factor), to_physical(height, scale_factor));
 
         // Create render context
-        let render_ctx = Box::into_raw(Box::new(RenderContext { frame_buffer }));
+        let render_ctx = Box::into_raw(Box::new(RenderContext {
+            frame_buffer,
+            frames: 0,
+            frame_size: (0, 0),
+        }));
 
         // SAFETY: All WPE/GLib API calls require valid pointers which we check.
         // The render_ctx pointer is valid because we just created it with Box::into_raw.
@@ -228,14 +573,22 @@ impl WebView {
             }
 
             // Create a WebKitWebView (it manages its own WPEView internally)
-            // Pass NULL for backend to use the Platform API
-            let web_view = wpe_sys::webkit_web_view_new(ptr::null_mut());
+            let web_view = popup::new_web_view(related);
             if web_view.is_null() {
                 drop(Box::from_raw(render_ctx));
                 tracing::error!("Failed to create WebKitWebView");
                 return Err(Error::WebViewCreationFailed);
             }
 
+            // Get the user content manager for IPC
+            let user_content_manager = wpe_sys::webkit_web_view_get_user_content_manager(web_view);
+            if user_content_manager.is_null() {
+                wpe_sys::g_object_unref(web_view as *mut _);
+                drop(Box::from_raw(render_ctx));
+                tracing::error!("Failed to get user content manager");
+                return Err(Error::WebViewCreationFailed);
+            }
+
             // Get the WPE view from the WebKitWebView
             let view = wpe_sys::webkit_web_view_get_wpe_view(web_view);
             if view.is_null() {
@@ -245,8 +598,73 @@ impl WebView {
                 return Err(Error::WebViewCreationFailed);
             }
 
-            // Set view size
+            webkit_settings::apply(web_view, &settings.runtime_settings());
+
+            // Count committed navigations so async IPC calls can be cancelled
+            // and messages from previous pages dropped
+            let navigation_count: NavigationCounter = Arc::new(AtomicU64::new(0));
+            let navigation_count_ptr =
+                native::connect_navigation_counter(web_view, &navigation_count);
+
+            // Route webkit.messageHandlers messages into the IPC queue
+            let message_queue: MessageQueue = MessageQueueInner::new(&navigation_count);
+            let world = settings.script_world.as_deref();
+            let message_handler_ptr = ipc::connect_message_handler(
+                web_view,
+                user_content_manager,
+                world,
+                &settings.ipc.handler_name,
+                &message_queue,
+            );
+            if world.is_none() {
+                ipc::route_fetch_messages(web_view, &message_queue);
+            }
+            user_content::install_bridge(user_content_manager, world, &settings.ipc);
+            let user_content = UserContent::new(user_content_manager);
+            let binary = Arc::new(BinaryChannel::new(world, &settings.ipc.global_name));
+            binary::route(web_view, &binary);
+
+            if let Some(ref static_assets) = settings.assets {
+                assets::mount(web_view, static_assets);
+            } else {
+                assets::mount_related(web_view, related);
+            }
+
+            let subscriptions = Rc::new(SubscriptionHub::new(
+                web_view,
+                settings.script_world.clone(),
+                &settings.ipc.global_name,
+                &navigation_count,
+            ));
+
+            // Queue navigation events and track when loads finish
+            let event_queue: EventQueue = Arc::new(Mutex::new(VecDeque::new()));
+            let event_queue_ptr = native::connect_navigation_events(web_view, &event_queue);
+            native::connect_close_event(web_view, event_queue_ptr);
+            let load_tracker = Arc::new(LoadTracker::default());
+            let load_tracker_ptr = native::connect_load_tracker(web_view, &load_tracker);
+
+            // Filter requests before the navigation policy sees them
+            let document_filter = Rc::new(FilterState::default());
+            let document_filter_ptr = filter::connect(web_view, &document_filter);
+
+            // Consult the navigation policy, once one is set
+            let navigation_policy: PolicySlot = Rc::new(RefCell::new(None));
+            let navigation_policy_ptr = policy::connect(web_view, &navigation_policy);
+
+            // Hand new-window requests to the app
+            let popups = Rc::new(PopupState::default());
+            let popups_ptr = popup::connect(web_view, &popups);
+
+            // Track downloads started by the page or by download_uri()
+            let downloads = Rc::new(DownloadState::default());
+            let (downloads_ptr, download_signal_id) = download::connect(web_view, &downloads);
+
+            // Set view size and scale
             wpe_sys::wpe_view_resized(view, width as i32, height as i32);
+            if (scale_factor - 1.0).abs() > f64::EPSILON {
+                set_view_scale(view, scale_factor);
+            }
 
             // Make the view visible
             wpe_sys::wpe_view_set_visible(view, 1);
@@ -286,8 +704,29 @@ impl WebView {
                 render_ctx,
                 width,
                 height,
+                scale_factor,
                 settings,
                 render_signal_id,
+                message_queue,
+                message_handler_ptr,
+                binary,
+                navigation_count,
+                navigation_count_ptr,
+                subscriptions,
+                event_queue,
+                event_queue_ptr,
+                load_tracker,
+                load_tracker_ptr,
+                document_filter,
+                document_filter_ptr,
+                navigation_policy,
+                navigation_policy_ptr,
+                popups,
+                popups_ptr,
+                downloads,
+                downloads_ptr,
+                download_signal_id,
+                user_content,
             })
         }
     }
@@ -305,6 +744,7 @@ impl WebView {
         unsafe {
             wpe_sys::webkit_web_view_load_uri(self.web_view, c_url.as_ptr());
         }
+        self.load_tracker.start();
 
         tracing::debug!("Loading URL: {}", url);
         Ok(())
@@ -324,32 +764,319 @@ impl WebView {
                 c_base.as_ref().map_or(ptr::null(), |c| c.as_ptr()),
             );
         }
+        self.load_tracker.start();
 
         tracing::debug!("Loading HTML content ({} bytes)", html.len());
         Ok(())
     }
 
     /// Execute JavaScript in the web view.
-    #[allow(unsafe_code)]
+    ///
+    /// Runs in the isolated script world if one is configured.
     pub fn evaluate_script(&self, script: &str) -> Result<()> {
-        let c_script = CString::new(script).map_err(|e| Error::JavaScriptError(e.to_string()))?;
+        script::run(self.web_view, self.settings.script_world.as_deref(), script)?;
+
+        tracing::debug!("Evaluating script ({} bytes)", script.len());
+        Ok(())
+    }
+
+    /// Execute JavaScript and return its result as a future.
+    ///
+    /// The completion value of the script is converted to JSON and then
+    /// deserialized into `T` (use `serde_json::Value` for untyped results).
+    /// Exceptions thrown by the script resolve to
+    /// [`Error::JavaScriptException`]. The future only makes progress while the
+    /// GLib main context is being iterated.
+    pub fn evaluate_script_with_result<T: DeserializeOwned>(
+        &self,
+        script: &str,
+    ) -> ScriptFuture<T> {
+        let (future, callback) = ScriptFuture::pending();
+        let world = self.settings.script_world.as_deref();
+        match script::evaluate(self.web_view, world, script, callback) {
+            Ok(()) => future,
+            Err(e) => ScriptFuture::failed(e),
+        }
+    }
+
+    /// Execute JavaScript and pass its result to `callback`.
+    ///
+    /// The callback runs on the GLib main loop once evaluation completes.
+    ///
+    /// # Errors
+    /// Returns an error if the script could not be passed to WebKit, in which
+    /// case the callback is never called.
+    pub fn evaluate_script_with_callback<T, F>(&self, script: &str, callback: F) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnOnce(Result<T>) + 'static,
+    {
+        let world = self.settings.script_world.as_deref();
+        script::evaluate(self.web_view, world, script, script::typed_callback(callback))
+    }
+
+    /// Get the settings this view was created with (including later updates).
+    #[must_use]
+    pub fn settings(&self) -> &WebViewSettings {
+        &self.settings
+    }
 
-        // SAFETY: self.web_view is valid, c_script is a valid C string with known length.
+    /// Change the WebKit options of the live view.
+    ///
+    /// The options (JavaScript, user agent, WebGL, fonts, ...) are re-applied
+    /// immediately. Creation-time settings such as the size, assets, script
+    /// world and IPC configuration cannot be changed.
+    ///
+    /// ```rust,ignore
+    /// webview.update_settings(|s| s.javascript_clipboard = true);
+    /// ```
+    #[allow(unsafe_code)]
+    pub fn update_settings<F: FnOnce(&mut RuntimeSettings)>(&mut self, update: F) {
+        let mut runtime = self.settings.runtime_settings();
+        update(&mut runtime);
+        // SAFETY: self.web_view is valid.
         unsafe {
-            wpe_sys::webkit_web_view_evaluate_javascript(
-                self.web_view,
-                c_script.as_ptr(),
-                script.len() as i64,
-                ptr::null(),
-                ptr::null(),
-                ptr::null_mut(),
-                None,
-                ptr::null_mut(),
-            );
+            webkit_settings::apply(self.web_view, &runtime);
         }
+        self.settings.set_runtime_settings(runtime);
+    }
 
-        tracing::debug!("Evaluating script ({} bytes)", script.len());
-        Ok(())
+    /// Receive all pending messages from JavaScript.
+    ///
+    /// Returns the messages posted through `webkit.messageHandlers.wpe`
+    /// since the last call. Messages from a page that has since been
+    /// navigated away from are dropped. [`crate::IpcBridge::poll`] uses this
+    /// to feed its queue.
+    #[must_use]
+    pub fn receive_messages(&self) -> Vec<FrontendMessage> {
+        self.message_queue
+            .take()
+            .into_iter()
+            .filter(|msg| !self.subscriptions.handle(msg))
+            .collect()
+    }
+
+    /// Send raw bytes to the frontend, delivered to `wpe.onBytes` as an `ArrayBuffer`.
+    ///
+    /// Data the page hasn't fetched when it navigates away is dropped.
+    pub fn send_bytes(&self, message_type: &str, data: impl Into<Vec<u8>>) -> Result<()> {
+        let script = self.binary.offer_bytes(message_type, data.into());
+        self.evaluate_script(&script)
+    }
+
+    /// Stream bytes from `reader` to the frontend, delivered to `wpe.onStream`
+    /// as a `ReadableStream`.
+    ///
+    /// The reader runs on a background thread once the page requests the data.
+    pub fn send_stream(
+        &self,
+        message_type: &str,
+        reader: impl Read + Send + 'static,
+        length: Option<u64>,
+    ) -> Result<()> {
+        let script = self.binary.offer_stream(message_type, reader, length);
+        self.evaluate_script(&script)
+    }
+
+    /// A handle for publishing to pages subscribed to `topic` with `wpe.subscribe`.
+    #[must_use]
+    pub fn subscription(&self, topic: impl Into<String>) -> Subscription {
+        Subscription::new(&self.subscriptions, topic)
+    }
+
+    /// Receive binary messages sent with `wpe.sendBytes` and `wpe.sendStream`.
+    ///
+    /// Always empty with an isolated script world; see [`crate::binary`].
+    #[must_use]
+    pub fn receive_bytes(&self) -> Vec<BinaryMessage> {
+        self.binary.receive()
+    }
+
+    /// Decide what happens to navigations and new-window requests.
+    ///
+    /// Replaces any previously set policy. Without a policy WebKit's default
+    /// handling applies.
+    pub fn set_navigation_policy(&mut self, policy: impl NavigationPolicy) {
+        *self.navigation_policy.borrow_mut() = Some(Box::new(policy));
+    }
+
+    /// Remove the navigation policy, restoring WebKit's default handling.
+    pub fn clear_navigation_policy(&mut self) {
+        *self.navigation_policy.borrow_mut() = None;
+    }
+
+    /// Filter the documents this page navigates to.
+    ///
+    /// Subresources are not filtered; block them with
+    /// [`add_content_filter`](Self::add_content_filter). See [`crate::filter`].
+    pub fn set_document_filter(&mut self, filter: impl DocumentFilter) {
+        self.document_filter.set(Arc::new(filter));
+    }
+
+    /// Remove the document filter.
+    pub fn clear_document_filter(&mut self) {
+        self.document_filter.clear();
+    }
+
+    /// Apply a compiled content rule list to this page.
+    ///
+    /// Takes effect from the next load.
+    #[allow(unsafe_code)]
+    pub fn add_content_filter(&mut self, filter: &ContentFilter) {
+        // SAFETY: self.web_view is valid (checked in new()).
+        unsafe {
+            let user_content_manager = wpe_sys::webkit_web_view_get_user_content_manager(self.web_view);
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_add_filter(user_content_manager, filter.raw());
+            }
+        }
+    }
+
+    /// Stop applying a content rule list.
+    #[allow(unsafe_code)]
+    pub fn remove_content_filter(&mut self, filter: &ContentFilter) {
+        // SAFETY: self.web_view is valid (checked in new()).
+        unsafe {
+            let user_content_manager = wpe_sys::webkit_web_view_get_user_content_manager(self.web_view);
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_remove_filter(
+                    user_content_manager,
+                    filter.raw(),
+                );
+            }
+        }
+    }
+
+    /// Stop applying all content rule lists.
+    #[allow(unsafe_code)]
+    pub fn remove_all_content_filters(&mut self) {
+        // SAFETY: self.web_view is valid (checked in new()).
+        unsafe {
+            let user_content_manager = wpe_sys::webkit_web_view_get_user_content_manager(self.web_view);
+            if !user_content_manager.is_null() {
+                wpe_sys::webkit_user_content_manager_remove_all_filters(user_content_manager);
+            }
+        }
+    }
+
+    /// Inject a script into documents loaded from now on.
+    pub fn add_user_script(&mut self, script: &UserScript) -> Result<()> {
+        self.user_content.add_script(script)
+    }
+
+    /// Apply a style sheet to documents loaded from now on.
+    pub fn add_user_style_sheet(&mut self, style_sheet: &UserStyleSheet) -> Result<()> {
+        self.user_content.add_style_sheet(style_sheet)
+    }
+
+    /// Remove all scripts added with [`WebView::add_user_script`].
+    ///
+    /// The IPC bridge stays installed.
+    pub fn clear_user_scripts(&mut self) {
+        self.user_content.clear_scripts();
+    }
+
+    /// Remove all style sheets added with [`WebView::add_user_style_sheet`].
+    pub fn clear_user_style_sheets(&mut self) {
+        self.user_content.clear_style_sheets();
+    }
+
+    /// Handle `window.open()` and `target="_blank"` requests.
+    ///
+    /// The handler returns the window to open, created with
+    /// [`NewWindowRequest::create_native_window`] or
+    /// [`NewWindowRequest::create_webview`], or `None` to deny the request.
+    /// Without a handler every request is denied.
+    pub fn on_create_window<F>(&mut self, handler: F)
+    where
+        F: Fn(&NewWindowRequest) -> Option<NewWindow> + 'static,
+    {
+        self.popups.set_handler(Box::new(handler));
+    }
+
+    /// Take the windows opened by this page since the last call.
+    ///
+    /// Windows not taken are kept alive until this one is dropped.
+    #[must_use]
+    pub fn take_new_windows(&mut self) -> Vec<NewWindow> {
+        self.popups.take_windows()
+    }
+
+    /// Choose where downloads are saved.
+    ///
+    /// The handler receives the download and the filename suggested by the
+    /// server. Without a handler, WebKit saves to the user's download
+    /// directory.
+    pub fn on_download<F>(&mut self, handler: F)
+    where
+        F: Fn(&Download, &str) -> DownloadDestination + 'static,
+    {
+        self.downloads.set_handler(Box::new(handler));
+    }
+
+    /// Start downloading `url` without navigating.
+    ///
+    /// # Errors
+    /// Returns an error if the URL is invalid or WebKit refuses the download.
+    #[allow(unsafe_code)]
+    pub fn download_uri(&mut self, url: &str) -> Result<Download> {
+        // SAFETY: self.web_view is valid and connected to self.downloads in new().
+        unsafe { download::download_uri(self.web_view, &self.downloads, url) }
+    }
+
+    /// Receive all pending download events.
+    #[must_use]
+    pub fn receive_download_events(&mut self) -> Vec<DownloadEvent> {
+        self.downloads.take_events()
+    }
+
+    /// Receive all pending navigation events.
+    ///
+    /// Returns the load, title, URL and progress changes queued since the
+    /// last call, like [`crate::NativeWindow::receive_events`].
+    #[must_use]
+    pub fn receive_events(&self) -> Vec<NavigationEvent> {
+        native::drain_events(&self.event_queue)
+    }
+
+    /// Block until the current page load finishes, processing events meanwhile.
+    ///
+    /// Returns immediately if nothing is loading. Failed loads count as
+    /// finished; check [`WebView::receive_events`] or [`WebView::url`] to
+    /// tell them apart.
+    ///
+    /// # Errors
+    /// Returns [`Error::LoadTimeout`] if the load does not finish within `timeout`.
+    pub fn wait_for_load(&mut self, timeout: Duration) -> Result<()> {
+        if self.spin_until(Instant::now() + timeout, Self::is_load_finished) {
+            Ok(())
+        } else {
+            Err(Error::LoadTimeout)
+        }
+    }
+
+    /// Wait for the current page load to finish without blocking.
+    ///
+    /// The returned future resolves to [`Error::LoadTimeout`] if the load
+    /// does not finish within `timeout`. It does not iterate the GLib main
+    /// context itself, so keep calling [`WebView::spin`] while awaiting it.
+    pub fn wait_for_load_async(&self, timeout: Duration) -> LoadFuture {
+        LoadFuture::new(Arc::clone(&self.load_tracker), timeout)
+    }
+
+    /// Whether the last requested load has finished.
+    fn is_load_finished(&self) -> bool {
+        !self.load_tracker.is_pending() && !self.is_loading()
+    }
+
+    /// The underlying `WebKitWebView`.
+    pub(crate) fn raw_web_view(&self) -> *mut wpe_sys::WebKitWebView {
+        self.web_view
+    }
+
+    /// Number of navigations committed in this view.
+    pub(crate) fn navigation_count(&self) -> u64 {
+        self.navigation_count.load(Ordering::Acquire)
     }
 
     /// Get the current URL.
@@ -405,6 +1132,9 @@ impl WebView {
     /// Go back in history.
     #[allow(unsafe_code)]
     pub fn go_back(&mut self) {
+        if self.can_go_back() {
+            self.load_tracker.start();
+        }
         // SAFETY: self.web_view is valid.
         unsafe {
             wpe_sys::webkit_web_view_go_back(self.web_view);
@@ -414,6 +1144,9 @@ impl WebView {
     /// Go forward in history.
     #[allow(unsafe_code)]
     pub fn go_forward(&mut self) {
+        if self.can_go_forward() {
+            self.load_tracker.start();
+        }
         // SAFETY: self.web_view is valid.
         unsafe {
             wpe_sys::webkit_web_view_go_forward(self.web_view);
@@ -423,6 +1156,9 @@ impl WebView {
     /// Reload the current page.
     #[allow(unsafe_code)]
     pub fn reload(&mut self) {
+        if self.url().is_some() {
+            self.load_tracker.start();
+        }
         // SAFETY: self.web_view is valid.
         unsafe {
             wpe_sys::webkit_web_view_reload(self.web_view);
@@ -439,18 +1175,15 @@ impl WebView {
     }
 
     /// Resize the web view.
+    ///
+    /// The size is in logical pixels; the frame buffer is resized to the
+    /// matching physical size for the current scale factor.
     #[allow(unsafe_code)]
     pub fn resize(&mut self, width: u32, height: u32) {
         self.width = width;
         self.height = height;
 
-        // Resize the shared frame buffer
-        if !self.render_ctx.is_null() {
-            // SAFETY: render_ctx was created with Box::into_raw and is valid until drop.
-            unsafe {
-                (*self.render_ctx).frame_buffer.resize(width, height);
-            }
-        }
+        self.resize_frame_buffer();
 
         // SAFETY: self.view is valid.
         unsafe {
@@ -460,6 +1193,51 @@ impl WebView {
         tracing::debug!("Resized to {}x{}", width, height);
     }
 
+    /// Get the current size in logical pixels.
+    #[must_use]
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Set the device scale factor.
+    ///
+    /// Content is rendered at `scale_factor` physical pixels per logical
+    /// pixel. Non-positive values are ignored.
+    #[allow(unsafe_code)]
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        if scale_factor <= 0.0 || (scale_factor - self.scale_factor).abs() < f64::EPSILON {
+            return;
+        }
+
+        self.scale_factor = scale_factor;
+        self.resize_frame_buffer();
+
+        // SAFETY: self.view is valid.
+        unsafe {
+            set_view_scale(self.view, scale_factor);
+        }
+    }
+
+    /// Get the device scale factor.
+    #[must_use]
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Resize the shared frame buffer to the current physical size.
+    #[allow(unsafe_code)]
+    fn resize_frame_buffer(&mut self) {
+        if !self.render_ctx.is_null() {
+            // SAFETY: render_ctx was created with Box::into_raw and is valid until drop.
+            unsafe {
+                (*self.render_ctx).frame_buffer.resize(
+                    to_physical(self.width, self.scale_factor),
+                    to_physical(self.height, self.scale_factor),
+                );
+            }
+        }
+    }
+
     /// Process pending events. Call this in your event loop.
     #[allow(unsafe_code)]
     pub fn spin(&mut self) {
@@ -476,6 +1254,249 @@ impl WebView {
         }
     }
 
+    /// Spin the GLib main context until `done` returns true or `deadline` passes.
+    ///
+    /// Returns whether `done` was satisfied.
+    fn spin_until(&mut self, deadline: Instant, mut done: impl FnMut(&Self) -> bool) -> bool {
+        loop {
+            self.spin();
+            if done(self) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Number of frames rendered into the frame buffer.
+    #[allow(unsafe_code)]
+    fn frame_count(&self) -> u64 {
+        // SAFETY: render_ctx was created with Box::into_raw and is valid until drop.
+        unsafe { (*self.render_ctx).frames }
+    }
+
+    /// Physical size of the last rendered frame.
+    #[allow(unsafe_code)]
+    fn frame_size(&self) -> (u32, u32) {
+        // SAFETY: render_ctx was created with Box::into_raw and is valid until drop.
+        unsafe { (*self.render_ctx).frame_size }
+    }
+
+    /// Copy `region` out of the current frame.
+    #[allow(unsafe_code)]
+    fn read_frame(&self, region: SnapshotRegion) -> Result<Snapshot> {
+        // SAFETY: render_ctx was created with Box::into_raw and is valid until drop.
+        let frame_buffer = unsafe { &(*self.render_ctx).frame_buffer };
+        frame_buffer.with_pixels(|pixels, width, height| {
+            snapshot::from_argb(pixels, width, height, region, self.scale_factor)
+        })
+    }
+
+    /// Evaluate `script`, spinning the main context until it completes.
+    fn evaluate_blocking<T: DeserializeOwned + 'static>(
+        &mut self,
+        script: &str,
+        deadline: Instant,
+    ) -> Result<T> {
+        let slot = Rc::new(RefCell::new(None));
+        let sink = Rc::clone(&slot);
+        self.evaluate_script_with_callback(script, move |result: Result<T>| {
+            *sink.borrow_mut() = Some(result);
+        })?;
+
+        self.spin_until(deadline, |_| slot.borrow().is_some());
+        let result = slot.borrow_mut().take();
+        result.unwrap_or_else(|| {
+            Err(Error::JavaScriptError(
+                "timed out waiting for script result".to_string(),
+            ))
+        })
+    }
+
+    /// Wait for the current load to finish.
+    fn wait_for_snapshot_load(&mut self, timeout: Duration) -> Result<()> {
+        self.wait_for_load(timeout).map_err(|_| {
+            Error::SnapshotFailed("timed out waiting for the page to load".to_string())
+        })
+    }
+
+    /// Capture the rendered page.
+    ///
+    /// Waits for the current load to finish and for the next frame to be
+    /// rendered, then copies `region` out of the frame buffer. See
+    /// [`Snapshot`] for the pixel format.
+    ///
+    /// # Errors
+    /// Returns [`Error::SnapshotFailed`] if the page does not finish loading
+    /// in time, nothing has been rendered yet, or `region` lies outside the view.
+    pub fn snapshot(&mut self, region: SnapshotRegion) -> Result<Snapshot> {
+        self.snapshot_with_timeout(region, SNAPSHOT_LOAD_TIMEOUT)
+    }
+
+    /// Like [`WebView::snapshot`], but with a custom load timeout.
+    ///
+    /// # Errors
+    /// See [`WebView::snapshot`].
+    pub fn snapshot_with_timeout(
+        &mut self,
+        region: SnapshotRegion,
+        timeout: Duration,
+    ) -> Result<Snapshot> {
+        self.wait_for_snapshot_load(timeout)?;
+
+        let loaded_frame = self.frame_count();
+        let fresh = self.spin_until(Instant::now() + SNAPSHOT_FRAME_TIMEOUT, |view| {
+            view.frame_count() > loaded_frame
+        });
+        if !fresh && loaded_frame == 0 {
+            return Err(Error::SnapshotFailed("no frame has been rendered".to_string()));
+        }
+
+        self.read_frame(region)
+    }
+
+    /// Capture the whole scrollable page, not just the viewport.
+    ///
+    /// The view is temporarily resized to the document height and restored
+    /// afterwards. Documents taller than 16384 physical pixels are scrolled
+    /// through and captured in tiles, so fixed-position elements repeat once
+    /// per tile. Pages whose height follows the viewport (e.g. `height: 100vh`
+    /// layouts) are captured at the height they report once resized.
+    ///
+    /// # Errors
+    /// Returns [`Error::SnapshotFailed`] if the page does not load or render
+    /// in time or is taller than 16 tiles, or a JavaScript error if the
+    /// document height cannot be read.
+    pub fn snapshot_full_page(&mut self) -> Result<Snapshot> {
+        self.capture_full_page(self.width)
+    }
+
+    /// Capture the whole page laid out at `width` logical pixels.
+    fn capture_full_page(&mut self, width: u32) -> Result<Snapshot> {
+        self.wait_for_snapshot_load(SNAPSHOT_LOAD_TIMEOUT)?;
+
+        let (original_width, original_height) = (self.width, self.height);
+        if width != original_width {
+            self.resize(width, original_height);
+        }
+
+        let result = self.capture_at_document_height(width);
+        self.resize(original_width, original_height);
+        result
+    }
+
+    fn capture_at_document_height(&mut self, width: u32) -> Result<Snapshot> {
+        let deadline = Instant::now() + FULL_PAGE_FRAME_TIMEOUT;
+        let document_height: u32 = self.evaluate_blocking(DOCUMENT_HEIGHT_SCRIPT, deadline)?;
+        let max_height = to_logical(FULL_PAGE_MAX_HEIGHT, self.scale_factor);
+        let height = document_height.clamp(1, max_height);
+        if document_height.div_ceil(max_height) > FULL_PAGE_MAX_TILES {
+            return Err(Error::SnapshotFailed(format!(
+                "document is {}px tall, full-page captures stop at {}px",
+                document_height,
+                max_height * FULL_PAGE_MAX_TILES
+            )));
+        }
+
+        self.resize(width, height);
+        let expected = (
+            to_physical(width, self.scale_factor),
+            to_physical(height, self.scale_factor),
+        );
+        if !self.spin_until(deadline, |view| view.frame_size() == expected) {
+            return Err(Error::SnapshotFailed(
+                "timed out waiting for a full-page frame".to_string(),
+            ));
+        }
+
+        if document_height <= max_height {
+            return self.read_frame(SnapshotRegion::Visible);
+        }
+
+        tracing::debug!(
+            "Document is {}px tall, capturing it in {}px tiles",
+            document_height,
+            max_height
+        );
+        let original_scroll: u32 = self.evaluate_blocking(SCROLL_Y_SCRIPT, deadline)?;
+        let result = self.capture_tiles(document_height, max_height, original_scroll);
+        let restored = self.evaluate_script(&scroll_to_script(original_scroll));
+        let snapshot = result?;
+        restored?;
+        Ok(snapshot)
+    }
+
+    /// Capture a document taller than the view by scrolling through it one
+    /// view height at a time and stitching the frames together.
+    ///
+    /// `scroll_y` is the offset shown in the current frame.
+    fn capture_tiles(
+        &mut self,
+        document_height: u32,
+        tile_height: u32,
+        mut scroll_y: u32,
+    ) -> Result<Snapshot> {
+        let mut offset = 0;
+        let mut stitched = Snapshot::from_rgba(self.frame_size().0, 0, Vec::new())?;
+        while offset < document_height {
+            let deadline = Instant::now() + FULL_PAGE_FRAME_TIMEOUT;
+            let frames = self.frame_count();
+            let reached: u32 = self.evaluate_blocking(&scroll_to_script(offset), deadline)?;
+            if reached != scroll_y && !self.spin_until(deadline, |view| view.frame_count() > frames)
+            {
+                return Err(Error::SnapshotFailed(
+                    "timed out waiting for a scrolled frame".to_string(),
+                ));
+            }
+            scroll_y = reached;
+
+            // The last tile usually scrolls less than a full view height
+            let end = (offset + tile_height).min(document_height);
+            if scroll_y > offset || end - scroll_y > tile_height {
+                return Err(Error::SnapshotFailed(format!(
+                    "page did not scroll to {offset}px"
+                )));
+            }
+
+            let frame = self.read_frame(SnapshotRegion::Visible)?;
+            let rows = to_physical(offset - scroll_y, self.scale_factor)
+                ..to_physical(end - scroll_y, self.scale_factor).min(frame.height());
+            stitched.append_rows(&frame, rows)?;
+            offset = end;
+        }
+
+        Ok(stitched)
+    }
+
+    /// Capture the whole page into a PDF document of raster images.
+    ///
+    /// The page is laid out at the printable width of `options`, captured as a
+    /// screenshot and paginated. This is not printing: text is not
+    /// selectable or searchable, and `@media print` rules do not apply; see
+    /// [`crate::pdf`].
+    ///
+    /// # Errors
+    /// Returns the same errors as [`WebView::snapshot_full_page`].
+    #[cfg(feature = "pdf")]
+    pub fn capture_to_pdf(&mut self, options: &crate::pdf::PdfOptions) -> Result<Vec<u8>> {
+        if !options.print_backgrounds {
+            self.evaluate_script(crate::pdf::HIDE_BACKGROUNDS_SCRIPT)?;
+        }
+
+        let capture = self.capture_full_page(options.content_width_px());
+
+        // The capture is still good if the page lost its backgrounds
+        if !options.print_backgrounds {
+            if let Err(e) = self.evaluate_script(crate::pdf::RESTORE_BACKGROUNDS_SCRIPT) {
+                tracing::warn!("Failed to restore page backgrounds after capture: {}", e);
+            }
+        }
+
+        Ok(crate::pdf::encode(&capture?, options, self.scale_factor))
+    }
+
     /// Render the web view. Call this when you need to redraw.
     #[allow(unsafe_code)]
     pub fn render(&mut self) {
@@ -699,6 +1720,11 @@ impl Drop for WebView {
         unsafe {
             // Unreference the WebKitWebView
             if !self.web_view.is_null() {
+                ipc::unroute_fetch_messages(self.web_view);
+                self.subscriptions.detach();
+                binary::unroute(self.web_view);
+                assets::unmount(self.web_view);
+                download::disconnect(self.web_view, self.download_signal_id);
                 wpe_sys::g_object_unref(self.web_view as *mut _);
             }
 
@@ -711,6 +1737,32 @@ impl Drop for WebView {
             if !self.render_ctx.is_null() {
                 drop(Box::from_raw(self.render_ctx));
             }
+
+            // Reclaim the message queue leaked for the script message handler
+            if !self.message_handler_ptr.is_null() {
+                drop(Box::from_raw(self.message_handler_ptr));
+            }
+            if !self.navigation_count_ptr.is_null() {
+                drop(Arc::from_raw(self.navigation_count_ptr));
+            }
+            if !self.event_queue_ptr.is_null() {
+                drop(Arc::from_raw(self.event_queue_ptr));
+            }
+            if !self.load_tracker_ptr.is_null() {
+                drop(Arc::from_raw(self.load_tracker_ptr));
+            }
+            if !self.document_filter_ptr.is_null() {
+                drop(Rc::from_raw(self.document_filter_ptr));
+            }
+            if !self.navigation_policy_ptr.is_null() {
+                drop(Rc::from_raw(self.navigation_policy_ptr));
+            }
+            if !self.popups_ptr.is_null() {
+                drop(Rc::from_raw(self.popups_ptr));
+            }
+            if !self.downloads_ptr.is_null() {
+                drop(Rc::from_raw(self.downloads_ptr));
+            }
         }
     }
 }
@@ -783,6 +1835,82 @@ mod tests {
         assert_eq!(settings1.developer_tools, settings2.developer_tools);
     }
 
+    #[test]
+    fn test_webview_settings_with_assets() {
+        let settings = WebViewSettings::new()
+            .with_assets(StaticAssets::from_directory("dist"));
+        assert_eq!(settings.initial_url(), Some("app://localhost/"));
+
+        let settings = settings.with_url("https://example.com");
+        assert_eq!(settings.initial_url(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn test_webview_settings_viewport() {
+        let settings = WebViewSettings::new()
+            .with_size(800, 600)
+            .with_scale_factor(2.0)
+            .with_title("My App");
+
+        assert_eq!((settings.width, settings.height), (800, 600));
+        assert_eq!(settings.scale_factor, Some(2.0));
+        assert_eq!(settings.title, "My App");
+
+        let defaults = WebViewSettings::new().with_scale_factor(0.0);
+        assert_eq!((defaults.width, defaults.height), (1280, 720));
+        assert!(defaults.scale_factor.is_none());
+    }
+
+    #[test]
+    fn test_physical_logical_conversion() {
+        assert_eq!(to_physical(1280, 2.0), 2560);
+        assert_eq!(to_physical(101, 1.5), 152);
+        assert_eq!(to_logical(2560, 2.0), 1280);
+    }
+
+    #[test]
+    fn test_webview_settings_webkit_options() {
+        let settings = WebViewSettings::new()
+            .with_javascript_enabled(false)
+            .with_user_agent("wpe-test")
+            .with_webgl(false)
+            .with_autoplay_policy(AutoplayPolicy::RequireUserGesture)
+            .with_default_font_family("Inter")
+            .with_default_font_size(18)
+            .with_smooth_scrolling(false)
+            .with_javascript_clipboard(true)
+            .with_hardware_acceleration(HardwareAccelerationPolicy::Never);
+
+        assert!(!settings.javascript_enabled);
+        assert_eq!(settings.user_agent.as_deref(), Some("wpe-test"));
+        assert!(!settings.webgl_enabled);
+        assert_eq!(settings.autoplay_policy, AutoplayPolicy::RequireUserGesture);
+        assert_eq!(settings.default_font_family.as_deref(), Some("Inter"));
+        assert_eq!(settings.default_font_size, Some(18));
+        assert!(settings.monospace_font_family.is_none());
+        assert!(!settings.smooth_scrolling);
+        assert!(!settings.local_file_access);
+        assert!(settings.javascript_clipboard);
+        assert_eq!(settings.hardware_acceleration, HardwareAccelerationPolicy::Never);
+    }
+
+    #[test]
+    fn test_webview_settings_runtime_settings() {
+        let mut settings = WebViewSettings::new()
+            .with_script_world("app")
+            .with_javascript_clipboard(true);
+
+        let mut runtime = settings.runtime_settings();
+        assert!(runtime.javascript_clipboard);
+        runtime.javascript_clipboard = false;
+        runtime.user_agent = Some("wpe-test".to_string());
+        settings.set_runtime_settings(runtime);
+
+        assert!(!settings.javascript_clipboard);
+        assert_eq!(settings.user_agent.as_deref(), Some("wpe-test"));
+        assert_eq!(settings.script_world.as_deref(), Some("app"));
+    }
+
     #[test]
     fn test_webview_settings_url_from_string() {
         let url = String::from("https://rust-lang.org");
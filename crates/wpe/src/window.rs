@@ -4,7 +4,7 @@
 //! allowing you to embed WPE WebViews in winit windows.
 
 #[cfg(feature = "winit")]
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "winit")]
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
@@ -17,8 +17,10 @@ use winit::{
     window::{Window, WindowAttributes, WindowId},
 };
 
-use crate::{Error, IpcBridge, Result, SoftwareRenderer, WebView, WebViewSettings};
+use crate::ipc::{BackendMessage, FrontendMessage};
+use crate::{Error, IpcBridge, IpcRouter, Result, SoftwareRenderer, WebView, WebViewSettings};
 use crate::renderer::SharedFrameBuffer;
+use crate::webview::to_logical;
 
 /// A custom event type for the winit event loop.
 #[derive(Debug, Clone)]
@@ -54,7 +56,7 @@ impl WpeWindow {
             window: None,
             webview: None,
             renderer: None,
-            frame_buffer: SharedFrameBuffer::new(1280, 720),
+            frame_buffer: SharedFrameBuffer::new(settings.width, settings.height),
             ipc: IpcBridge::new(),
             settings,
             ready: false,
@@ -99,6 +101,18 @@ impl WpeWindow {
         &mut self.ipc
     }
 
+    /// Send a message to the frontend.
+    ///
+    /// # Errors
+    /// Returns an error if the webview is not ready or the message could not be sent.
+    pub fn send_message(&self, message: &BackendMessage) -> Result<()> {
+        let webview = self
+            .webview
+            .as_ref()
+            .ok_or_else(|| Error::IpcError("WebView is not ready".to_string()))?;
+        self.ipc.send(webview, message)
+    }
+
     /// Check if the webview is ready.
     #[must_use]
     pub fn is_ready(&self) -> bool {
@@ -110,8 +124,11 @@ impl WpeWindow {
         tracing::debug!("WpeWindow::initialize starting");
 
         let attrs = WindowAttributes::default()
-            .with_title("WPE WebView")
-            .with_inner_size(winit::dpi::LogicalSize::new(1280, 720));
+            .with_title(self.settings.title.as_str())
+            .with_inner_size(winit::dpi::LogicalSize::new(
+                self.settings.width,
+                self.settings.height,
+            ));
 
         let window = Arc::new(
             event_loop
@@ -129,18 +146,20 @@ impl WpeWindow {
         let renderer = SoftwareRenderer::new(window.clone(), self.frame_buffer.clone())?;
         tracing::debug!("Renderer created");
 
-        // Create the webview with the same shared frame buffer
-        let mut webview = WebView::new(self.settings.clone(), self.frame_buffer.clone())?;
+        // Create the webview with the same shared frame buffer, rendering at
+        // the window's scale unless the settings force one
+        let mut settings = self.settings.clone();
+        settings.scale_factor.get_or_insert(window.scale_factor());
+        let mut webview = WebView::new(settings, self.frame_buffer.clone())?;
         tracing::debug!("WebView created");
 
         // Load initial content
-        if let Some(ref url) = self.settings.url {
+        if let Some(url) = self.settings.initial_url() {
             tracing::debug!("Loading URL: {}", url);
             webview.load_url(url)?;
         } else if let Some(ref html) = self.settings.html {
             tracing::debug!("Loading HTML content");
-            let html_with_bridge = IpcBridge::inject_bridge(html);
-            webview.load_html(&html_with_bridge, None)?;
+            webview.load_html(html, None)?;
         }
         tracing::debug!("Content loaded");
 
@@ -164,12 +183,21 @@ impl WpeWindow {
                 self.frame_buffer.resize(size.width, size.height);
 
                 if let Some(ref mut webview) = self.webview {
-                    webview.resize(size.width, size.height);
+                    let scale = webview.scale_factor();
+                    webview.resize(to_logical(size.width, scale), to_logical(size.height, scale));
                 }
                 if let Some(ref mut renderer) = self.renderer {
                     renderer.resize(size.width, size.height);
                 }
             }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. }
+                if self.settings.scale_factor.is_none() =>
+            {
+                // A Resized event with the new physical size follows
+                if let Some(ref mut webview) = self.webview {
+                    webview.set_scale_factor(scale_factor);
+                }
+            }
             WindowEvent::RedrawRequested => {
                 tracing::trace!("RedrawRequested");
 
@@ -206,9 +234,11 @@ impl WpeWindow {
                 }
             }
             WindowEvent::CursorMoved { position, .. } => {
-                self.cursor_pos = (position.x, position.y);
+                // WPE expects logical coordinates
+                let scale = self.webview.as_ref().map_or(1.0, WebView::scale_factor);
+                self.cursor_pos = (position.x / scale, position.y / scale);
                 if let Some(ref mut webview) = self.webview {
-                    webview.mouse_move(position.x, position.y, self.modifiers);
+                    webview.mouse_move(self.cursor_pos.0, self.cursor_pos.1, self.modifiers);
                 }
             }
             WindowEvent::CursorEntered { .. } => {
@@ -249,7 +279,8 @@ impl WpeWindow {
                         (x as f64 * 40.0, y as f64 * 40.0, false)
                     }
                     MouseScrollDelta::PixelDelta(pos) => {
-                        (pos.x, pos.y, true)
+                        let scale = self.webview.as_ref().map_or(1.0, WebView::scale_factor);
+                        (pos.x / scale, pos.y / scale, true)
                     }
                 };
                 if let Some(ref mut webview) = self.webview {
@@ -304,6 +335,20 @@ impl WpeWindow {
     }
 }
 
+/// Boxed message handler, the handler type of [`WpeApp::with_router`] apps.
+#[cfg(feature = "winit")]
+pub type BoxedMessageHandler =
+    Box<dyn FnMut(&mut WpeWindow, &FrontendMessage) -> Option<serde_json::Value>>;
+
+/// How a [`WpeApp`] answers messages from the page.
+#[cfg(feature = "winit")]
+enum MessageDispatch<F> {
+    /// A closure called for each message
+    Handler(F),
+    /// A router that also drives async calls
+    Router(IpcRouter),
+}
+
 /// Builder for creating a WPE application with winit.
 #[cfg(feature = "winit")]
 pub struct WpeApp<F>
@@ -311,7 +356,7 @@ where
     F: FnMut(&mut WpeWindow, &crate::ipc::FrontendMessage) -> Option<serde_json::Value>,
 {
     settings: WebViewSettings,
-    message_handler: F,
+    dispatch: MessageDispatch<F>,
 }
 
 #[cfg(feature = "winit")]
@@ -323,7 +368,7 @@ where
     pub fn new(settings: WebViewSettings, message_handler: F) -> Self {
         Self {
             settings,
-            message_handler,
+            dispatch: MessageDispatch::Handler(message_handler),
         }
     }
 
@@ -336,9 +381,21 @@ where
             .build()
             .map_err(|_| Error::InitFailed)?;
 
+        let mut dispatch = self.dispatch;
+        if let MessageDispatch::Router(ref mut router) = dispatch {
+            // Async calls woken from other threads must wake the winit loop,
+            // which doesn't wait on the GLib main context
+            let proxy = Mutex::new(event_loop.create_proxy());
+            router.set_loop_waker(move || {
+                if let Ok(proxy) = proxy.lock() {
+                    let _ = proxy.send_event(WpeEvent::Wake);
+                }
+            });
+        }
+
         let mut app = WpeAppHandler {
             wpe_window: WpeWindow::new(self.settings),
-            message_handler: self.message_handler,
+            dispatch,
         };
 
         event_loop.run_app(&mut app).map_err(|_| Error::InitFailed)?;
@@ -347,13 +404,78 @@ where
     }
 }
 
+#[cfg(feature = "winit")]
+impl WpeApp<BoxedMessageHandler> {
+    /// Create a new WPE application that dispatches messages through a router.
+    ///
+    /// Requests are answered with the handler's result or an error response.
+    /// Async handlers are polled on the winit loop and cancelled when the
+    /// page navigates away.
+    #[must_use]
+    pub fn with_router(settings: WebViewSettings, router: IpcRouter) -> Self {
+        Self {
+            settings,
+            dispatch: MessageDispatch::Router(router),
+        }
+    }
+}
+
 #[cfg(feature = "winit")]
 struct WpeAppHandler<F>
 where
     F: FnMut(&mut WpeWindow, &crate::ipc::FrontendMessage) -> Option<serde_json::Value>,
 {
     wpe_window: WpeWindow,
-    message_handler: F,
+    dispatch: MessageDispatch<F>,
+}
+
+#[cfg(feature = "winit")]
+impl<F> WpeAppHandler<F>
+where
+    F: FnMut(&mut WpeWindow, &crate::ipc::FrontendMessage) -> Option<serde_json::Value>,
+{
+    /// Dispatch messages from the page and send the responses.
+    fn process_messages(&mut self) {
+        // Borrow ipc and webview separately to satisfy borrow checker
+        let messages: Vec<_> = if let Some(ref webview) = self.wpe_window.webview {
+            self.wpe_window.ipc.poll(webview)
+        } else {
+            vec![]
+        };
+
+        match self.dispatch {
+            MessageDispatch::Router(ref router) => {
+                if let Some(ref webview) = self.wpe_window.webview {
+                    router.sync_navigation(webview.navigation_count());
+                }
+
+                let responses: Vec<_> = messages
+                    .iter()
+                    .filter_map(|msg| router.dispatch(msg))
+                    .chain(router.poll_pending())
+                    .collect();
+
+                for response in responses {
+                    if let Err(e) = self.wpe_window.send_message(&response) {
+                        tracing::warn!("Failed to send response: {}", e);
+                    }
+                }
+            }
+            MessageDispatch::Handler(ref mut handler) => {
+                for msg in messages {
+                    if let Some(result) = handler(&mut self.wpe_window, &msg) {
+                        if let Some(request_id) = &msg.request_id {
+                            let response =
+                                crate::ipc::BackendMessage::response(request_id.clone(), result);
+                            if let Some(ref webview) = self.wpe_window.webview {
+                                let _ = self.wpe_window.ipc.send(webview, &response);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(feature = "winit")]
@@ -376,6 +498,7 @@ where
                 if let Some(ref mut webview) = self.wpe_window.webview {
                     webview.spin();
                 }
+                self.process_messages();
             }
             WpeEvent::Redraw => {
                 if let Some(ref window) = self.wpe_window.window {
@@ -392,24 +515,7 @@ where
         event: WindowEvent,
     ) {
         // Process IPC messages before handling events
-        // Borrow ipc and webview separately to satisfy borrow checker
-        let messages: Vec<_> = if let Some(ref webview) = self.wpe_window.webview {
-            self.wpe_window.ipc.poll(webview)
-        } else {
-            vec![]
-        };
-
-        for msg in messages {
-            if let Some(result) = (self.message_handler)(&mut self.wpe_window, &msg) {
-                if let Some(request_id) = &msg.request_id {
-                    let response =
-                        crate::ipc::BackendMessage::response(request_id.clone(), result);
-                    if let Some(ref webview) = self.wpe_window.webview {
-                        let _ = self.wpe_window.ipc.send(webview, &response);
-                    }
-                }
-            }
-        }
+        self.process_messages();
 
         if self.wpe_window.handle_event(event) {
             event_loop.exit();
@@ -4,7 +4,10 @@
 //! It uses headless WPE rendering and blits to an X11 window via shared memory.
 
 use std::ffi::CString;
+use std::io::Read;
 use std::ptr;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use x11rb::connection::Connection;
 use x11rb::protocol::shm::{self, ConnectionExt as ShmConnectionExt};
@@ -14,7 +17,16 @@ use x11rb::protocol::xproto::{
 use x11rb::wrapper::ConnectionExt as WrapperConnectionExt;
 use x11rb::xcb_ffi::XCBConnection;
 
-use crate::ipc::{BackendMessage, FrontendMessage, IpcBridge};
+use crate::assets;
+use crate::binary::{self, BinaryChannel, BinaryMessage};
+use crate::ipc::{self, BackendMessage, FrontendMessage, IpcBridge, MessageHandlerState, MessageQueue, MessageQueueInner};
+use crate::native::{self, NavigationCounter};
+use crate::router::IpcRouter;
+use crate::script;
+use crate::settings::RuntimeSettings;
+use crate::subscription::{Subscription, SubscriptionHub};
+use crate::user_content;
+use crate::webview::{set_view_scale, to_logical, to_physical};
 use crate::webview::WebViewSettings;
 use crate::{Error, Result};
 
@@ -85,6 +97,8 @@ pub struct X11Window {
     shm_seg: Option<ShmSegment>,
     width: u32,
     height: u32,
+    /// Device scale factor
+    scale_factor: f64,
     /// WPE display (headless)
     display: *mut wpe_sys::WPEDisplay,
     /// WPE view
@@ -98,7 +112,19 @@ pub struct X11Window {
     /// Whether the window should close
     should_close: bool,
     /// Pending messages from JavaScript
-    message_queue: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<FrontendMessage>>>,
+    message_queue: MessageQueue,
+    /// Raw pointer from `Box::into_raw` for the script message handler
+    message_handler_ptr: *mut MessageHandlerState,
+    /// Binary messages from JavaScript and data waiting to be fetched by it
+    binary: Arc<BinaryChannel>,
+    /// Count of committed navigations (for cancelling async IPC calls)
+    navigation_count: NavigationCounter,
+    /// Raw pointer from `Arc::into_raw` for the load-changed signal handler
+    navigation_count_ptr: *const std::sync::atomic::AtomicU64,
+    /// Topics the page subscribed to and values waiting to be published
+    subscriptions: Rc<SubscriptionHub>,
+    /// Settings used to create this window
+    settings: WebViewSettings,
 }
 
 impl X11Window {
@@ -108,8 +134,10 @@ impl X11Window {
             .map_err(|e| Error::X11Error(format!("Failed to connect to X11: {}", e)))?;
 
         let screen = &conn.setup().roots[screen_num];
-        let width = 1280u32;
-        let height = 720u32;
+        // The window is sized in physical pixels; WPE works in logical ones
+        let scale_factor = settings.scale_factor.unwrap_or(1.0);
+        let width = to_physical(settings.width, scale_factor);
+        let height = to_physical(settings.height, scale_factor);
 
         // Create window
         let window = conn.generate_id().map_err(|e| Error::X11Error(e.to_string()))?;
@@ -149,7 +177,7 @@ impl X11Window {
         .map_err(|e| Error::X11Error(e.to_string()))?;
 
         // Set window title
-        let title = "WPE WebView";
+        let title = settings.title.as_str();
         conn.change_property8(
             xproto::PropMode::REPLACE,
             window,
@@ -173,12 +201,28 @@ impl X11Window {
         let shm_size = (width * height * 4) as usize;
         let shm_seg = ShmSegment::new(&conn, shm_size).ok();
 
-        // Create message queue for IPC
-        let message_queue = std::sync::Arc::new(std::sync::Mutex::new(std::collections::VecDeque::new()));
+        // Create message queue for IPC, dropping messages from previous pages
+        let navigation_count = NavigationCounter::default();
+        let message_queue = MessageQueueInner::new(&navigation_count);
+        let binary = Arc::new(BinaryChannel::new(
+            settings.script_world.as_deref(),
+            &settings.ipc.global_name,
+        ));
 
         // Initialize WPE in headless mode
+        script::world_name(settings.script_world.as_deref())?;
+        if settings.assets.is_some() {
+            assets::register_scheme()?;
+        }
+        if CString::new(settings.ipc.handler_name.as_str()).is_err() {
+            return Err(Error::IpcError(format!(
+                "Invalid script message handler name: {:?}",
+                settings.ipc.handler_name
+            )));
+        }
+
         // SAFETY: WPE API calls with null checks.
-        let (display, view, web_view) = unsafe {
+        let (display, view, web_view, message_handler_ptr, navigation_count_ptr) = unsafe {
             let display = wpe_sys::wpe_display_headless_new();
             if display.is_null() {
                 return Err(Error::X11Error("Failed to create headless WPE display".to_string()));
@@ -198,8 +242,11 @@ impl X11Window {
                 return Err(Error::X11Error("Failed to create WPE view".to_string()));
             }
 
-            // Set initial size
-            wpe_sys::wpe_view_resized(view, width as i32, height as i32);
+            // Set initial size and scale
+            wpe_sys::wpe_view_resized(view, settings.width as i32, settings.height as i32);
+            if (scale_factor - 1.0).abs() > f64::EPSILON {
+                set_view_scale(view, scale_factor);
+            }
             wpe_sys::wpe_view_set_visible(view, 1);
 
             // Create WebKit web view (pass null for default backend)
@@ -210,36 +257,49 @@ impl X11Window {
                 return Err(Error::X11Error("Failed to create WebKit web view".to_string()));
             }
 
+            crate::settings::apply(web_view, &settings.runtime_settings());
+
             // Get user content manager and set up IPC
             let user_content_manager = wpe_sys::webkit_web_view_get_user_content_manager(web_view);
-            if !user_content_manager.is_null() {
-                let queue_ptr = std::sync::Arc::into_raw(message_queue.clone());
-
-                let signal_name = CString::new("script-message-received::wpe")
-                    .expect("static string has no NUL bytes");
-                wpe_sys::g_signal_connect_data(
-                    user_content_manager as *mut _,
-                    signal_name.as_ptr(),
-                    Some(std::mem::transmute::<
-                        *const (),
-                        unsafe extern "C" fn(),
-                    >(on_script_message as *const ())),
-                    queue_ptr as *mut _,
-                    None,
-                    0,
+            let message_handler_ptr = if user_content_manager.is_null() {
+                ptr::null_mut()
+            } else {
+                let handler_ptr = ipc::connect_message_handler(
+                    web_view,
+                    user_content_manager,
+                    settings.script_world.as_deref(),
+                    &settings.ipc.handler_name,
+                    &message_queue,
                 );
-
-                let handler_name = CString::new("wpe").expect("static string has no NUL bytes");
-                wpe_sys::webkit_user_content_manager_register_script_message_handler(
+                user_content::install_bridge(
                     user_content_manager,
-                    handler_name.as_ptr(),
-                    ptr::null(),
+                    settings.script_world.as_deref(),
+                    &settings.ipc,
                 );
+                handler_ptr
+            };
+
+            if settings.script_world.is_none() {
+                ipc::route_fetch_messages(web_view, &message_queue);
+            }
+            binary::route(web_view, &binary);
+
+            if let Some(ref static_assets) = settings.assets {
+                assets::mount(web_view, static_assets);
             }
 
-            (display, view, web_view)
+            let navigation_count_ptr = native::connect_navigation_counter(web_view, &navigation_count);
+
+            (display, view, web_view, message_handler_ptr, navigation_count_ptr)
         };
 
+        let subscriptions = Rc::new(SubscriptionHub::new(
+            web_view,
+            settings.script_world.clone(),
+            &settings.ipc.global_name,
+            &navigation_count,
+        ));
+
         let mut window = Self {
             conn,
             screen_num,
@@ -248,6 +308,7 @@ impl X11Window {
             shm_seg,
             width,
             height,
+            scale_factor,
             display,
             view,
             web_view,
@@ -255,13 +316,19 @@ impl X11Window {
             ipc: IpcBridge::new(),
             should_close: false,
             message_queue,
+            message_handler_ptr,
+            binary,
+            navigation_count,
+            navigation_count_ptr,
+            subscriptions,
+            settings,
         };
 
         // Load initial content
-        if let Some(ref url) = settings.url {
-            window.load_url(url)?;
-        } else if let Some(ref html) = settings.html {
-            window.load_html(html, None)?;
+        if let Some(url) = window.settings.initial_url().map(str::to_string) {
+            window.load_url(&url)?;
+        } else if let Some(html) = window.settings.html.clone() {
+            window.load_html(&html, None)?;
         }
 
         Ok(window)
@@ -279,8 +346,7 @@ impl X11Window {
 
     /// Load HTML content.
     pub fn load_html(&mut self, html: &str, base_url: Option<&str>) -> Result<()> {
-        let html_with_bridge = IpcBridge::inject_bridge(html);
-        let c_html = CString::new(html_with_bridge).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        let c_html = CString::new(html).map_err(|e| Error::InvalidUrl(e.to_string()))?;
         let c_base = base_url.and_then(|u| CString::new(u).ok());
 
         // SAFETY: web_view is valid, c_html is a valid C string.
@@ -294,6 +360,24 @@ impl X11Window {
         Ok(())
     }
 
+    /// Get the settings this window was created with (including later updates).
+    pub fn settings(&self) -> &WebViewSettings {
+        &self.settings
+    }
+
+    /// Change the WebKit options of the live view.
+    ///
+    /// See [`crate::WebView::update_settings`].
+    pub fn update_settings<F: FnOnce(&mut RuntimeSettings)>(&mut self, update: F) {
+        let mut runtime = self.settings.runtime_settings();
+        update(&mut runtime);
+        // SAFETY: web_view is valid.
+        unsafe {
+            crate::settings::apply(self.web_view, &runtime);
+        }
+        self.settings.set_runtime_settings(runtime);
+    }
+
     /// Process events. Returns false if the window should close.
     pub fn process_events(&mut self) -> Result<bool> {
         // Process X11 events
@@ -331,13 +415,16 @@ impl X11Window {
                     self.handle_key(e.detail as u32, false);
                 }
                 x11rb::protocol::Event::ButtonPress(e) => {
-                    self.handle_button(e.detail as u32, true, e.event_x as f64, e.event_y as f64);
+                    let (x, y) = self.to_view_coords(e.event_x, e.event_y);
+                    self.handle_button(e.detail as u32, true, x, y);
                 }
                 x11rb::protocol::Event::ButtonRelease(e) => {
-                    self.handle_button(e.detail as u32, false, e.event_x as f64, e.event_y as f64);
+                    let (x, y) = self.to_view_coords(e.event_x, e.event_y);
+                    self.handle_button(e.detail as u32, false, x, y);
                 }
                 x11rb::protocol::Event::MotionNotify(e) => {
-                    self.handle_motion(e.event_x as f64, e.event_y as f64);
+                    let (x, y) = self.to_view_coords(e.event_x, e.event_y);
+                    self.handle_motion(x, y);
                 }
                 _ => {}
             }
@@ -357,6 +444,11 @@ impl X11Window {
         Ok(!self.should_close)
     }
 
+    /// Convert window coordinates (physical pixels) to view coordinates.
+    fn to_view_coords(&self, x: i16, y: i16) -> (f64, f64) {
+        (f64::from(x) / self.scale_factor, f64::from(y) / self.scale_factor)
+    }
+
     /// Handle keyboard input.
     fn handle_key(&mut self, keycode: u32, pressed: bool) {
         let event_type = if pressed {
@@ -507,9 +599,11 @@ impl X11Window {
         self.shm_seg = ShmSegment::new(&self.conn, shm_size).ok();
 
         // Resize WPE view
+        let logical_width = to_logical(self.width, self.scale_factor);
+        let logical_height = to_logical(self.height, self.scale_factor);
         // SAFETY: view is valid.
         unsafe {
-            wpe_sys::wpe_view_resized(self.view, self.width as i32, self.height as i32);
+            wpe_sys::wpe_view_resized(self.view, logical_width as i32, logical_height as i32);
         }
 
         Ok(())
@@ -517,36 +611,84 @@ impl X11Window {
 
     /// Send a message to JavaScript.
     pub fn send(&self, message: &BackendMessage) -> Result<()> {
-        let script = format!(
-            "if(window.__wpe_receive){{window.__wpe_receive({})}}",
-            serde_json::to_string(message).map_err(|e| Error::JavaScriptError(e.to_string()))?
-        );
+        let json =
+            serde_json::to_string(message).map_err(|e| Error::JavaScriptError(e.to_string()))?;
+        let script = ipc::hook_script(&self.settings.ipc.global_name, "__receive", &json);
         self.evaluate_script(&script)
     }
 
+    /// Send raw bytes to the frontend, delivered to `wpe.onBytes` as an `ArrayBuffer`.
+    ///
+    /// Data the page hasn't fetched when it navigates away is dropped.
+    pub fn send_bytes(&self, message_type: &str, data: impl Into<Vec<u8>>) -> Result<()> {
+        let script = self.binary.offer_bytes(message_type, data.into());
+        self.evaluate_script(&script)
+    }
+
+    /// Stream bytes from `reader` to the frontend, delivered to `wpe.onStream`
+    /// as a `ReadableStream`.
+    ///
+    /// The reader runs on a background thread once the page requests the data.
+    pub fn send_stream(
+        &self,
+        message_type: &str,
+        reader: impl Read + Send + 'static,
+        length: Option<u64>,
+    ) -> Result<()> {
+        let script = self.binary.offer_stream(message_type, reader, length);
+        self.evaluate_script(&script)
+    }
+
+    /// A handle for publishing to pages subscribed to `topic` with `wpe.subscribe`.
+    #[must_use]
+    pub fn subscription(&self, topic: impl Into<String>) -> Subscription {
+        Subscription::new(&self.subscriptions, topic)
+    }
+
+    /// Receive binary messages sent with `wpe.sendBytes` and `wpe.sendStream`.
+    ///
+    /// Always empty with an isolated script world; see [`crate::binary`].
+    #[must_use]
+    pub fn receive_bytes(&mut self) -> Vec<BinaryMessage> {
+        self.binary.receive()
+    }
+
     /// Execute JavaScript.
     pub fn evaluate_script(&self, script: &str) -> Result<()> {
-        let c_script = CString::new(script).map_err(|e| Error::JavaScriptError(e.to_string()))?;
-        // SAFETY: web_view is valid.
-        unsafe {
-            wpe_sys::webkit_web_view_evaluate_javascript(
-                self.web_view,
-                c_script.as_ptr(),
-                script.len() as i64,
-                ptr::null(),
-                ptr::null(),
-                ptr::null_mut(),
-                None,
-                ptr::null_mut(),
-            );
-        }
-        Ok(())
+        script::run(self.web_view, self.settings.script_world.as_deref(), script)
     }
 
     /// Receive pending messages from JavaScript.
+    ///
+    /// Messages from a page that has since been navigated away from are dropped.
     pub fn receive_messages(&mut self) -> Vec<FrontendMessage> {
-        let mut queue = self.message_queue.lock().unwrap();
-        queue.drain(..).collect()
+        self.message_queue
+            .take()
+            .into_iter()
+            .filter(|msg| !self.subscriptions.handle(msg))
+            .collect()
+    }
+
+    /// Process events and dispatch pending messages through a router.
+    ///
+    /// Completed async calls are answered as well. Returns false if the
+    /// window should close.
+    pub fn step_with_router(&mut self, router: &IpcRouter) -> Result<bool> {
+        let running = self.process_events()?;
+        router.sync_navigation(self.navigation_count.load(std::sync::atomic::Ordering::Acquire));
+
+        let responses: Vec<_> = self
+            .receive_messages()
+            .iter()
+            .filter_map(|msg| router.dispatch(msg))
+            .chain(router.poll_pending())
+            .collect();
+
+        for response in responses {
+            self.send(&response)?;
+        }
+
+        Ok(running)
     }
 }
 
@@ -555,8 +697,19 @@ impl Drop for X11Window {
         // SAFETY: Releasing GObjects we own.
         unsafe {
             if !self.web_view.is_null() {
+                ipc::unroute_fetch_messages(self.web_view);
+                self.subscriptions.detach();
+                binary::unroute(self.web_view);
+                assets::unmount(self.web_view);
                 wpe_sys::g_object_unref(self.web_view as *mut _);
             }
+            // Reclaim the references held by the (now released) signal handlers
+            if !self.message_handler_ptr.is_null() {
+                drop(Box::from_raw(self.message_handler_ptr));
+            }
+            if !self.navigation_count_ptr.is_null() {
+                drop(std::sync::Arc::from_raw(self.navigation_count_ptr));
+            }
             if !self.view.is_null() {
                 wpe_sys::g_object_unref(self.view as *mut _);
             }
@@ -567,34 +720,6 @@ impl Drop for X11Window {
     }
 }
 
-/// Script message callback.
-unsafe extern "C" fn on_script_message(
-    _manager: *mut wpe_sys::WebKitUserContentManager,
-    result: *mut wpe_sys::JSCValue,
-    user_data: *mut std::ffi::c_void,
-) {
-    if result.is_null() || user_data.is_null() {
-        return;
-    }
-
-    let queue = &*(user_data as *const std::sync::Mutex<std::collections::VecDeque<FrontendMessage>>);
-    let json_str = wpe_sys::jsc_value_to_string(result);
-    if json_str.is_null() {
-        return;
-    }
-
-    let c_str = std::ffi::CStr::from_ptr(json_str);
-    if let Ok(s) = c_str.to_str() {
-        if let Ok(msg) = serde_json::from_str::<FrontendMessage>(s) {
-            if let Ok(mut q) = queue.lock() {
-                q.push_back(msg);
-            }
-        }
-    }
-
-    wpe_sys::g_free(json_str as *mut _);
-}
-
 /// Cast pixel slice to bytes without bytemuck dependency.
 fn bytemuck_cast_pixels(pixels: &[u32]) -> &[u8] {
     // SAFETY: u32 slice can be viewed as u8 slice with 4x length.